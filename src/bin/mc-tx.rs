@@ -14,16 +14,35 @@ extern crate clap;
 #[macro_use]
 extern crate amplify;
 
-use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{fs, io, process};
 
-use clap::Parser;
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::consensus::{Decodable, Encodable};
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::secp256k1::{Secp256k1, SECP256K1};
+use bitcoin::util::bip32::{self, ExtendedPrivKey, Fingerprint};
+use bitcoin::{Address, Amount, Denomination, Network, Transaction};
+use clap::{ArgEnum, Parser};
 use colored::Colorize;
-use mycitadel::model::{file, FileDocument, Wallet};
+use electrum_client::ElectrumApi;
+use miniscript::psbt::PsbtExt;
+use mycitadel::model::{
+    compose, file, labels, psbt, DerivationType, DescriptorClass, DescriptorError, ElectrumPreset,
+    ElectrumServer, FileDocument, HeaderIndex, Payment, PaymentRequest, PublicNetwork, Requirement,
+    Signer, Wallet, WalletSettings, WalletTemplate, XprivSigner, XpubDescriptor, XpubParseError,
+    XpubRequirementError,
+};
+use mycitadel::worker::electrum;
+use serde_crate::Serialize;
+use wallet::hd::{SegmentIndexes, TerminalStep};
+use wallet::psbt::sign::{SignAll, SignError};
+use wallet::psbt::Psbt;
 
 /// Command-line arguments
 #[derive(Parser)]
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 #[clap(
     author,
     version,
@@ -31,6 +50,14 @@ use mycitadel::model::{file, FileDocument, Wallet};
     about = "Command-line tool for working with MyCitadel wallets"
 )]
 pub struct Args {
+    /// Format of the command output
+    #[clap(long, global = true, arg_enum, default_value = "text")]
+    pub format: OutputFormat,
+
+    /// Print the command output in JSON format; same as `--format json`
+    #[clap(long, global = true, hide = true, conflicts_with = "format")]
+    pub json: bool,
+
     /// Command to execute
     #[clap(subcommand)]
     pub command: Command,
@@ -38,7 +65,7 @@ pub struct Args {
 
 /// Wallet command to execute
 #[derive(Subcommand)]
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum Command {
     DumpWallet {
         /// Wallet *.mcw file
@@ -51,9 +78,419 @@ pub enum Command {
 
         /// Wallet *.mcw file
         destination: PathBuf,
+
+        /// Encrypt the wallet file with a passphrase
+        #[clap(short, long)]
+        encrypt: bool,
+    },
+
+    /// Create new wallet from a template and a list of signer xpubs
+    Create {
+        /// Wallet template
+        #[clap(short, long, arg_enum)]
+        template: TemplateArg,
+
+        /// Number of required signatures for the multisig template, or minimal number of signers
+        /// for the hodling template; by default multisig wallets require all signatures
+        #[clap(short, long)]
+        sigs: Option<u16>,
+
+        /// Signer extended public key, optionally prefixed with the signer name as
+        /// `<name>=<xpub>`; may be repeated
+        #[clap(long = "signer", required = true)]
+        signers: Vec<SignerArg>,
+
+        /// Descriptor class of the wallet
+        #[clap(short, long, arg_enum, default_value = "segwit")]
+        class: ClassArg,
+
+        /// Bitcoin network of the wallet
+        #[clap(short, long, arg_enum, default_value = "mainnet")]
+        network: NetworkArg,
+
+        /// Wallet *.mcw file to create
+        destination: PathBuf,
+
+        /// Encrypt the wallet file with a passphrase
+        #[clap(short, long)]
+        encrypt: bool,
+    },
+
+    /// Encrypt, decrypt or change passphrase of a wallet file
+    Passphrase {
+        /// Wallet *.mcw file
+        wallet: PathBuf,
+
+        /// Store the wallet file unencrypted instead of setting a new passphrase
+        #[clap(short, long)]
+        decrypt: bool,
+    },
+
+    /// Import BIP-329 labels into the wallet
+    ImportLabels {
+        /// Wallet *.mcw file
+        wallet: PathBuf,
+
+        /// BIP-329 JSONL file with labels
+        labels: PathBuf,
+    },
+
+    /// Export wallet labels in BIP-329 format
+    ExportLabels {
+        /// Wallet *.mcw file
+        wallet: PathBuf,
+
+        /// Destination JSONL file; if omitted, labels are printed to STDOUT
+        output: Option<PathBuf>,
+    },
+
+    /// List wallet transactions
+    History {
+        /// Wallet *.mcw file
+        wallet: PathBuf,
+    },
+
+    /// List unspent wallet outputs
+    Utxos {
+        /// Wallet *.mcw file
+        wallet: PathBuf,
+    },
+
+    /// Synchronize the wallet with the electrum server, updating the wallet file
+    Sync {
+        /// Wallet *.mcw file
+        wallet: PathBuf,
+    },
+
+    /// Compose payment transaction and save it as a PSBT file
+    Compose {
+        /// Wallet *.mcw file
+        wallet: PathBuf,
+
+        /// Payment in `<address>:<amount in BTC>` format; may be repeated
+        #[clap(short = 't', long = "to", required = true)]
+        payments: Vec<PaymentArg>,
+
+        /// Fee rate in sats per vbyte; defaults to the wallet estimate for the next block
+        #[clap(short, long)]
+        fee_rate: Option<f32>,
+
+        /// Destination PSBT file
+        #[clap(short, long)]
+        out: PathBuf,
+    },
+
+    /// Derive wallet addresses
+    Address {
+        /// Wallet *.mcw file
+        wallet: PathBuf,
+
+        /// Derive change addresses instead of receiving ones
+        #[clap(short, long)]
+        change: bool,
+
+        /// Index of a single address to derive
+        #[clap(short, long, conflicts_with = "range")]
+        index: Option<u16>,
+
+        /// Range of address indexes in `<from>..<to>` or `<from>..=<to>` format; defaults to
+        /// the first addresses within the wallet gap limit
+        #[clap(short, long)]
+        range: Option<IndexRange>,
+
+        /// Descriptor class of the addresses; defaults to the wallet primary class
+        #[clap(long, arg_enum)]
+        class: Option<ClassArg>,
+    },
+
+    /// Print wallet descriptors
+    Descriptor {
+        /// Wallet *.mcw file
+        wallet: PathBuf,
+
+        /// Descriptor format
+        #[clap(long, arg_enum, default_value = "core")]
+        descriptor_format: DescriptorFormat,
+
+        /// Descriptor class to print; if omitted, descriptors for all wallet classes are printed
+        #[clap(long, arg_enum)]
+        class: Option<ClassArg>,
+    },
+
+    /// Inspect, sign, finalize and publish PSBT files
+    Psbt {
+        /// PSBT command to execute
+        #[clap(subcommand)]
+        command: PsbtCommand,
     },
 }
 
+/// PSBT command to execute
+#[derive(Subcommand)]
+#[derive(Clone, PartialEq, Debug)]
+pub enum PsbtCommand {
+    /// Print transaction inputs, outputs, fee and signatures of the PSBT
+    Inspect {
+        /// PSBT file
+        psbt: PathBuf,
+    },
+
+    /// Sign PSBT inputs with an extended private key, which is taken from `MYCITADEL_XPRIV`,
+    /// asked on the terminal or read from STDIN
+    Sign {
+        /// PSBT file
+        psbt: PathBuf,
+
+        /// Fingerprint of the master key, required when signing with an account key
+        #[clap(short, long)]
+        master_fp: Option<Fingerprint>,
+
+        /// Destination PSBT file; if omitted, the source file is updated
+        #[clap(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Combine signatures from several PSBT files of the same transaction
+    Combine {
+        /// PSBT files
+        #[clap(required = true, min_values = 2)]
+        psbts: Vec<PathBuf>,
+
+        /// Destination PSBT file
+        #[clap(short, long)]
+        out: PathBuf,
+    },
+
+    /// Finalize signed PSBT inputs
+    Finalize {
+        /// PSBT file
+        psbt: PathBuf,
+
+        /// Destination PSBT file; if omitted, the source file is updated
+        #[clap(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Extract signed transaction from the PSBT
+    Extract {
+        /// PSBT file
+        psbt: PathBuf,
+
+        /// Destination file for the consensus-encoded transaction; if omitted, the transaction
+        /// is printed to STDOUT in hex
+        #[clap(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Finalize the PSBT and publish the signed transaction to the bitcoin network
+    Broadcast {
+        /// PSBT file
+        psbt: PathBuf,
+
+        /// Electrum server URL; defaults to the server of the wallet or, for the explicitly
+        /// given network, to the local server or a public one
+        #[clap(short, long)]
+        server: Option<String>,
+
+        /// Bitcoin network of the PSBT; required for testnet, signet and regtest keys unless
+        /// the server or the wallet is given
+        #[clap(short, long, arg_enum, conflicts_with = "wallet")]
+        network: Option<NetworkArg>,
+
+        /// Wallet *.mcw file which defines the network and the electrum server
+        #[clap(short, long)]
+        wallet: Option<PathBuf>,
+    },
+}
+
+/// Format of the command output
+#[derive(ArgEnum, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OutputFormat {
+    /// Human-readable text
+    Text,
+    /// Single-line JSON, following the serialization of the wallet data types
+    Json,
+    /// YAML, following the serialization of the wallet data types
+    Yaml,
+}
+
+impl OutputFormat {
+    /// Prints `data` in the machine-readable format. Does nothing for the text format, which is
+    /// specific to each command.
+    pub fn print(self, data: &impl Serialize) -> Result<(), Error> {
+        match self {
+            OutputFormat::Text => {}
+            OutputFormat::Json => println!("{}", serde_json::to_string(data)?),
+            OutputFormat::Yaml => println!("{}", serde_yaml::to_string(data)?),
+        }
+        Ok(())
+    }
+}
+
+/// Descriptor class given in a command-line argument
+#[derive(ArgEnum, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ClassArg {
+    Legacy,
+    Segwit,
+    Nested,
+    Taproot,
+}
+
+impl From<ClassArg> for DescriptorClass {
+    fn from(class: ClassArg) -> Self {
+        match class {
+            ClassArg::Legacy => DescriptorClass::PreSegwit,
+            ClassArg::Segwit => DescriptorClass::SegwitV0,
+            ClassArg::Nested => DescriptorClass::NestedV0,
+            ClassArg::Taproot => DescriptorClass::TaprootC0,
+        }
+    }
+}
+
+impl From<DescriptorClass> for ClassArg {
+    fn from(class: DescriptorClass) -> Self {
+        match class {
+            DescriptorClass::PreSegwit => ClassArg::Legacy,
+            DescriptorClass::SegwitV0 => ClassArg::Segwit,
+            DescriptorClass::NestedV0 => ClassArg::Nested,
+            DescriptorClass::TaprootC0 => ClassArg::Taproot,
+        }
+    }
+}
+
+impl ClassArg {
+    pub fn name(self) -> &'static str {
+        match self {
+            ClassArg::Legacy => "legacy",
+            ClassArg::Segwit => "segwit",
+            ClassArg::Nested => "nested",
+            ClassArg::Taproot => "taproot",
+        }
+    }
+}
+
+/// Wallet template given in a command-line argument
+#[derive(ArgEnum, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TemplateArg {
+    /// Single-signature wallet
+    Singlesig,
+    /// Multisig wallet requiring all signatures for the first years, with gradual relaxation
+    /// of the requirements afterwards
+    Hodling,
+    /// Multisig wallet with the specified number of required signatures
+    Multisig,
+}
+
+/// Bitcoin network given in a command-line argument
+#[derive(ArgEnum, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum NetworkArg {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl From<NetworkArg> for PublicNetwork {
+    fn from(network: NetworkArg) -> Self {
+        match network {
+            NetworkArg::Mainnet => PublicNetwork::Mainnet,
+            NetworkArg::Testnet => PublicNetwork::Testnet,
+            NetworkArg::Signet => PublicNetwork::Signet,
+            NetworkArg::Regtest => PublicNetwork::Regtest,
+        }
+    }
+}
+
+/// Signer given in `[<name>=]<xpub>` format
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SignerArg {
+    pub name: Option<String>,
+    pub xpub: String,
+}
+
+impl FromStr for SignerArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once('=') {
+            Some((name, xpub)) => SignerArg {
+                name: Some(name.to_owned()),
+                xpub: xpub.to_owned(),
+            },
+            None => SignerArg {
+                name: None,
+                xpub: s.to_owned(),
+            },
+        })
+    }
+}
+
+/// Format of the printed descriptors
+#[derive(ArgEnum, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DescriptorFormat {
+    /// Bitcoin Core descriptors
+    Core,
+    /// LNP/BP descriptors, using multipath derivation
+    Lnpbp,
+    /// BIP-388 wallet policy: descriptor template with the list of keys
+    Bip388,
+}
+
+/// Inclusive range of address indexes
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct IndexRange {
+    pub first: u16,
+    pub last: u16,
+}
+
+impl FromStr for IndexRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "range `{}` must have `<from>..<to>` or `<from>..=<to>` format",
+                s
+            )
+        };
+        let (from, to) = s.split_once("..").ok_or_else(err)?;
+        let first = u16::from_str(from).map_err(|_| err())?;
+        let last = match to.strip_prefix('=') {
+            Some(to) => u16::from_str(to).map_err(|_| err())?,
+            None => u16::from_str(to)
+                .map_err(|_| err())?
+                .checked_sub(1)
+                .ok_or_else(err)?,
+        };
+        if last < first {
+            return Err(format!("range `{}` is empty", s));
+        }
+        Ok(IndexRange { first, last })
+    }
+}
+
+/// Payment given in `<address>:<amount in BTC>` format
+#[derive(Clone, PartialEq, Debug)]
+pub struct PaymentArg {
+    pub address: Address,
+    pub amount: Amount,
+}
+
+impl FromStr for PaymentArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, amount) = s
+            .rsplit_once(':')
+            .ok_or_else(|| format!("payment `{}` must have `<address>:<amount>` format", s))?;
+        Ok(PaymentArg {
+            address: Address::from_str(address).map_err(|err| err.to_string())?,
+            amount: Amount::from_str_in(amount, Denomination::Bitcoin)
+                .map_err(|err| err.to_string())?,
+        })
+    }
+}
+
 #[derive(Debug, Display, Error, From)]
 #[display(inner)]
 pub enum Error {
@@ -62,31 +499,956 @@ pub enum Error {
 
     #[from]
     Yaml(serde_yaml::Error),
+
+    #[from]
+    Json(serde_json::Error),
+
+    #[from]
+    Labels(labels::Error),
+
+    #[from]
+    Io(io::Error),
+
+    #[from]
+    Electrum(electrum_client::Error),
+
+    #[from]
+    Compose(compose::Error),
+
+    #[from]
+    Miniscript(miniscript::Error),
+
+    #[from]
+    Descriptor(DescriptorError),
+
+    #[display("invalid signer key {0}: {1}")]
+    Xpub(String, XpubParseError),
+
+    #[display("passphrases do not match")]
+    PassphraseMismatch,
+
+    #[display("wallet file {0} already exists")]
+    Exists(String),
+
+    #[display("single-signature wallets do not take the number of signatures")]
+    SigsSinglesig,
+
+    #[display("hodling wallets require at least 3 signers")]
+    SigsHodling,
+
+    #[display("multisig wallets require at least 2 signatures")]
+    SigsMultisig,
+
+    #[display("you need at least {0} signer(s)")]
+    TooFewSigners(u16),
+
+    #[display("excessive signers: you need no more than {0} signer(s)")]
+    TooManySigners(u16),
+
+    #[display("address {0} does not belong to the wallet network")]
+    Network(Address),
+
+    #[from]
+    PsbtEncoding(bitcoin::consensus::encode::Error),
+
+    #[from]
+    PsbtCombine(bitcoin::psbt::Error),
+
+    #[from]
+    Xpriv(bip32::Error),
+
+    #[from]
+    Sign(SignError),
+
+    #[display("network used by the PSBT and network of the provided private key does not match")]
+    XprivNetwork,
+
+    #[display("the provided key can't sign any of the transaction inputs")]
+    NoSignatures,
+
+    #[display("network used by the PSBT and the requested network does not match")]
+    PsbtNetwork,
+
+    #[display(
+        "PSBT keys are used by testnet, signet and regtest; please specify the network, the \
+         server or the wallet"
+    )]
+    AmbiguousNetwork,
+
+    #[display("unable to finalize PSBT: {0}")]
+    Finalize(String),
 }
 
 impl Args {
+    /// Output format, taking the legacy `--json` flag into account.
+    pub fn output_format(&self) -> OutputFormat {
+        if self.json {
+            OutputFormat::Json
+        } else {
+            self.format
+        }
+    }
+
     pub fn exec(self) -> Result<(), Error> {
+        let format = self.output_format();
         match &self.command {
             Command::DumpWallet { wallet } => {
-                let wallet = Wallet::read_file(wallet)?;
-                println!("{}", serde_yaml::to_string(&wallet)?);
+                let (wallet, _) = open_wallet(wallet)?;
+                match format {
+                    OutputFormat::Json => format.print(&wallet)?,
+                    OutputFormat::Text | OutputFormat::Yaml => OutputFormat::Yaml.print(&wallet)?,
+                }
             }
             Command::ConstructWallet {
                 source,
                 destination,
+                encrypt,
             } => {
+                let passphrase = new_passphrase(*encrypt)?;
                 let wallet: Wallet =
                     serde_yaml::from_reader(fs::File::open(source).map_err(file::Error::File)?)?;
-                wallet.write_file(destination)?;
+                wallet.write_file_with(destination, passphrase.as_deref())?;
+                format.print(&serde_json::json!({ "file": destination }))?;
+            }
+            Command::Create {
+                template,
+                sigs,
+                signers,
+                class,
+                network,
+                destination,
+                encrypt,
+            } => {
+                if destination.exists() {
+                    return Err(Error::Exists(destination.display().to_string()));
+                }
+                let class = DescriptorClass::from(*class);
+                let network = PublicNetwork::from(*network);
+                let template = match (template, sigs) {
+                    (TemplateArg::Singlesig, None) => {
+                        WalletTemplate::singlesig(class, network.clone(), false)
+                    }
+                    (TemplateArg::Singlesig, Some(_)) => return Err(Error::SigsSinglesig),
+                    (TemplateArg::Hodling, Some(sigs)) if *sigs < 3 => {
+                        return Err(Error::SigsHodling)
+                    }
+                    (TemplateArg::Hodling, sigs) => WalletTemplate::hodling(
+                        class,
+                        network.clone(),
+                        sigs.unwrap_or(4),
+                        Requirement::Allow,
+                        Requirement::Allow,
+                    ),
+                    (TemplateArg::Multisig, Some(sigs)) if *sigs < 2 => {
+                        return Err(Error::SigsMultisig)
+                    }
+                    (TemplateArg::Multisig, sigs) => WalletTemplate::multisig(
+                        class,
+                        network.clone(),
+                        *sigs,
+                        Requirement::Allow,
+                        Requirement::Allow,
+                    ),
+                };
+
+                let signer_count = signers.len() as u16;
+                if signer_count < template.min_signer_count {
+                    return Err(Error::TooFewSigners(template.min_signer_count));
+                }
+                match template.max_signer_count {
+                    Some(max_count) if signer_count > max_count => {
+                        return Err(Error::TooManySigners(max_count))
+                    }
+                    _ => {}
+                }
+
+                let bip43 = template.bip43();
+                let signers = signers
+                    .iter()
+                    .enumerate()
+                    .map(|(no, signer)| {
+                        let xpub = match XpubDescriptor::from_str_checked(
+                            &signer.xpub,
+                            network.is_testnet(),
+                            Some(template.default_derivation.clone()),
+                        ) {
+                            Ok(xpub) => xpub,
+                            Err(XpubParseError::Inconsistency(
+                                err @ XpubRequirementError::TestnetMismatch { .. },
+                            )) => return Err(Error::Xpub(signer.xpub.clone(), err.into())),
+                            Err(XpubParseError::Inconsistency(err)) => {
+                                eprintln!("{}: {} {}", "Warning".yellow(), signer.xpub, err);
+                                XpubDescriptor::<DerivationType>::from_str(&signer.xpub)
+                                    .map_err(|err| Error::Xpub(signer.xpub.clone(), err))?
+                            }
+                            Err(err) => return Err(Error::Xpub(signer.xpub.clone(), err)),
+                        };
+                        let mut signer_info =
+                            Signer::with_xpub(xpub.into(), &bip43, network.clone());
+                        signer_info.name = signer
+                            .name
+                            .clone()
+                            .unwrap_or_else(|| format!("Signer #{}", no + 1));
+                        Ok(signer_info)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let electrum = ElectrumServer::local(&network)
+                    .unwrap_or_else(|| ElectrumServer::tls(ElectrumPreset::Blockstream, &network));
+                let settings = WalletSettings::with(
+                    signers,
+                    template.conditions,
+                    bset![class],
+                    vec![TerminalStep::range(0u8, 1u8), TerminalStep::Wildcard],
+                    network,
+                    electrum,
+                )?;
+                // Catch descriptors which can't be constructed before the wallet is saved
+                let (descriptor, _) = settings.descriptors_all()?;
+                let passphrase = new_passphrase(*encrypt)?;
+                Wallet::from(settings).write_file_with(destination, passphrase.as_deref())?;
+                match format {
+                    OutputFormat::Text => {
+                        eprintln!("Wallet is saved to {}", destination.display());
+                        println!("{:#}", descriptor);
+                    }
+                    _ => format.print(&serde_json::json!({
+                        "file": destination,
+                        "descriptor": format!("{:#}", descriptor),
+                    }))?,
+                }
+            }
+            Command::Passphrase {
+                wallet: path,
+                decrypt,
+            } => {
+                let (wallet, passphrase) = open_wallet(path)?;
+                let headers = read_headers(path, passphrase.as_deref());
+                let new_passphrase = new_passphrase(!decrypt)?;
+                headers.write_for(path, new_passphrase.as_deref())?;
+                wallet.rekey_file(path, new_passphrase.as_deref())?;
+                match (format, &new_passphrase) {
+                    (OutputFormat::Text, Some(_)) => eprintln!("Wallet file is now encrypted"),
+                    (OutputFormat::Text, None) => {
+                        eprintln!("Wallet file is now stored unencrypted")
+                    }
+                    _ => format.print(&serde_json::json!({
+                        "encrypted": new_passphrase.is_some(),
+                    }))?,
+                }
+            }
+            Command::ImportLabels {
+                wallet: path,
+                labels: source,
+            } => {
+                let (mut wallet, passphrase) = open_wallet(path)?;
+                let labels = labels::read_labels(io::BufReader::new(fs::File::open(source)?))?;
+                let total = labels.len();
+                let count = wallet.import_labels(labels);
+                wallet.write_file_with(path, passphrase.as_deref())?;
+                match format {
+                    OutputFormat::Text => eprintln!("{} of {} labels imported", count, total),
+                    _ => format.print(&serde_json::json!({
+                        "imported": count,
+                        "total": total,
+                    }))?,
+                }
+            }
+            Command::ExportLabels {
+                wallet,
+                output,
+            } => {
+                let (wallet, _) = open_wallet(wallet)?;
+                let labels = wallet.export_labels();
+                // BIP-329 defines its own JSONL format, so only the export summary follows the
+                // output format
+                match output {
+                    Some(path) => {
+                        labels::write_labels(fs::File::create(path)?, &labels)?;
+                        format.print(&serde_json::json!({ "exported": labels.len() }))?;
+                    }
+                    None => {
+                        labels::write_labels(io::stdout(), &labels)?;
+                    }
+                }
+            }
+            Command::History { wallet: path } => {
+                let (wallet, passphrase) = open_wallet(path)?;
+                let headers = read_headers(path, passphrase.as_deref());
+                match format {
+                    OutputFormat::Text => {
+                        for entry in wallet.history() {
+                            let fee = entry
+                                .fee
+                                .map(|fee| format!("{} sats fee", fee))
+                                .unwrap_or_default();
+                            println!(
+                                "{}\t{}\t{:+.08} BTC\t{}",
+                                entry.mining_info(&headers),
+                                entry.onchain.txid,
+                                entry.balance() as f64 / 100_000_000.0,
+                                fee
+                            );
+                        }
+                    }
+                    _ => format.print(wallet.history())?,
+                }
+            }
+            Command::Utxos { wallet: path } => {
+                let (wallet, passphrase) = open_wallet(path)?;
+                let headers = read_headers(path, passphrase.as_deref());
+                match format {
+                    OutputFormat::Text => {
+                        for utxo in wallet.utxos() {
+                            println!(
+                                "{}\t{}\t{:.08} BTC\t{}",
+                                utxo.outpoint(),
+                                utxo.addr_src.address,
+                                utxo.value as f64 / 100_000_000.0,
+                                utxo.mining_info(&headers)
+                            );
+                        }
+                    }
+                    _ => format.print(wallet.utxos())?,
+                }
+            }
+            Command::Sync { wallet: path } => {
+                let (mut wallet, passphrase) = open_wallet(path)?;
+                let mut headers = read_headers(path, passphrase.as_deref());
+                electrum::sync_blocking(&mut wallet, &mut headers, 0)?;
+                wallet.write_file_with(path, passphrase.as_deref())?;
+                headers.write_for(path, passphrase.as_deref())?;
+
+                let fees = wallet.ephemerals().fees;
+                match format {
+                    OutputFormat::Text => {
+                        println!("Block height: {}", wallet.height());
+                        println!("Balance: {:.08} BTC", wallet.state().balance_btc());
+                        println!("Transactions: {}", wallet.tx_count());
+                        println!("Unspent outputs: {}", wallet.utxos().len());
+                        println!(
+                            "Fee rates: {:.1} / {:.1} / {:.1} sat/vbyte for 1 / 2 / 3 blocks",
+                            fees.0, fees.1, fees.2
+                        );
+                    }
+                    _ => format.print(&serde_json::json!({
+                        "height": wallet.height(),
+                        "balance": wallet.state().balance,
+                        "transactions": wallet.tx_count(),
+                        "utxos": wallet.utxos().len(),
+                        "fee_rates": [fees.0, fees.1, fees.2],
+                    }))?,
+                }
+            }
+            Command::Compose {
+                wallet: path,
+                payments,
+                fee_rate,
+                out,
+            } => {
+                let (mut wallet, passphrase) = open_wallet(path)?;
+                let headers = read_headers(path, passphrase.as_deref());
+                let network = Network::from(wallet.as_settings().network());
+                let payments = payments
+                    .iter()
+                    .map(|payment| {
+                        if !payment.address.is_valid_for_network(network) {
+                            return Err(Error::Network(payment.address.clone()));
+                        }
+                        Ok(Payment {
+                            script_pubkey: payment.address.script_pubkey(),
+                            amount: payment.amount.as_sat(),
+                            subtract_fee: false,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let fee_rate = fee_rate.unwrap_or(wallet.ephemerals().fees.0);
+                let request = PaymentRequest::with(payments, fee_rate);
+
+                let composition = wallet.compose_payment(&request, &headers)?;
+                let psbt = PartiallySignedTransaction::from(composition.psbt);
+                psbt.consensus_encode(fs::File::create(out)?)?;
+                if wallet.update_next_change_index(composition.change_index) {
+                    wallet.write_file_with(path, passphrase.as_deref())?;
+                }
+                match format {
+                    OutputFormat::Text => eprintln!(
+                        "Transaction of {:.0} vbytes paying {} sats of fees is saved to {}",
+                        composition.vsize,
+                        composition.fee,
+                        out.display()
+                    ),
+                    _ => format.print(&serde_json::json!({
+                        "file": out,
+                        "txid": psbt.unsigned_tx.txid().to_string(),
+                        "vsize": composition.vsize,
+                        "fee": composition.fee,
+                    }))?,
+                }
+            }
+            Command::Address {
+                wallet,
+                change,
+                index,
+                range,
+                class,
+            } => {
+                let (wallet, _) = open_wallet(wallet)?;
+                let settings = wallet.as_settings();
+                let class = class
+                    .map(DescriptorClass::from)
+                    .unwrap_or_else(|| settings.primary_class());
+                let range = match (index, range) {
+                    (Some(index), _) => *index..=*index,
+                    (None, Some(range)) => range.first..=range.last,
+                    (None, None) => 0..=settings.gap_limit().saturating_sub(1),
+                };
+                let addresses = settings.addresses(class, *change, range)?;
+                match format {
+                    OutputFormat::Text => {
+                        for (index, address) in addresses {
+                            println!("{}\t{}", index, address);
+                        }
+                    }
+                    _ => {
+                        let addresses = addresses
+                            .iter()
+                            .map(|(index, address)| {
+                                serde_json::json!({
+                                    "index": index.first_index(),
+                                    "address": address.to_string(),
+                                    "change": change,
+                                    "class": ClassArg::from(class).name(),
+                                })
+                            })
+                            .collect::<Vec<_>>();
+                        format.print(&addresses)?;
+                    }
+                }
+            }
+            Command::Descriptor {
+                wallet,
+                descriptor_format,
+                class,
+            } => {
+                let (wallet, _) = open_wallet(wallet)?;
+                let settings = wallet.as_settings();
+                let classes = match class {
+                    Some(class) => vec![DescriptorClass::from(*class)],
+                    None => settings.descriptor_classes().iter().copied().collect(),
+                };
+                let mut descriptors = vec![];
+                for class in classes {
+                    let (descriptor, keys) = match descriptor_format {
+                        DescriptorFormat::Core => (
+                            format!("{:#}", settings.descriptor_for_class(class)?),
+                            vec![],
+                        ),
+                        DescriptorFormat::Lnpbp => {
+                            (format!("{}", settings.descriptor_for_class(class)?), vec![])
+                        }
+                        DescriptorFormat::Bip388 => settings.wallet_policy(class)?,
+                    };
+                    descriptors.push((ClassArg::from(class).name(), descriptor, keys));
+                }
+                match format {
+                    OutputFormat::Text => {
+                        for (class, descriptor, keys) in descriptors {
+                            println!("# {}", class);
+                            println!("{}", descriptor);
+                            for (no, key) in keys.iter().enumerate() {
+                                println!("@{}: {}", no, key);
+                            }
+                        }
+                    }
+                    _ => {
+                        let descriptors = descriptors
+                            .into_iter()
+                            .map(|(class, descriptor, keys)| match descriptor_format {
+                                DescriptorFormat::Bip388 => serde_json::json!({
+                                    "class": class,
+                                    "template": descriptor,
+                                    "keys": keys,
+                                }),
+                                _ => serde_json::json!({
+                                    "class": class,
+                                    "descriptor": descriptor,
+                                }),
+                            })
+                            .collect::<Vec<_>>();
+                        format.print(&descriptors)?;
+                    }
+                }
+            }
+            Command::Psbt { command } => command.exec(format)?,
+        }
+        Ok(())
+    }
+}
+
+impl PsbtCommand {
+    pub fn exec(&self, format: OutputFormat) -> Result<(), Error> {
+        match self {
+            PsbtCommand::Inspect { psbt: path } => {
+                let psbt = Psbt::from(read_psbt(path)?);
+                inspect(&psbt, format)?;
+            }
+            PsbtCommand::Sign {
+                psbt: path,
+                master_fp,
+                out,
+            } => {
+                let mut psbt = Psbt::from(read_psbt(path)?);
+                let xpriv = read_secret("Extended private key", XPRIV_ENV)?;
+                let xpriv = ExtendedPrivKey::from_str(xpriv.trim())?;
+                if PublicNetwork::from(xpriv.network).is_testnet() != psbt::uses_testnet_keys(&psbt)
+                {
+                    return Err(Error::XprivNetwork);
+                }
+                // Master extended key is identified by its own fingerprint
+                let master_fp = master_fp.unwrap_or_else(|| xpriv.fingerprint(&SECP256K1));
+                let signer = XprivSigner {
+                    xpriv,
+                    master_fp,
+                    secp: Secp256k1::new(),
+                };
+                let count = psbt.sign_all(&signer)?;
+                if count == 0 {
+                    return Err(Error::NoSignatures);
+                }
+                let out = out.as_ref().unwrap_or(path);
+                write_psbt(out, psbt.into())?;
+                match format {
+                    OutputFormat::Text => {
+                        eprintln!("{} signatures added to {}", count, out.display())
+                    }
+                    _ => format.print(&serde_json::json!({ "signatures": count }))?,
+                }
+            }
+            PsbtCommand::Combine { psbts, out } => {
+                let mut paths = psbts.iter();
+                let mut psbt = read_psbt(paths.next().expect("clap requires several PSBT files"))?;
+                for path in paths {
+                    psbt.combine(read_psbt(path)?)?;
+                }
+                write_psbt(out, psbt)?;
+                match format {
+                    OutputFormat::Text => eprintln!(
+                        "{} PSBT files are combined into {}",
+                        psbts.len(),
+                        out.display()
+                    ),
+                    _ => format.print(&serde_json::json!({ "combined": psbts.len() }))?,
+                }
+            }
+            PsbtCommand::Finalize { psbt: path, out } => {
+                let mut psbt = read_psbt(path)?;
+                psbt.finalize_mut(&SECP256K1).map_err(finalize_error)?;
+                let txid = psbt.unsigned_tx.txid();
+                let out = out.as_ref().unwrap_or(path);
+                write_psbt(out, psbt)?;
+                match format {
+                    OutputFormat::Text => eprintln!("Finalized PSBT is saved to {}", out.display()),
+                    _ => format.print(&serde_json::json!({ "txid": txid.to_string() }))?,
+                }
+            }
+            PsbtCommand::Extract { psbt: path, out } => {
+                let tx = signed_tx(read_psbt(path)?)?;
+                match out {
+                    Some(out) => {
+                        tx.consensus_encode(fs::File::create(out)?)?;
+                    }
+                    None if format == OutputFormat::Text => println!("{}", serialize_hex(&tx)),
+                    None => format.print(&serde_json::json!({
+                        "txid": tx.txid().to_string(),
+                        "hex": serialize_hex(&tx),
+                    }))?,
+                }
+            }
+            PsbtCommand::Broadcast {
+                psbt: path,
+                server,
+                network,
+                wallet,
+            } => {
+                let psbt = read_psbt(path)?;
+                let testnet_keys = psbt::uses_testnet_keys(&psbt.clone().into());
+                let (network, electrum) = match (wallet, network) {
+                    (Some(path), _) => {
+                        let settings = open_wallet(path)?.0.into_settings();
+                        (Some(settings.network().clone()), Some(settings.electrum().clone()))
+                    }
+                    (None, Some(network)) => (Some(PublicNetwork::from(*network)), None),
+                    // Testnet coin type is shared by testnet, signets and regtest, so only
+                    // mainnet keys identify the network unambiguously
+                    (None, None) if !testnet_keys => (Some(PublicNetwork::Mainnet), None),
+                    (None, None) => (None, None),
+                };
+                if matches!(network, Some(ref network) if network.is_testnet() != testnet_keys) {
+                    return Err(Error::PsbtNetwork);
+                }
+                let tx = signed_tx(psbt)?;
+                let server = match (server, electrum, network) {
+                    (Some(server), ..) => server.clone(),
+                    (None, Some(electrum), _) => electrum.to_string(),
+                    (None, None, Some(network)) => ElectrumServer::local(&network)
+                        .unwrap_or_else(|| {
+                            ElectrumServer::tls(ElectrumPreset::Blockstream, &network)
+                        })
+                        .to_string(),
+                    (None, None, None) => return Err(Error::AmbiguousNetwork),
+                };
+                let txid = electrum::electrum_connect(&server)?.transaction_broadcast(&tx)?;
+                match format {
+                    OutputFormat::Text => println!("Transaction {} is published", txid),
+                    _ => format.print(&serde_json::json!({ "txid": txid.to_string() }))?,
+                }
             }
         }
         Ok(())
     }
 }
 
+fn read_psbt(path: &Path) -> Result<PartiallySignedTransaction, Error> {
+    let file = fs::File::open(path)?;
+    Ok(PartiallySignedTransaction::consensus_decode(file)?)
+}
+
+fn write_psbt(path: &Path, psbt: PartiallySignedTransaction) -> Result<(), Error> {
+    psbt.consensus_encode(fs::File::create(path)?)?;
+    Ok(())
+}
+
+fn finalize_error(errors: Vec<miniscript::psbt::Error>) -> Error {
+    Error::Finalize(
+        errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; "),
+    )
+}
+
+/// Extracts the signed transaction, finalizing PSBT inputs which are not finalized yet.
+fn signed_tx(psbt: PartiallySignedTransaction) -> Result<Transaction, Error> {
+    let is_final = psbt
+        .inputs
+        .iter()
+        .all(|input| input.final_script_sig.is_some() || input.final_script_witness.is_some());
+    if is_final {
+        return Ok(psbt.extract_tx());
+    }
+    psbt::finalize(&psbt.into()).map_err(finalize_error)
+}
+
+fn inspect(psbt: &Psbt, format: OutputFormat) -> Result<(), Error> {
+    let network = match psbt::uses_testnet_keys(psbt) {
+        true => Network::Testnet,
+        false => Network::Bitcoin,
+    };
+    let tx = psbt.clone().into_transaction();
+    let sighashes = psbt::non_default_sighashes(psbt);
+    let signers = psbt::signer_status(psbt);
+    let is_final = psbt
+        .inputs
+        .iter()
+        .all(|input| input.final_script_sig.is_some() || input.final_script_witness.is_some());
+
+    let amounts = psbt
+        .inputs
+        .iter()
+        .map(|input| {
+            input
+                .witness_utxo
+                .as_ref()
+                .map(|txout| txout.value)
+                .or_else(|| {
+                    input
+                        .non_witness_utxo
+                        .as_ref()
+                        .and_then(|tx| tx.output.get(input.previous_outpoint.vout as usize))
+                        .map(|txout| txout.value)
+                })
+        })
+        .collect::<Vec<_>>();
+    let sent = psbt.outputs.iter().map(|output| output.amount).sum::<u64>();
+    let fee = amounts
+        .iter()
+        .copied()
+        .sum::<Option<u64>>()
+        .and_then(|volume| volume.checked_sub(sent));
+    // Outputs with known key derivations are treated as change
+    let outputs = psbt
+        .outputs
+        .iter()
+        .map(|output| {
+            let address = Address::from_script(&output.script, network)
+                .map(|address| address.to_string())
+                .unwrap_or_else(|| output.script.to_string());
+            let is_change =
+                !(output.bip32_derivation.is_empty() && output.tap_key_origins.is_empty());
+            (address, output.amount, is_change)
+        })
+        .collect::<Vec<_>>();
+
+    if format != OutputFormat::Text {
+        let inputs = psbt
+            .inputs
+            .iter()
+            .zip(&amounts)
+            .enumerate()
+            .map(|(no, (input, amount))| {
+                serde_json::json!({
+                    "outpoint": input.previous_outpoint.to_string(),
+                    "amount": amount,
+                    "sighash": sighashes.get(&no),
+                })
+            })
+            .collect::<Vec<_>>();
+        let outputs = outputs
+            .iter()
+            .map(|(address, amount, is_change)| {
+                serde_json::json!({
+                    "address": address,
+                    "amount": amount,
+                    "change": is_change,
+                })
+            })
+            .collect::<Vec<_>>();
+        let signers = signers
+            .iter()
+            .map(|signer| {
+                serde_json::json!({
+                    "master_fp": signer.master_fp.to_string(),
+                    "name": signer.name,
+                    "present": signer.present,
+                    "required": signer.required,
+                })
+            })
+            .collect::<Vec<_>>();
+        let details = serde_json::json!({
+            "txid": tx.txid().to_string(),
+            "lock_time": tx.lock_time,
+            "inputs": inputs,
+            "outputs": outputs,
+            "fee": fee,
+            "signers": signers,
+            "finalized": is_final,
+        });
+        return format.print(&details);
+    }
+
+    println!("Transaction id: {}", tx.txid());
+    println!("Lock time: {}", tx.lock_time);
+    println!("Inputs:");
+    for (no, (input, amount)) in psbt.inputs.iter().zip(&amounts).enumerate() {
+        let amount = amount
+            .map(|amount| format!("{:.08} BTC", amount as f64 / 100_000_000.0))
+            .unwrap_or_else(|| s!("unknown amount"));
+        let sighash = sighashes
+            .get(&no)
+            .map(|sighash| format!(" ({} sighash)", sighash).yellow().to_string())
+            .unwrap_or_default();
+        println!(
+            "  #{} {} {}{}",
+            no, input.previous_outpoint, amount, sighash
+        );
+    }
+    println!("Outputs:");
+    for (no, (address, amount, is_change)) in outputs.iter().enumerate() {
+        let change = if *is_change { " (change)" } else { "" };
+        println!(
+            "  #{} {} {:.08} BTC{}",
+            no,
+            address,
+            *amount as f64 / 100_000_000.0,
+            change
+        );
+    }
+    match fee {
+        Some(fee) => println!(
+            "Fee: {} sats, {:.1} sat/vbyte",
+            fee,
+            fee as f32 / tx.vsize() as f32
+        ),
+        None => println!("Fee: unknown"),
+    }
+    println!("Signatures:");
+    for (no, signer) in signers.iter().enumerate() {
+        let name = signer
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Signer #{}", no + 1));
+        println!(
+            "  {} [{}]: {} of {}",
+            name, signer.master_fp, signer.present, signer.required
+        );
+    }
+    if is_final {
+        println!("Status: {}", "finalized".green());
+    } else {
+        println!("Status: not finalized");
+    }
+    Ok(())
+}
+
+impl Error {
+    /// Process exit code for the error. Codes are stable, such that scripts may rely on them;
+    /// codes 1 and 2 are not used, since they are reserved for panics and invalid command-line
+    /// arguments.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::File(_) => 10,
+            Error::Yaml(_) => 11,
+            Error::Json(_) => 12,
+            Error::Labels(_) => 13,
+            Error::Io(_) => 14,
+            Error::PassphraseMismatch => 15,
+            Error::Electrum(_) => 20,
+            Error::Compose(_) => 30,
+            Error::Network(_) => 31,
+            Error::Miniscript(_) => 40,
+            Error::Descriptor(_) => 41,
+            Error::Xpub(..) => 42,
+            Error::Exists(_) => 43,
+            Error::SigsSinglesig => 44,
+            Error::SigsHodling => 45,
+            Error::SigsMultisig => 46,
+            Error::TooFewSigners(_) => 47,
+            Error::TooManySigners(_) => 48,
+            Error::PsbtEncoding(_) => 50,
+            Error::PsbtCombine(_) => 51,
+            Error::Xpriv(_) => 52,
+            Error::XprivNetwork => 53,
+            Error::Sign(_) => 54,
+            Error::NoSignatures => 55,
+            Error::Finalize(_) => 56,
+            Error::PsbtNetwork => 57,
+            Error::AmbiguousNetwork => 58,
+        }
+    }
+}
+
+/// Environment variable with the passphrase of encrypted wallet files.
+pub const PASSPHRASE_ENV: &str = "MYCITADEL_PASSPHRASE";
+
+/// Environment variable with the passphrase for newly encrypted wallet files.
+pub const NEW_PASSPHRASE_ENV: &str = "MYCITADEL_NEW_PASSPHRASE";
+
+/// Environment variable with the extended private key used for PSBT signing.
+pub const XPRIV_ENV: &str = "MYCITADEL_XPRIV";
+
+/// Passphrases and private keys are never taken from the command line, where they are exposed to
+/// other users through the process list and are kept in the shell history. Instead, they are read
+/// from the `env` environment variable, asked interactively on the terminal without echoing, or
+/// read as a single line from STDIN when it is not a terminal.
+fn read_secret(prompt: &str, env: &str) -> Result<String, Error> {
+    if let Ok(passphrase) = std::env::var(env) {
+        return Ok(passphrase);
+    }
+    let mut passphrase = s!("");
+    if is_tty() {
+        eprint!("{}: ", prompt);
+        let _echo_guard = EchoGuard::disable();
+        io::stdin().read_line(&mut passphrase)?;
+    } else {
+        io::stdin().read_line(&mut passphrase)?;
+    }
+    Ok(passphrase.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+/// Reads the wallet file, asking for the passphrase if the file is encrypted. Returns the
+/// passphrase together with the wallet, such that the file can be saved back.
+fn open_wallet(path: &Path) -> Result<(Wallet, Option<String>), Error> {
+    let passphrase = if Wallet::is_encrypted(path)? {
+        Some(read_secret(
+            &format!("Passphrase for {}", path.display()),
+            PASSPHRASE_ENV,
+        )?)
+    } else {
+        None
+    };
+    let wallet = Wallet::read_file_with(path, passphrase.as_deref())?;
+    Ok((wallet, passphrase))
+}
+
+/// Reads block index of the wallet file at `path`. The index is a cache, so a damaged one is
+/// simply rebuilt with the next sync.
+fn read_headers(path: &Path, passphrase: Option<&str>) -> HeaderIndex {
+    HeaderIndex::read_for(path, passphrase).unwrap_or_default()
+}
+
+/// Asks for a new wallet file passphrase if `encrypt` is set. Interactively entered passphrases
+/// are asked twice.
+fn new_passphrase(encrypt: bool) -> Result<Option<String>, Error> {
+    if !encrypt {
+        return Ok(None);
+    }
+    let passphrase = read_secret("New passphrase", NEW_PASSPHRASE_ENV)?;
+    if std::env::var(NEW_PASSPHRASE_ENV).is_err()
+        && is_tty()
+        && read_secret("Repeat new passphrase", NEW_PASSPHRASE_ENV)? != passphrase
+    {
+        return Err(Error::PassphraseMismatch);
+    }
+    Ok(Some(passphrase))
+}
+
+#[cfg(unix)]
+fn is_tty() -> bool { unsafe { libc::isatty(libc::STDIN_FILENO) == 1 } }
+
+#[cfg(not(unix))]
+fn is_tty() -> bool { false }
+
+/// Disables terminal echo of STDIN, restoring it when dropped.
+struct EchoGuard(#[cfg(unix)] Option<libc::termios>);
+
+impl EchoGuard {
+    #[cfg(unix)]
+    fn disable() -> EchoGuard {
+        unsafe {
+            let mut termios = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                return EchoGuard(None);
+            }
+            let original = termios;
+            termios.c_lflag &= !libc::ECHO;
+            termios.c_lflag |= libc::ECHONL;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+                return EchoGuard(None);
+            }
+            EchoGuard(Some(original))
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn disable() -> EchoGuard { EchoGuard() }
+}
+
+#[cfg(unix)]
+impl Drop for EchoGuard {
+    fn drop(&mut self) {
+        if let Some(termios) = self.0 {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+            }
+        }
+    }
+}
+
 fn main() {
     let args = Args::parse();
+    let format = args.output_format();
     if let Err(err) = args.exec() {
-        eprintln!("{}: {}\n", "Error".bright_red(), err);
+        let code = err.exit_code();
+        let details = serde_json::json!({ "error": code, "message": err.to_string() });
+        match format {
+            OutputFormat::Text => eprintln!("{}: {}\n", "Error".bright_red(), err),
+            OutputFormat::Json => eprintln!("{}", details),
+            OutputFormat::Yaml => {
+                eprintln!("{}", serde_yaml::to_string(&details).unwrap_or_default())
+            }
+        }
+        process::exit(code);
     }
 }
@@ -18,8 +18,10 @@
 extern crate amplify;
 #[macro_use]
 extern crate strict_encoding;
+#[cfg(feature = "ui")]
 #[macro_use]
 extern crate relm;
+#[cfg(feature = "ui")]
 #[macro_use]
 extern crate relm_derive;
 #[cfg(feature = "serde")]
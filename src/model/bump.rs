@@ -0,0 +1,688 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime SA, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! Fee bumping of the wallet's own unconfirmed transactions.
+
+use std::collections::BTreeMap;
+
+use ::wallet::hd::{SegmentIndexes, TrackingAccount, UnhardenedIndex};
+use ::wallet::lex_order::lex_order::LexOrder;
+use ::wallet::locks::{LockTime, SeqNo};
+use ::wallet::psbt::Psbt;
+use ::wallet::scripts::PubkeyScript;
+use amplify::Wrapper;
+use bitcoin::blockdata::constants::WITNESS_SCALE_FACTOR;
+use bitcoin::consensus::serialize;
+use bitcoin::policy::DUST_RELAY_TX_FEE;
+use bitcoin::{Transaction, TxOut, Txid};
+use miniscript::{Descriptor, DescriptorTrait};
+
+use crate::model::psbt::ConstructError;
+use crate::model::{
+    CoinSelectParams, CoinSelectStrategy, DescriptorClass, HeaderIndex, HistoryEntry, Prevout,
+    TimelockReq, TipError, UtxoTxid, Wallet, TXIN_BASE_VSIZE,
+};
+
+/// Minimal fee rate increment (in sats per vbyte) the replacement transaction must pay for its
+/// own relay, as required by BIP-125 rule 4. Matches Bitcoin Core default.
+pub const INCREMENTAL_RELAY_FEE: f32 = 1.0;
+
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// transaction {0} is not known to the wallet.
+    UnknownTx(Txid),
+
+    /// transaction {0} is already mined.
+    Mined(Txid),
+
+    /// transaction {0} does not signal replaceability.
+    NotReplaceable(Txid),
+
+    /// transaction {0} spends outputs which do not belong to the wallet.
+    ForeignInputs(Txid),
+
+    /// new fee rate must exceed fee rate of the original transaction ({0:.1} sat/vbyte).
+    FeeRateTooLow(f32),
+
+    /// transaction {0} has no unspent outputs which can be spent by the wallet.
+    NoOutputs(Txid),
+
+    /// fee of transaction {0} is unknown.
+    UnknownFee(Txid),
+
+    /// available wallet funds are insufficient to pay the new fee.
+    InsufficientFunds,
+
+    /// internal error in descriptor: {0}
+    #[from]
+    Miniscript(miniscript::Error),
+
+    /// unable to set transaction lock time: {0}
+    #[from]
+    LockTime(TipError),
+
+    /// unable to construct PSBT: {0}
+    #[from]
+    Construct(ConstructError),
+}
+
+/// Method of accelerating an unconfirmed transaction.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[display(doc_comments)]
+pub enum BumpMethod {
+    /// Replace-by-fee
+    ReplaceByFee,
+    /// Child pays for parent
+    ChildPaysForParent,
+}
+
+/// Transaction accelerating confirmation of an unconfirmed wallet transaction.
+#[derive(Clone, Debug)]
+pub struct FeeBump {
+    pub psbt: Psbt,
+    /// Index of the change output; may be a new one if the original transaction had no change.
+    pub change_index: UnhardenedIndex,
+    pub fee: u64,
+    pub vsize: f32,
+}
+
+impl Wallet {
+    /// Method which can accelerate the unconfirmed wallet transaction `txid`, if any. Transactions
+    /// spending wallet funds only are replaced; incoming payments are accelerated by a child
+    /// transaction.
+    pub fn bump_method(&self, txid: Txid) -> Option<BumpMethod> {
+        let entry = self.history_entry(txid)?;
+        if entry.onchain.status.is_mined() {
+            None
+        } else if entry.credit.len() == entry.tx.input.len() && signals_rbf(&entry.tx) {
+            Some(BumpMethod::ReplaceByFee)
+        } else if self.spendable_utxos().any(|utxo| utxo.onchain.txid == txid) {
+            Some(BumpMethod::ChildPaysForParent)
+        } else {
+            None
+        }
+    }
+
+    /// Accelerates the unconfirmed wallet transaction `txid` with the given `method`. The `headers`
+    /// index is used to derive lock time of the child transaction.
+    pub fn bump_fee(
+        &self,
+        txid: Txid,
+        method: BumpMethod,
+        fee_rate: f32,
+        headers: &HeaderIndex,
+    ) -> Result<FeeBump, Error> {
+        match method {
+            BumpMethod::ReplaceByFee => self.replace_by_fee(txid, fee_rate),
+            BumpMethod::ChildPaysForParent => self.child_pays_for_parent(txid, fee_rate, headers),
+        }
+    }
+
+    /// Builds replacement for the unconfirmed wallet transaction `txid` paying `fee_rate` (in sats
+    /// per vbyte) according to BIP-125. All payments of the original transaction are kept; the
+    /// fee increase is taken from the change output and, if it is not enough, from additional
+    /// confirmed wallet outputs of the change descriptor class. Spent outputs may belong to any
+    /// of the wallet descriptor classes.
+    pub fn replace_by_fee(&self, txid: Txid, fee_rate: f32) -> Result<FeeBump, Error> {
+        let entry = self.history_entry(txid).ok_or(Error::UnknownTx(txid))?;
+        let tx = &entry.tx;
+        if entry.onchain.status.is_mined() {
+            return Err(Error::Mined(txid));
+        }
+        if !signals_rbf(tx) {
+            return Err(Error::NotReplaceable(txid));
+        }
+        if entry.credit.len() != tx.input.len() {
+            return Err(Error::ForeignInputs(txid));
+        }
+        // All inputs are ours, so we know the exact fee of the original transaction
+        let available = entry.value_credited();
+        let original_fee = available - tx.output.iter().map(|txout| txout.value).sum::<u64>();
+        let original_rate = original_fee as f32 / tx.vsize() as f32;
+        if fee_rate <= original_rate {
+            return Err(Error::FeeRateTooLow(original_rate));
+        }
+        let required_fee = |vsize: f32| -> u64 {
+            ((fee_rate * vsize).ceil() as u64)
+                .max(original_fee + (INCREMENTAL_RELAY_FEE * vsize).ceil() as u64)
+        };
+
+        let mut prevouts = entry
+            .credit
+            .iter()
+            .map(|(vin, addr_value)| Prevout {
+                outpoint: tx.input[*vin as usize].previous_output,
+                amount: addr_value.value,
+                change: addr_value.addr_src.change,
+                index: addr_value.addr_src.index,
+                class: addr_value.addr_src.class,
+            })
+            .collect::<Vec<_>>();
+        let class = self
+            .change_class(&prevouts)
+            .expect("all inputs of the transaction are wallet outputs");
+
+        // Change outputs are re-created by the PSBT constructor; everything else is kept. The
+        // original change index is reused only if it belongs to the same descriptor class.
+        let change_index = entry
+            .debit
+            .values()
+            .find(|addr_src| addr_src.change && addr_src.class == class)
+            .map(|addr_src| addr_src.index)
+            .unwrap_or_else(|| self.next_change_index());
+        let payments = tx
+            .output
+            .iter()
+            .enumerate()
+            .filter(|(vout, _)| {
+                !matches!(entry.debit.get(&(*vout as u32)), Some(addr_src) if addr_src.change)
+            })
+            .map(|(_, txout)| txout.clone())
+            .collect::<Vec<_>>();
+        let payment_value = payments.iter().map(|txout| txout.value).sum::<u64>();
+
+        let params = self.bump_params(class, change_index, fee_rate)?;
+        let base_vsize = Transaction {
+            version: tx.version,
+            lock_time: tx.lock_time,
+            input: vec![],
+            output: payments.clone(),
+        }
+        .vsize() as f32
+            + prevouts
+                .iter()
+                .map(|prevout| params.class_vsize(prevout.class))
+                .sum::<f32>();
+        let base_fee = required_fee(base_vsize);
+        let (fee, vsize) = if available >= payment_value + base_fee {
+            // Reducing (or removing) the change is enough
+            let vsize = base_vsize + params.change_vsize;
+            let fee_with_change = required_fee(vsize);
+            if available >= payment_value + fee_with_change + params.dust_limit {
+                (fee_with_change, vsize)
+            } else {
+                (available - payment_value, base_vsize)
+            }
+        } else {
+            // BIP-125 rule 2: replacement may not add unconfirmed inputs. Outputs of other classes
+            // are not added, since they may change the class of the change output.
+            let candidates = self
+                .spendable_utxos()
+                .filter(|utxo| utxo.addr_src.class == class && utxo.onchain.status.is_mined())
+                .copied()
+                .collect::<Vec<UtxoTxid>>();
+            let target = payment_value + base_fee - available;
+            let selection = CoinSelectStrategy::LargestFirst
+                .selector()
+                .select(&candidates, target, &params)
+                .ok_or(Error::InsufficientFunds)?;
+            let vsize = base_vsize
+                + selection.inputs_vsize(&params)
+                + selection
+                    .change
+                    .map(|_| params.change_vsize)
+                    .unwrap_or_default();
+            let fee =
+                available + selection.value - payment_value - selection.change.unwrap_or_default();
+            if fee < required_fee(vsize) {
+                return Err(Error::InsufficientFunds);
+            }
+            prevouts.extend(selection.prevouts);
+            (fee, vsize)
+        };
+
+        let lock_time = LockTime::with_height(tx.lock_time)
+            .or_else(|| LockTime::with_unix_timestamp(tx.lock_time))
+            .expect("lock time is either a block height or a timestamp");
+        let payments = payments
+            .into_iter()
+            .map(|txout| (PubkeyScript::from(txout.script_pubkey), txout.value))
+            .collect::<Vec<_>>();
+        let mut psbt = self.construct_psbt(
+            lock_time,
+            &prevouts,
+            &payments,
+            SeqNo::new_rbf(),
+            change_index,
+            fee,
+        )?;
+        psbt.lex_order();
+        Ok(FeeBump {
+            psbt,
+            change_index,
+            fee,
+            vsize,
+        })
+    }
+
+    /// Builds a child transaction spending wallet outputs of the unconfirmed transaction `txid`
+    /// (of any descriptor class) to a new change address, such that the package of both
+    /// transactions pays `fee_rate` (in sats per vbyte). The `headers` index is used to derive
+    /// the transaction lock time.
+    pub fn child_pays_for_parent(
+        &self,
+        txid: Txid,
+        fee_rate: f32,
+        headers: &HeaderIndex,
+    ) -> Result<FeeBump, Error> {
+        let entry = self.history_entry(txid).ok_or(Error::UnknownTx(txid))?;
+        if entry.onchain.status.is_mined() {
+            return Err(Error::Mined(txid));
+        }
+        let parent_fee = entry.fee.ok_or(Error::UnknownFee(txid))?;
+        let parent_vsize = entry.tx.vsize() as f32;
+        let parent_rate = parent_fee as f32 / parent_vsize;
+        if fee_rate <= parent_rate {
+            return Err(Error::FeeRateTooLow(parent_rate));
+        }
+        let prevouts = self
+            .spendable_utxos()
+            .filter(|utxo| utxo.onchain.txid == txid)
+            .map(Prevout::from)
+            .collect::<Vec<_>>();
+        if prevouts.is_empty() {
+            return Err(Error::NoOutputs(txid));
+        }
+
+        let change_index = self.next_change_index();
+        let class = self
+            .change_class(&prevouts)
+            .expect("prevouts are not empty");
+        let params = self.bump_params(class, change_index, fee_rate)?;
+        let vsize = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        }
+        .vsize() as f32
+            + prevouts
+                .iter()
+                .map(|prevout| params.class_vsize(prevout.class))
+                .sum::<f32>()
+            + params.change_vsize;
+        let fee = ((fee_rate * (parent_vsize + vsize)).ceil() as u64).saturating_sub(parent_fee);
+        let value = prevouts.iter().map(|prevout| prevout.amount).sum::<u64>();
+        if value < fee + params.dust_limit {
+            return Err(Error::InsufficientFunds);
+        }
+
+        let lock_time = self.lock_time(TimelockReq::Anytime, headers)?;
+        let mut psbt =
+            self.construct_psbt(lock_time, &prevouts, &[], SeqNo::new_rbf(), change_index, fee)?;
+        psbt.lex_order();
+        Ok(FeeBump {
+            psbt,
+            change_index,
+            fee,
+            vsize,
+        })
+    }
+
+    fn history_entry(&self, txid: Txid) -> Option<&HistoryEntry> {
+        self.history()
+            .iter()
+            .find(|entry| entry.onchain.txid == txid)
+    }
+
+    /// Coin selection parameters for spending wallet outputs with the change at `change_index` of
+    /// the descriptor `class`.
+    fn bump_params(
+        &self,
+        class: DescriptorClass,
+        change_index: UnhardenedIndex,
+        fee_rate: f32,
+    ) -> Result<CoinSelectParams, Error> {
+        let settings = self.as_settings();
+        let (descriptor, _) = settings.descriptors_all()?;
+        let change_no = change_index.first_index() as u16;
+        let change_script = settings
+            .script_pubkeys(class, true, change_no..=change_no)?
+            .into_values()
+            .next()
+            .expect("change script pubkey is always derivable");
+        let input_vsize = |descriptor: &Descriptor<TrackingAccount>| -> Result<f32, Error> {
+            let satisfaction_weight = descriptor.max_satisfaction_weight()? as f32;
+            Ok(TXIN_BASE_VSIZE + satisfaction_weight / WITNESS_SCALE_FACTOR as f32)
+        };
+        let class_input_vsize = settings
+            .descriptor_classes()
+            .iter()
+            .map(|class| Ok((*class, input_vsize(&settings.descriptor_for_class(*class)?)?)))
+            .collect::<Result<BTreeMap<_, _>, Error>>()?;
+        let input_vsize = input_vsize(&descriptor)?;
+        let change_vsize = serialize(&TxOut {
+            value: 0,
+            script_pubkey: change_script.into_inner(),
+        })
+        .len() as f32;
+        let params = CoinSelectParams {
+            fee_rate,
+            long_term_fee_rate: self.ephemerals().fees.2.min(fee_rate),
+            input_vsize,
+            class_input_vsize,
+            change_vsize,
+            dust_limit: ((input_vsize + change_vsize) * DUST_RELAY_TX_FEE as f32 / 1000.0).ceil()
+                as u64,
+        };
+        Ok(params)
+    }
+}
+
+/// Checks whether the transaction signals replaceability explicitly (BIP-125 rule 1).
+fn signals_rbf(tx: &Transaction) -> bool {
+    tx.input
+        .iter()
+        .any(|txin| txin.sequence <= SeqNo::new_rbf().as_u32())
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::{BlockHeader, OutPoint, Script, TxIn, Witness};
+    use chrono::Utc;
+    use electrum_client::HeaderNotification;
+
+    use super::*;
+    use crate::model::fixtures::{external, settings};
+    use crate::model::{AddressSource, DescriptorClass, OnchainStatus, OnchainTxid, WalletSettings};
+    use crate::worker::electrum::TxidMeta;
+
+    const PAYMENT: u64 = 60_000;
+    const SPENT_FEE: u64 = 1_000;
+
+    fn address(settings: &WalletSettings, class: DescriptorClass, change: bool) -> AddressSource {
+        let index = UnhardenedIndex::zero();
+        let script = settings.script_pubkeys(class, change, 0..=0).unwrap()[&index].clone();
+        AddressSource::with(&script, index, change, class, bitcoin::Network::Bitcoin)
+    }
+
+    fn tx(prevout: OutPoint, outputs: Vec<(Script, u64)>) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: prevout,
+                script_sig: none!(),
+                sequence: SeqNo::new_rbf().as_u32(),
+                // Signature and public key of a P2WPKH input, such that the transaction has
+                // its real size
+                witness: Witness::from_vec(vec![vec![0u8; 72], vec![0u8; 33]]),
+            }],
+            output: outputs
+                .into_iter()
+                .map(|(script_pubkey, value)| TxOut {
+                    value,
+                    script_pubkey,
+                })
+                .collect(),
+        }
+    }
+
+    /// Segwit wallet which received 100 000 and 50 000 sats in a confirmed transaction and has an
+    /// unconfirmed transaction spending the former to a [`PAYMENT`] with 39 000 sats of change.
+    /// Returns the wallet and txid of the unconfirmed transaction.
+    fn wallet() -> (Wallet, Txid) { spending(DescriptorClass::SegwitV0) }
+
+    /// Same as [`wallet`], but with segwit and the given descriptor `class`, which receives and
+    /// spends the funds.
+    fn spending(class: DescriptorClass) -> (Wallet, Txid) {
+        let settings = settings(&[DescriptorClass::SegwitV0, class]);
+        let receive = address(&settings, class, false);
+        let change = address(&settings, class, true);
+        let script = |addr_src: AddressSource| addr_src.address.script_pubkey().into_inner();
+
+        let funding = tx(OutPoint::default(), vec![
+            (script(receive), 100_000),
+            (external(), 1_000_000),
+            (script(receive), 50_000),
+        ]);
+        let spending = tx(OutPoint::new(funding.txid(), 0), vec![
+            (external(), PAYMENT),
+            (script(change), 100_000 - PAYMENT - SPENT_FEE),
+        ]);
+        let onchain = |tx: &Transaction, status| OnchainTxid {
+            txid: tx.txid(),
+            status,
+            date_time: None,
+        };
+        let funding_onchain = onchain(&funding, OnchainStatus::Blockchain(100));
+        let spending_onchain = onchain(&spending, OnchainStatus::Mempool);
+
+        let mut wallet = Wallet::from(settings);
+        wallet.update_utxos(bset![
+            UtxoTxid {
+                onchain: funding_onchain,
+                value: 50_000,
+                vout: 2,
+                addr_src: receive,
+            },
+            UtxoTxid {
+                onchain: spending_onchain,
+                value: 100_000 - PAYMENT - SPENT_FEE,
+                vout: 1,
+                addr_src: change,
+            }
+        ]);
+        let meta = |onchain, fee| TxidMeta { onchain, fee };
+        wallet.update_complete(
+            &bmap! {
+                receive => bset![meta(funding_onchain, None), meta(spending_onchain, Some(SPENT_FEE))],
+                change => bset![meta(spending_onchain, Some(SPENT_FEE))]
+            },
+            &[funding, spending.clone()],
+        );
+        (wallet, spending.txid())
+    }
+
+    /// Wallet with segwit and nested segwit descriptors, which has an unconfirmed incoming payment
+    /// of [`PAYMENT`] to the nested segwit address paying [`SPENT_FEE`], and knows the recent tip
+    /// at the height 1000. Returns the wallet, its header index and txid of the payment.
+    fn incoming() -> (Wallet, HeaderIndex, Txid) {
+        let class = DescriptorClass::NestedV0;
+        let settings = settings(&[DescriptorClass::SegwitV0, class]);
+        let receive = address(&settings, class, false);
+        let payment = tx(OutPoint::default(), vec![
+            (external(), 500_000),
+            (receive.address.script_pubkey().into_inner(), PAYMENT),
+        ]);
+        let txid = payment.txid();
+        let onchain = OnchainTxid {
+            txid,
+            status: OnchainStatus::Mempool,
+            date_time: None,
+        };
+
+        let mut wallet = Wallet::from(settings);
+        wallet.update_utxos(bset![UtxoTxid {
+            onchain,
+            value: PAYMENT,
+            vout: 1,
+            addr_src: receive,
+        }]);
+        wallet.update_complete(
+            &bmap! { receive => bset![TxidMeta { onchain, fee: Some(SPENT_FEE) }] },
+            &[payment],
+        );
+        let header = BlockHeader {
+            version: 1,
+            prev_blockhash: zero!(),
+            merkle_root: zero!(),
+            time: Utc::now().timestamp() as u32,
+            bits: 0,
+            nonce: 0,
+        };
+        wallet.update_last_block(&HeaderNotification {
+            height: 1000,
+            header,
+        });
+        let mut headers = HeaderIndex::default();
+        headers.insert(1000, &header);
+        (wallet, headers, txid)
+    }
+
+    fn outputs(bump: &FeeBump) -> Vec<u64> {
+        bump.psbt.outputs.iter().map(|output| output.amount).collect()
+    }
+
+    fn payment_kept(bump: &FeeBump) -> bool {
+        bump.psbt
+            .outputs
+            .iter()
+            .any(|output| output.script == external() && output.amount == PAYMENT)
+    }
+
+    #[test]
+    fn rbf_increases_fee_rate() {
+        let (wallet, txid) = wallet();
+        assert_eq!(wallet.bump_method(txid), Some(BumpMethod::ReplaceByFee));
+
+        let bump = wallet.replace_by_fee(txid, 20.0).unwrap();
+        assert_eq!(bump.psbt.inputs.len(), 1);
+        assert!(payment_kept(&bump));
+        assert_eq!(outputs(&bump).iter().sum::<u64>() + bump.fee, 100_000);
+        assert!(bump.fee > SPENT_FEE);
+        assert!(bump.fee as f32 >= 20.0 * bump.vsize);
+        assert_eq!(bump.change_index, UnhardenedIndex::zero());
+    }
+
+    #[test]
+    fn rbf_rejects_lower_fee_rate() {
+        let (wallet, txid) = wallet();
+        let entry = wallet.history_entry(txid).unwrap();
+        let original_rate = SPENT_FEE as f32 / entry.tx.vsize() as f32;
+
+        assert!(matches!(
+            wallet.replace_by_fee(txid, original_rate),
+            Err(Error::FeeRateTooLow(_))
+        ));
+    }
+
+    #[test]
+    fn rbf_pays_for_own_relay() {
+        let (wallet, txid) = wallet();
+        let entry = wallet.history_entry(txid).unwrap();
+        let original_rate = SPENT_FEE as f32 / entry.tx.vsize() as f32;
+
+        // A slightly higher fee rate alone does not cover relay of the replacement (BIP-125 rule
+        // 4), so the absolute fee must grow by the incremental relay fee
+        let bump = wallet.replace_by_fee(txid, original_rate + 0.1).unwrap();
+        assert_eq!(
+            bump.fee,
+            SPENT_FEE + (INCREMENTAL_RELAY_FEE * bump.vsize).ceil() as u64
+        );
+        assert_eq!(outputs(&bump).iter().sum::<u64>() + bump.fee, 100_000);
+    }
+
+    #[test]
+    fn rbf_absorbs_dust_change() {
+        let (wallet, txid) = wallet();
+        let with_change = wallet.replace_by_fee(txid, 20.0).unwrap();
+        let params = wallet
+            .bump_params(DescriptorClass::SegwitV0, with_change.change_index, 20.0)
+            .unwrap();
+
+        // Fee rate leaving less than the dust limit for the change
+        let available = 100_000 - PAYMENT;
+        let fee_rate = (available - params.dust_limit / 2) as f32 / with_change.vsize;
+        let bump = wallet.replace_by_fee(txid, fee_rate).unwrap();
+        assert_eq!(bump.psbt.inputs.len(), 1);
+        assert_eq!(outputs(&bump), vec![PAYMENT]);
+        assert_eq!(bump.fee, available);
+        assert!(payment_kept(&bump));
+    }
+
+    #[test]
+    fn rbf_adds_confirmed_inputs() {
+        let (wallet, txid) = wallet();
+        let with_change = wallet.replace_by_fee(txid, 20.0).unwrap();
+        let params = wallet
+            .bump_params(DescriptorClass::SegwitV0, with_change.change_index, 20.0)
+            .unwrap();
+
+        // Fee rate at which the original input can't pay the fee even without the change; the
+        // unconfirmed change of the replaced transaction must not be used (BIP-125 rule 2)
+        let base_vsize = with_change.vsize - params.change_vsize;
+        let fee_rate = (100_000 - PAYMENT + 1_000) as f32 / base_vsize;
+        let bump = wallet.replace_by_fee(txid, fee_rate).unwrap();
+        assert_eq!(bump.psbt.inputs.len(), 2);
+        assert!(payment_kept(&bump));
+        assert_eq!(outputs(&bump).iter().sum::<u64>() + bump.fee, 150_000);
+        assert!(bump.fee as f32 >= fee_rate * bump.vsize);
+
+        assert!(matches!(
+            wallet.replace_by_fee(txid, 2_000.0),
+            Err(Error::InsufficientFunds)
+        ));
+    }
+
+    #[test]
+    fn rbf_spends_secondary_class() {
+        let class = DescriptorClass::NestedV0;
+        let (wallet, txid) = spending(class);
+        assert_eq!(wallet.bump_method(txid), Some(BumpMethod::ReplaceByFee));
+
+        let bump = wallet.replace_by_fee(txid, 20.0).unwrap();
+        assert_eq!(bump.psbt.inputs.len(), 1);
+        assert!(bump.psbt.inputs[0].redeem_script.is_some());
+        assert!(payment_kept(&bump));
+        assert!(bump.fee as f32 >= 20.0 * bump.vsize);
+        // Change goes back to the nested segwit change address of the original transaction
+        let change = address(wallet.as_settings(), class, true);
+        assert_eq!(bump.change_index, change.index);
+        assert!(bump.psbt.outputs.iter().any(|output| {
+            output.script == change.address.script_pubkey().into_inner()
+                && output.amount == 100_000 - PAYMENT - bump.fee
+        }));
+
+        let params = wallet.bump_params(class, bump.change_index, 20.0).unwrap();
+        let base_vsize = bump.vsize - params.change_vsize;
+        let fee_rate = (100_000 - PAYMENT + 1_000) as f32 / base_vsize;
+        let bump = wallet.replace_by_fee(txid, fee_rate).unwrap();
+        assert_eq!(bump.psbt.inputs.len(), 2);
+        assert!(bump.psbt.inputs.iter().all(|input| input.redeem_script.is_some()));
+        assert_eq!(outputs(&bump).iter().sum::<u64>() + bump.fee, 150_000);
+    }
+
+    #[test]
+    fn cpfp_spends_secondary_class() {
+        let (wallet, headers, txid) = incoming();
+        assert_eq!(wallet.bump_method(txid), Some(BumpMethod::ChildPaysForParent));
+
+        let parent_vsize = wallet.history_entry(txid).unwrap().tx.vsize() as f32;
+        let bump = wallet
+            .bump_fee(txid, BumpMethod::ChildPaysForParent, 20.0, &headers)
+            .unwrap();
+        assert_eq!(bump.psbt.inputs.len(), 1);
+        assert_eq!(bump.psbt.inputs[0].previous_outpoint, OutPoint::new(txid, 1));
+        assert_eq!(outputs(&bump), vec![PAYMENT - bump.fee]);
+        assert!((bump.fee + SPENT_FEE) as f32 >= 20.0 * (parent_vsize + bump.vsize));
+
+        // Nested segwit inputs are larger than the segwit ones of the primary class
+        let params = wallet
+            .bump_params(DescriptorClass::NestedV0, bump.change_index, 20.0)
+            .unwrap();
+        assert!(bump.vsize > params.input_vsize + params.change_vsize);
+
+        // Lock time discourages fee sniping, being close to the current tip
+        let lock_time = bump.psbt.lock_time();
+        assert!(lock_time <= 1000 && lock_time > 900);
+    }
+
+    #[test]
+    fn cpfp_requires_known_tip() {
+        let (wallet, _, txid) = incoming();
+        assert!(matches!(
+            wallet.child_pays_for_parent(txid, 20.0, &HeaderIndex::default()),
+            Err(Error::LockTime(TipError::Unknown))
+        ));
+    }
+}
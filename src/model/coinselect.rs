@@ -0,0 +1,748 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime SA, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use wallet::scripts::address::AddressCompat;
+
+use crate::model::{DescriptorClass, Prevout, UtxoTxid};
+
+/// Size of a transaction input without its satisfaction: outpoint, sequence number and
+/// script length byte.
+pub const TXIN_BASE_VSIZE: f32 = 32.0 + 4.0 + 4.0 + 1.0;
+
+/// Maximal number of tries for the branch-and-bound algorithm, matching Bitcoin Core.
+const BNB_TOTAL_TRIES: usize = 100_000;
+
+/// Number of random passes done by the knapsack solver, matching Bitcoin Core.
+const KNAPSACK_ITERATIONS: usize = 1000;
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display(doc_comments)]
+pub enum CoinSelectStrategy {
+    /// Branch and bound (changeless)
+    BranchAndBound,
+    /// Knapsack
+    Knapsack,
+    /// Largest first
+    LargestFirst,
+    /// Oldest first
+    OldestFirst,
+    /// Privacy-preserving
+    Privacy,
+    /// Manual coin control
+    Manual,
+    /// Send all funds (no change)
+    SendAll,
+}
+
+impl Default for CoinSelectStrategy {
+    fn default() -> Self { CoinSelectStrategy::BranchAndBound }
+}
+
+impl CoinSelectStrategy {
+    pub fn all() -> &'static [CoinSelectStrategy] {
+        &[
+            CoinSelectStrategy::BranchAndBound,
+            CoinSelectStrategy::Knapsack,
+            CoinSelectStrategy::LargestFirst,
+            CoinSelectStrategy::OldestFirst,
+            CoinSelectStrategy::Privacy,
+            CoinSelectStrategy::Manual,
+            CoinSelectStrategy::SendAll,
+        ]
+    }
+
+    pub fn selector(self) -> &'static dyn CoinSelector {
+        match self {
+            CoinSelectStrategy::BranchAndBound => &BranchAndBound,
+            CoinSelectStrategy::Knapsack => &Knapsack,
+            CoinSelectStrategy::LargestFirst => &LargestFirst,
+            CoinSelectStrategy::OldestFirst => &OldestFirst,
+            CoinSelectStrategy::Privacy => &Privacy,
+            CoinSelectStrategy::Manual => &Manual,
+            CoinSelectStrategy::SendAll => &SendAll,
+        }
+    }
+}
+
+/// Fee-related parameters of a coin selection. All fee rates are in sats per vbyte.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CoinSelectParams {
+    /// Fee rate of the transaction being composed.
+    pub fee_rate: f32,
+    /// Fee rate we expect to pay for spending the same outputs at some point in the future.
+    pub long_term_fee_rate: f32,
+    /// Size of a single transaction input of the primary descriptor class (which is also used by
+    /// the change output), including its satisfaction.
+    pub input_vsize: f32,
+    /// Sizes of inputs of other descriptor classes, if they differ from `input_vsize`.
+    pub class_input_vsize: BTreeMap<DescriptorClass, f32>,
+    /// Size of a change output.
+    pub change_vsize: f32,
+    /// Minimal value of a change output; smaller changes are added to the fee.
+    pub dust_limit: u64,
+}
+
+impl CoinSelectParams {
+    /// Size of an input spending output of the descriptor `class`, including its satisfaction.
+    pub fn class_vsize(&self, class: DescriptorClass) -> f32 {
+        self.class_input_vsize
+            .get(&class)
+            .copied()
+            .unwrap_or(self.input_vsize)
+    }
+
+    pub fn input_fee(&self, utxo: &UtxoTxid) -> u64 {
+        (self.class_vsize(utxo.addr_src.class) * self.fee_rate).ceil() as u64
+    }
+
+    pub fn change_fee(&self) -> u64 { (self.change_vsize * self.fee_rate).ceil() as u64 }
+
+    /// Cost of creating a change output now and spending it later.
+    pub fn cost_of_change(&self) -> u64 {
+        self.change_fee() + (self.input_vsize * self.long_term_fee_rate).ceil() as u64
+    }
+
+    /// Difference between spending an input now and at the long-term fee rate.
+    pub fn input_waste(&self, utxo: &UtxoTxid) -> i64 {
+        let vsize = self.class_vsize(utxo.addr_src.class);
+        self.input_fee(utxo) as i64 - (vsize * self.long_term_fee_rate).ceil() as i64
+    }
+
+    /// Value of an output after paying the fee for spending it.
+    pub fn effective_value(&self, utxo: &UtxoTxid) -> i64 {
+        utxo.value as i64 - self.input_fee(utxo) as i64
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CoinSelection {
+    /// Algorithm which produced the selection.
+    pub strategy: CoinSelectStrategy,
+    pub prevouts: BTreeSet<Prevout>,
+    /// Total value of the selected outputs.
+    pub value: u64,
+    /// Value of the change output, if any.
+    pub change: Option<u64>,
+    /// Waste metric as defined by Bitcoin Core: excess fees paid for the inputs comparing to
+    /// the long-term fee rate plus either cost of change or the value dropped to fees.
+    pub waste: i64,
+}
+
+impl CoinSelection {
+    /// Total size of the selected inputs, including their satisfaction.
+    pub fn inputs_vsize(&self, params: &CoinSelectParams) -> f32 {
+        self.prevouts
+            .iter()
+            .map(|prevout| params.class_vsize(prevout.class))
+            .sum()
+    }
+
+    /// Selection target is the value to pay (including fees for everything except inputs and
+    /// change output) which must be covered by effective values of the selected outputs.
+    fn with(
+        strategy: CoinSelectStrategy,
+        selected: &[&UtxoTxid],
+        target: u64,
+        params: &CoinSelectParams,
+        allow_change: bool,
+    ) -> Option<CoinSelection> {
+        let effective = selected
+            .iter()
+            .map(|utxo| params.effective_value(utxo))
+            .sum::<i64>();
+        if selected.is_empty() || effective < target as i64 {
+            return None;
+        }
+        let excess = (effective - target as i64) as u64;
+        let change = if allow_change && excess >= params.change_fee() + params.dust_limit {
+            Some(excess - params.change_fee())
+        } else {
+            None
+        };
+        let waste = selected
+            .iter()
+            .map(|utxo| params.input_waste(utxo))
+            .sum::<i64>()
+            + match change {
+                Some(_) => params.cost_of_change() as i64,
+                None => excess as i64,
+            };
+        Some(CoinSelection {
+            strategy,
+            prevouts: selected.iter().map(|utxo| Prevout::from(*utxo)).collect(),
+            value: selected.iter().map(|utxo| utxo.value).sum(),
+            change,
+            waste,
+        })
+    }
+}
+
+/// Splits `amount` into `count` shares which are as equal as possible; the first share receives
+/// the remainder of the division.
+pub fn split_evenly(amount: u64, count: usize) -> Vec<u64> {
+    if count == 0 {
+        return vec![];
+    }
+    let share = amount / count as u64;
+    let mut shares = vec![share; count];
+    shares[0] += amount % count as u64;
+    shares
+}
+
+pub trait CoinSelector {
+    /// Selects outputs from `candidates` such that their effective value covers `target`.
+    fn select(
+        &self,
+        candidates: &[UtxoTxid],
+        target: u64,
+        params: &CoinSelectParams,
+    ) -> Option<CoinSelection>;
+}
+
+/// Takes outputs in the given order until the target is reached; adds more of them if the
+/// remaining value is not enough to pay for a change output.
+fn select_sequential(
+    strategy: CoinSelectStrategy,
+    ordered: &[&UtxoTxid],
+    target: u64,
+    params: &CoinSelectParams,
+) -> Option<CoinSelection> {
+    let target = target as i64;
+    let mut acc = 0i64;
+    for (no, utxo) in ordered.iter().enumerate() {
+        acc += params.effective_value(utxo);
+        if acc < target {
+            continue;
+        }
+        let excess = (acc - target) as u64;
+        if excess <= params.cost_of_change() || excess >= params.change_fee() + params.dust_limit {
+            return CoinSelection::with(strategy, &ordered[..=no], target as u64, params, true);
+        }
+    }
+    CoinSelection::with(strategy, ordered, target as u64, params, true)
+}
+
+/// Bitcoin Core branch-and-bound algorithm searching for a changeless input set with the
+/// minimal waste.
+pub struct BranchAndBound;
+
+impl CoinSelector for BranchAndBound {
+    fn select(
+        &self,
+        candidates: &[UtxoTxid],
+        target: u64,
+        params: &CoinSelectParams,
+    ) -> Option<CoinSelection> {
+        let mut pool = candidates
+            .iter()
+            .map(|utxo| (params.effective_value(utxo), params.input_waste(utxo), utxo))
+            .filter(|(value, _, _)| *value > 0)
+            .collect::<Vec<_>>();
+        pool.sort_by(|(a, _, _), (b, _, _)| b.cmp(a));
+
+        let target = target as i64;
+        let cost_of_change = params.cost_of_change() as i64;
+        // Adding inputs increases the waste only if the fee rate exceeds the long-term one
+        let waste_grows = params.fee_rate > params.long_term_fee_rate;
+
+        let mut available = pool.iter().map(|(value, _, _)| value).sum::<i64>();
+        if available < target {
+            return None;
+        }
+
+        let mut curr_value = 0i64;
+        let mut curr_waste = 0i64;
+        let mut selection = Vec::<usize>::new();
+        let mut best: Option<(Vec<usize>, i64)> = None;
+
+        let mut index = 0usize;
+        for _ in 0..BNB_TOTAL_TRIES {
+            let best_waste = best.as_ref().map(|(_, waste)| *waste).unwrap_or(i64::MAX);
+            let mut backtrack = false;
+            if curr_value + available < target
+                || curr_value > target + cost_of_change
+                || (curr_waste > best_waste && waste_grows)
+            {
+                backtrack = true;
+            } else if curr_value >= target {
+                let waste = curr_waste + curr_value - target;
+                if waste <= best_waste {
+                    best = Some((selection.clone(), waste));
+                }
+                backtrack = true;
+            }
+
+            if backtrack {
+                let last = match selection.last() {
+                    None => break,
+                    Some(last) => *last,
+                };
+                index -= 1;
+                while index > last {
+                    available += pool[index].0;
+                    index -= 1;
+                }
+                curr_value -= pool[index].0;
+                curr_waste -= pool[index].1;
+                selection.pop();
+            } else {
+                let (value, waste, _) = pool[index];
+                available -= value;
+                // Skip outputs equivalent to the previous one which was already omitted
+                if selection.is_empty()
+                    || selection.last() == Some(&(index - 1))
+                    || (value, waste) != (pool[index - 1].0, pool[index - 1].1)
+                {
+                    selection.push(index);
+                    curr_value += value;
+                    curr_waste += waste;
+                }
+            }
+            index += 1;
+        }
+
+        let (selection, _) = best?;
+        let selected = selection
+            .into_iter()
+            .map(|no| pool[no].2)
+            .collect::<Vec<_>>();
+        CoinSelection::with(
+            CoinSelectStrategy::BranchAndBound,
+            &selected,
+            target as u64,
+            params,
+            false,
+        )
+    }
+}
+
+/// Bitcoin Core legacy knapsack solver doing randomized approximation of the best subset.
+pub struct Knapsack;
+
+impl CoinSelector for Knapsack {
+    fn select(
+        &self,
+        candidates: &[UtxoTxid],
+        target: u64,
+        params: &CoinSelectParams,
+    ) -> Option<CoinSelection> {
+        let strategy = CoinSelectStrategy::Knapsack;
+        let target = target as i64;
+        let min_change = (params.change_fee() + params.dust_limit) as i64;
+
+        let mut lowest_larger: Option<(i64, &UtxoTxid)> = None;
+        let mut applicable = Vec::with_capacity(candidates.len());
+        for utxo in candidates {
+            let value = params.effective_value(utxo);
+            if value <= 0 {
+                continue;
+            }
+            if value == target {
+                return CoinSelection::with(strategy, &[utxo], target as u64, params, true);
+            } else if value < target + min_change {
+                applicable.push((value, utxo));
+            } else if lowest_larger.map(|(v, _)| value < v).unwrap_or(true) {
+                lowest_larger = Some((value, utxo));
+            }
+        }
+
+        let total_lower = applicable.iter().map(|(value, _)| value).sum::<i64>();
+        if total_lower < target {
+            let (_, utxo) = lowest_larger?;
+            return CoinSelection::with(strategy, &[utxo], target as u64, params, true);
+        }
+
+        applicable.sort_by(|(a, _), (b, _)| b.cmp(a));
+        let values = applicable
+            .iter()
+            .map(|(value, _)| *value)
+            .collect::<Vec<_>>();
+        let mut rng = XorShift::new();
+        let (mut best, mut best_value) =
+            approximate_best_subset(&mut rng, &values, total_lower, target);
+        if best_value != target && total_lower >= target + min_change {
+            let (subset, value) =
+                approximate_best_subset(&mut rng, &values, total_lower, target + min_change);
+            best = subset;
+            best_value = value;
+        }
+
+        let selected = match lowest_larger {
+            Some((value, utxo))
+                if (best_value != target && best_value < target + min_change)
+                    || value <= best_value =>
+            {
+                vec![utxo]
+            }
+            _ => best
+                .into_iter()
+                .zip(&applicable)
+                .filter(|(included, _)| *included)
+                .map(|(_, (_, utxo))| *utxo)
+                .collect(),
+        };
+        CoinSelection::with(strategy, &selected, target as u64, params, true)
+    }
+}
+
+fn approximate_best_subset(
+    rng: &mut XorShift,
+    values: &[i64],
+    total_lower: i64,
+    target: i64,
+) -> (Vec<bool>, i64) {
+    let mut best = vec![true; values.len()];
+    let mut best_value = total_lower;
+
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_value == target {
+            break;
+        }
+        let mut included = vec![false; values.len()];
+        let mut total = 0i64;
+        let mut reached_target = false;
+        for pass in 0..2 {
+            if reached_target {
+                break;
+            }
+            for (no, value) in values.iter().enumerate() {
+                let take = if pass == 0 {
+                    rng.next_bool()
+                } else {
+                    !included[no]
+                };
+                if !take {
+                    continue;
+                }
+                total += value;
+                included[no] = true;
+                if total >= target {
+                    reached_target = true;
+                    if total < best_value {
+                        best_value = total;
+                        best = included.clone();
+                    }
+                    total -= value;
+                    included[no] = false;
+                }
+            }
+        }
+    }
+
+    (best, best_value)
+}
+
+/// Minimal xorshift generator; knapsack solver does not need cryptographic randomness.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new() -> XorShift {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        XorShift(seed | 1)
+    }
+
+    fn next_bool(&mut self) -> bool {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 & 1 == 1
+    }
+}
+
+/// Spends the largest outputs first, minimizing the number of inputs.
+pub struct LargestFirst;
+
+impl CoinSelector for LargestFirst {
+    fn select(
+        &self,
+        candidates: &[UtxoTxid],
+        target: u64,
+        params: &CoinSelectParams,
+    ) -> Option<CoinSelection> {
+        let mut ordered = candidates.iter().collect::<Vec<_>>();
+        ordered.sort_by_key(|utxo| Reverse(utxo.value));
+        select_sequential(CoinSelectStrategy::LargestFirst, &ordered, target, params)
+    }
+}
+
+/// Spends the oldest outputs first; unconfirmed outputs are used as a last resort.
+pub struct OldestFirst;
+
+impl CoinSelector for OldestFirst {
+    fn select(
+        &self,
+        candidates: &[UtxoTxid],
+        target: u64,
+        params: &CoinSelectParams,
+    ) -> Option<CoinSelection> {
+        let mut ordered = candidates.iter().collect::<Vec<_>>();
+        ordered.sort_by_key(|utxo| utxo.onchain.status);
+        select_sequential(CoinSelectStrategy::OldestFirst, &ordered, target, params)
+    }
+}
+
+/// Avoids mixing address clusters: outputs on the same address are always spent together,
+/// and a single cluster is preferred whenever it is able to cover the payment.
+pub struct Privacy;
+
+impl CoinSelector for Privacy {
+    fn select(
+        &self,
+        candidates: &[UtxoTxid],
+        target: u64,
+        params: &CoinSelectParams,
+    ) -> Option<CoinSelection> {
+        let strategy = CoinSelectStrategy::Privacy;
+        let mut clusters = candidates
+            .iter()
+            .fold(
+                BTreeMap::<AddressCompat, Vec<&UtxoTxid>>::new(),
+                |mut map, utxo| {
+                    map.entry(utxo.addr_src.address).or_default().push(utxo);
+                    map
+                },
+            )
+            .into_values()
+            .map(|cluster| {
+                let value = cluster
+                    .iter()
+                    .map(|utxo| params.effective_value(utxo))
+                    .sum::<i64>();
+                (value, cluster)
+            })
+            .collect::<Vec<_>>();
+
+        clusters.sort_by_key(|(value, _)| *value);
+        if let Some((_, cluster)) = clusters.iter().find(|(value, _)| *value >= target as i64) {
+            return CoinSelection::with(strategy, cluster, target, params, true);
+        }
+
+        // No single cluster is sufficient: merge the smallest possible number of them
+        let mut selected = vec![];
+        let mut acc = 0i64;
+        for (value, cluster) in clusters.iter().rev() {
+            selected.extend(cluster);
+            acc += value;
+            if acc >= target as i64 {
+                break;
+            }
+        }
+        CoinSelection::with(strategy, &selected, target, params, true)
+    }
+}
+
+/// Spends all of the candidates, which are expected to be hand-picked by the user.
+pub struct Manual;
+
+impl CoinSelector for Manual {
+    fn select(
+        &self,
+        candidates: &[UtxoTxid],
+        target: u64,
+        params: &CoinSelectParams,
+    ) -> Option<CoinSelection> {
+        let selected = candidates.iter().collect::<Vec<_>>();
+        CoinSelection::with(CoinSelectStrategy::Manual, &selected, target, params, true)
+    }
+}
+
+/// Spends all of the candidates with no change output. The value exceeding the target is left in
+/// the selection, such that it can be sent to the beneficiaries receiving all remaining funds.
+pub struct SendAll;
+
+impl CoinSelector for SendAll {
+    fn select(
+        &self,
+        candidates: &[UtxoTxid],
+        target: u64,
+        params: &CoinSelectParams,
+    ) -> Option<CoinSelection> {
+        let selected = candidates.iter().collect::<Vec<_>>();
+        CoinSelection::with(
+            CoinSelectStrategy::SendAll,
+            &selected,
+            target,
+            params,
+            false,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Network, Script, Txid, WPubkeyHash};
+    use wallet::hd::UnhardenedIndex;
+
+    use super::*;
+    use crate::model::{fixtures, AddressSource, OnchainStatus, OnchainTxid, Wallet};
+
+    /// Input fee at 10 sat/vbyte is 680 sats, so outputs of `value + FEE` sats have effective
+    /// value of `value`.
+    const FEE: u64 = 680;
+
+    fn params() -> CoinSelectParams {
+        CoinSelectParams {
+            fee_rate: 10.0,
+            long_term_fee_rate: 5.0,
+            input_vsize: 68.0,
+            class_input_vsize: empty!(),
+            change_vsize: 31.0,
+            dust_limit: 294,
+        }
+    }
+
+    /// Output number `no` of the value `value` sitting on the address number `address`, mined in
+    /// block `height`.
+    fn utxo(no: u8, address: u8, value: u64, height: u32) -> UtxoTxid {
+        let script = Script::new_v0_p2wpkh(&WPubkeyHash::from_inner([address; 20]));
+        UtxoTxid {
+            onchain: OnchainTxid {
+                txid: Txid::from_inner([no; 32]),
+                status: OnchainStatus::Blockchain(height),
+                date_time: None,
+            },
+            value,
+            vout: 0,
+            addr_src: AddressSource::with(
+                &script.into(),
+                UnhardenedIndex::from(address),
+                false,
+                DescriptorClass::SegwitV0,
+                Network::Bitcoin,
+            ),
+        }
+    }
+
+    fn selected(selection: &CoinSelection) -> BTreeSet<Txid> {
+        selection
+            .prevouts
+            .iter()
+            .map(|prevout| prevout.outpoint.txid)
+            .collect()
+    }
+
+    fn txids(utxos: &[&UtxoTxid]) -> BTreeSet<Txid> {
+        utxos.iter().map(|utxo| utxo.onchain.txid).collect()
+    }
+
+    #[test]
+    fn bnb_exact_match() {
+        let params = params();
+        let utxos = [
+            utxo(1, 1, 100_000 + FEE, 10),
+            utxo(2, 2, 50_000 + FEE, 10),
+            utxo(3, 3, 30_000 + FEE, 10),
+            utxo(4, 4, 20_000 + FEE, 10),
+        ];
+
+        let selection = BranchAndBound.select(&utxos, 80_000, &params).unwrap();
+        assert_eq!(selection.strategy, CoinSelectStrategy::BranchAndBound);
+        assert_eq!(selected(&selection), txids(&[&utxos[1], &utxos[2]]));
+        assert_eq!(selection.value, 80_000 + 2 * FEE);
+        assert_eq!(selection.change, None);
+        // Each input costs 340 sats more than at the long-term fee rate; there is no excess
+        assert_eq!(selection.waste, 2 * 340);
+    }
+
+    #[test]
+    fn bnb_falls_back_to_knapsack() {
+        let params = params();
+        let utxos = [
+            utxo(1, 1, 40_000 + FEE, 10),
+            utxo(2, 2, 40_000 + FEE, 10),
+            utxo(3, 3, 40_000 + FEE, 10),
+        ];
+        assert!(BranchAndBound.select(&utxos, 50_000, &params).is_none());
+
+        let wallet = Wallet::from(fixtures::settings(&[DescriptorClass::SegwitV0]));
+        let selection = wallet
+            .coinselect(CoinSelectStrategy::BranchAndBound, &utxos, 50_000, &params)
+            .unwrap();
+        assert_eq!(selection.strategy, CoinSelectStrategy::Knapsack);
+        assert_eq!(selection.prevouts.len(), 2);
+        assert_eq!(selection.change, Some(30_000 - params.change_fee()));
+        assert_eq!(
+            selection.waste,
+            2 * 340 + params.cost_of_change() as i64
+        );
+    }
+
+    #[test]
+    fn dust_change_goes_to_fee() {
+        let params = params();
+        let min_change = params.change_fee() + params.dust_limit;
+
+        let utxos = [utxo(1, 1, 10_000 + min_change - 1 + FEE, 10)];
+        let selection = Manual.select(&utxos, 10_000, &params).unwrap();
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.waste, 340 + min_change as i64 - 1);
+
+        let utxos = [utxo(1, 1, 10_000 + min_change + FEE, 10)];
+        let selection = Manual.select(&utxos, 10_000, &params).unwrap();
+        assert_eq!(selection.change, Some(params.dust_limit));
+        assert_eq!(selection.waste, 340 + params.cost_of_change() as i64);
+
+        let utxos = [utxo(1, 1, 10_000 + FEE - 1, 10)];
+        assert!(Manual.select(&utxos, 10_000, &params).is_none());
+    }
+
+    #[test]
+    fn largest_and_oldest_first() {
+        let params = params();
+        let utxos = [
+            utxo(1, 1, 20_000, 30),
+            utxo(2, 2, 50_000, 20),
+            utxo(3, 3, 10_000, 10),
+        ];
+
+        let selection = LargestFirst.select(&utxos, 30_000, &params).unwrap();
+        assert_eq!(selection.strategy, CoinSelectStrategy::LargestFirst);
+        assert_eq!(selected(&selection), txids(&[&utxos[1]]));
+
+        let selection = OldestFirst.select(&utxos, 25_000, &params).unwrap();
+        assert_eq!(selection.strategy, CoinSelectStrategy::OldestFirst);
+        assert_eq!(selected(&selection), txids(&[&utxos[1], &utxos[2]]));
+    }
+
+    #[test]
+    fn privacy_prefers_single_cluster() {
+        let params = params();
+        let utxos = [
+            utxo(1, 1, 30_000 + FEE, 10),
+            utxo(2, 1, 30_000 + FEE, 10),
+            utxo(3, 2, 100_000 + FEE, 10),
+            utxo(4, 3, 10_000 + FEE, 10),
+        ];
+
+        // The smallest cluster covering the payment is spent as a whole
+        let selection = Privacy.select(&utxos, 50_000, &params).unwrap();
+        assert_eq!(selection.strategy, CoinSelectStrategy::Privacy);
+        assert_eq!(selected(&selection), txids(&[&utxos[0], &utxos[1]]));
+
+        let selection = Privacy.select(&utxos, 20_000, &params).unwrap();
+        assert_eq!(selected(&selection), txids(&[&utxos[0], &utxos[1]]));
+
+        // Otherwise the largest clusters are merged
+        let selection = Privacy.select(&utxos, 120_000, &params).unwrap();
+        assert_eq!(
+            selected(&selection),
+            txids(&[&utxos[0], &utxos[1], &utxos[2]])
+        );
+    }
+}
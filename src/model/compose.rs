@@ -0,0 +1,466 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime SA, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! Composing payment transactions from the wallet funds.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ::wallet::hd::{SegmentIndexes, UnhardenedIndex};
+use ::wallet::lex_order::lex_order::LexOrder;
+use ::wallet::locks::SeqNo;
+use ::wallet::psbt::Psbt;
+use ::wallet::scripts::PubkeyScript;
+use amplify::Wrapper;
+use bitcoin::blockdata::constants::WITNESS_SCALE_FACTOR;
+use bitcoin::consensus::serialize;
+use bitcoin::policy::DUST_RELAY_TX_FEE;
+use bitcoin::{OutPoint, Script, Transaction, TxOut};
+
+use crate::model::psbt::{retain_tap_leaf, set_sighash_type, ConstructError, McKeys, SighashType};
+use crate::model::{
+    split_evenly, BlockTime, CoinSelectParams, CoinSelectStrategy, CoinSelection, DescriptorClass,
+    HeaderIndex, PathStatus, TipError, Wallet, TXIN_BASE_VSIZE,
+};
+
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// transaction has no beneficiaries.
+    NoPayments,
+
+    /// one or more of payment amounts are zeros.
+    Amount,
+
+    /// at least one payment must have no amount, such that it receives all the remaining funds.
+    NoRemainder,
+
+    /// no coins are selected for spending.
+    NoCoins,
+
+    /// available wallet funds are insufficient to cover the transaction.
+    InsufficientFunds,
+
+    /// payment amount is too small to pay the transaction fee deducted from it.
+    FeeExceedsAmount,
+
+    /// selected spending condition can't be used yet: {0}.
+    #[from]
+    PathUnavailable(PathStatus),
+
+    /// unable to set transaction lock time: {0}
+    #[from]
+    LockTime(TipError),
+
+    /// internal error in descriptor: {0}
+    #[from]
+    Miniscript(miniscript::Error),
+
+    /// unable to construct PSBT: {0}
+    #[from]
+    PsbtConstruct(ConstructError),
+
+    /// unable to compute proper fee.
+    FeeFailure,
+
+    /// SIGHASH_SINGLE signatures require the transaction to have at least as many outputs as
+    /// inputs.
+    SighashSingle,
+}
+
+/// Payment to a single beneficiary.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Payment {
+    pub script_pubkey: Script,
+    /// Amount in sats. Payments with zero amount receive all the remaining funds when sending
+    /// with [`CoinSelectStrategy::SendAll`].
+    pub amount: u64,
+    /// Whether the transaction fee is deducted from this payment.
+    pub subtract_fee: bool,
+}
+
+/// Parameters of a payment transaction.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PaymentRequest {
+    pub payments: Vec<Payment>,
+    /// Fee rate in sats per vbyte.
+    pub fee_rate: f32,
+    pub coinselect: CoinSelectStrategy,
+    /// Coins to spend with [`CoinSelectStrategy::Manual`].
+    pub manual_coins: BTreeSet<OutPoint>,
+    /// Number of the spending path, as listed by [`crate::model::WalletSettings::spending_paths`].
+    pub spending_path: usize,
+    pub sighash_type: SighashType,
+}
+
+impl PaymentRequest {
+    /// Request paying `payments` with the default coin selection through the first spending
+    /// path.
+    pub fn with(payments: Vec<Payment>, fee_rate: f32) -> PaymentRequest {
+        PaymentRequest {
+            payments,
+            fee_rate,
+            coinselect: CoinSelectStrategy::default(),
+            manual_coins: empty!(),
+            spending_path: 0,
+            sighash_type: SighashType::default(),
+        }
+    }
+}
+
+/// Composed payment transaction.
+#[derive(Clone, Debug)]
+pub struct Composition {
+    pub psbt: Psbt,
+    /// Index used by the change output, if the transaction has one.
+    pub change_index: UnhardenedIndex,
+    pub fee: u64,
+    pub vsize: f32,
+    pub selection: CoinSelection,
+}
+
+impl Wallet {
+    /// Composes PSBT paying the `request`. The `headers` index is used to check timelocks of the
+    /// spending path and to derive the transaction lock time.
+    pub fn compose_payment(
+        &self,
+        request: &PaymentRequest,
+        headers: &HeaderIndex,
+    ) -> Result<Composition, Error> {
+        // When sending all funds, beneficiaries with no amount receive everything which remains
+        // after paying others; otherwise the fee may be deducted from the marked payments
+        let send_all = request.coinselect == CoinSelectStrategy::SendAll;
+        let mut txouts = Vec::with_capacity(request.payments.len());
+        let mut remainder_outs = vec![];
+        let mut fee_outs = vec![];
+        let mut output_value = 0u64;
+        for payment in &request.payments {
+            match payment.amount {
+                0 if send_all => remainder_outs.push(txouts.len()),
+                0 => return Err(Error::Amount),
+                _ if payment.subtract_fee => fee_outs.push(txouts.len()),
+                _ => {}
+            }
+            output_value += payment.amount;
+            txouts.push(TxOut {
+                script_pubkey: payment.script_pubkey.clone(),
+                value: payment.amount,
+            });
+        }
+
+        if txouts.is_empty() {
+            return Err(Error::NoPayments);
+        }
+        if send_all && remainder_outs.is_empty() {
+            return Err(Error::NoRemainder);
+        }
+        let subtract_fee = !send_all && !fee_outs.is_empty();
+
+        let settings = self.as_settings();
+        let class = settings.primary_class();
+        let path = request.spending_path;
+        let timelock = settings
+            .spending_paths()
+            .get(path)
+            .copied()
+            .unwrap_or_default()
+            .timelock();
+        let tip = BlockTime::with(self.height(), headers);
+        let status = self.path_status(timelock, tip, headers);
+        if !status.is_available() {
+            return Err(Error::PathUnavailable(status));
+        }
+        let lock_time = self.lock_time(timelock, headers)?;
+        let seq_no = timelock.seq_no().unwrap_or_else(SeqNo::new_rbf);
+        let sighash_type = request.sighash_type;
+        let change_index = self.next_change_index();
+        let change_no = change_index.first_index() as u16;
+        let change_size = |class| -> Result<f32, Error> {
+            let change_script = settings
+                .script_pubkeys(class, true, change_no..=change_no)?
+                .into_values()
+                .next()
+                .expect("change script pubkey is always derivable");
+            Ok(serialize(&TxOut {
+                value: 0,
+                script_pubkey: change_script.into_inner(),
+            })
+            .len() as f32)
+        };
+
+        let fee_rate = request.fee_rate;
+        // Inputs of each descriptor class have their own satisfaction weight
+        let mut class_input_vsize = settings
+            .descriptor_classes()
+            .iter()
+            .map(|class| {
+                let satisfaction_weight = settings.path_satisfaction_weight(*class, path)? as f32;
+                Ok((
+                    *class,
+                    TXIN_BASE_VSIZE + satisfaction_weight / WITNESS_SCALE_FACTOR as f32,
+                ))
+            })
+            .collect::<Result<BTreeMap<_, _>, miniscript::Error>>()?;
+        let input_vsize = class_input_vsize
+            .remove(&class)
+            .expect("primary class is one of the wallet descriptor classes");
+        // Coin selection assumes the change of the primary class
+        let change_vsize = change_size(class)?;
+        let params = CoinSelectParams {
+            fee_rate,
+            long_term_fee_rate: self.ephemerals().fees.2.min(fee_rate),
+            input_vsize,
+            class_input_vsize,
+            change_vsize,
+            dust_limit: ((input_vsize + change_vsize) * DUST_RELAY_TX_FEE as f32 / 1000.0).ceil()
+                as u64,
+        };
+
+        // Everything except inputs and change output is paid from the selection target
+        let tx = Transaction {
+            version: 1,
+            lock_time: lock_time.as_u32(),
+            input: vec![],
+            output: txouts.clone(),
+        };
+        let base_vsize = tx.vsize() as f32;
+        let base_fee = (fee_rate * base_vsize).ceil() as u64;
+        // Fee deducted from the payments is not covered by the selected coins
+        let (target, select_params) = match subtract_fee {
+            true => (output_value, CoinSelectParams {
+                fee_rate: 0.0,
+                long_term_fee_rate: 0.0,
+                ..params.clone()
+            }),
+            false => (output_value + base_fee, params.clone()),
+        };
+        let candidates = self.path_utxos(timelock, tip, headers);
+        let mut selection = match request.coinselect {
+            CoinSelectStrategy::Manual if request.manual_coins.is_empty() => {
+                return Err(Error::NoCoins)
+            }
+            CoinSelectStrategy::Manual => {
+                self.coinselect_manual(&candidates, &request.manual_coins, target, &select_params)
+            }
+            strategy => self.coinselect(strategy, &candidates, target, &select_params),
+        }
+        .ok_or(Error::InsufficientFunds)?;
+
+        // Change is derived from the descriptor class of the selected coins, which may differ
+        // from the primary one, so it pays for its actual size
+        let prevouts = selection.prevouts.iter().copied().collect::<Vec<_>>();
+        let change_class = self
+            .change_class(&prevouts)
+            .ok_or(Error::InsufficientFunds)?;
+        let change_vsize = match change_class == class {
+            true => change_vsize,
+            false => change_size(change_class)?,
+        };
+        if change_vsize != params.change_vsize && !subtract_fee {
+            let extra_fee = (fee_rate * (change_vsize - params.change_vsize)).ceil() as i64;
+            selection.change = selection
+                .change
+                .and_then(|change| u64::try_from(change as i64 - extra_fee).ok())
+                .filter(|change| *change >= params.dust_limit);
+        }
+        let vsize = base_vsize
+            + selection.inputs_vsize(&params)
+            + selection.change.map(|_| change_vsize).unwrap_or_default();
+        let change = selection.change.unwrap_or_default();
+        let required_fee = (fee_rate * vsize).ceil() as u64;
+        if send_all {
+            let remaining = (selection.value - output_value)
+                .checked_sub(required_fee)
+                .ok_or(Error::InsufficientFunds)?;
+            for (no, share) in remainder_outs
+                .iter()
+                .zip(split_evenly(remaining, remainder_outs.len()))
+            {
+                if share < params.dust_limit {
+                    return Err(Error::InsufficientFunds);
+                }
+                txouts[*no].value = share;
+            }
+        } else if subtract_fee {
+            for (no, share) in fee_outs
+                .iter()
+                .zip(split_evenly(required_fee, fee_outs.len()))
+            {
+                txouts[*no].value = txouts[*no]
+                    .value
+                    .checked_sub(share)
+                    .filter(|value| *value >= params.dust_limit)
+                    .ok_or(Error::FeeExceedsAmount)?;
+            }
+        }
+        let output_value = txouts.iter().map(|txout| txout.value).sum::<u64>();
+        let fee = selection.value - output_value - change;
+        if fee < vsize.ceil() as u64 {
+            return Err(Error::FeeFailure);
+        }
+
+        let outputs = txouts
+            .into_iter()
+            .map(|txout| (PubkeyScript::from(txout.script_pubkey), txout.value))
+            .collect::<Vec<_>>();
+
+        let mut psbt =
+            self.construct_psbt(lock_time, &prevouts, &outputs, seq_no, change_index, fee)?;
+        if sighash_type.is_single() {
+            // Each SIGHASH_SINGLE signature commits to the output with the same index as the
+            // input, so BIP-69 ordering would pair inputs with arbitrary outputs. Instead, inputs
+            // are paired with the payments in the requested order, followed by the change.
+            if psbt.inputs.len() > psbt.outputs.len() {
+                return Err(Error::SighashSingle);
+            }
+        } else {
+            psbt.lex_order();
+        }
+        set_sighash_type(&mut psbt, sighash_type);
+        if let Some((depth, script_len)) = settings.tap_leaf(DescriptorClass::TaprootC0, path)? {
+            retain_tap_leaf(&mut psbt, depth, script_len);
+        }
+
+        for signer in settings.signers() {
+            psbt.set_signer_name(signer.master_fp, &signer.name);
+        }
+
+        Ok(Composition {
+            psbt,
+            change_index,
+            fee,
+            vsize,
+            selection,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::psbt::PartiallySignedTransaction;
+    use bitcoin::{Address, BlockHeader, TxIn};
+    use chrono::Utc;
+    use electrum_client::HeaderNotification;
+
+    use super::*;
+    use crate::model::fixtures::{external, settings};
+    use crate::model::{AddressSource, OnchainStatus, OnchainTxid, UtxoTxid};
+    use crate::worker::electrum::TxidMeta;
+
+    /// Wallet with the given descriptor `classes`, which received 100 000 sats to the first
+    /// address of `class` and knows the recent tip at the height 1000.
+    fn wallet(classes: &[DescriptorClass], class: DescriptorClass) -> (Wallet, HeaderIndex) {
+        let settings = settings(classes);
+        let index = UnhardenedIndex::zero();
+        let script = settings.script_pubkeys(class, false, 0..=0).unwrap()[&index].clone();
+        let addr_src = AddressSource::with(&script, index, false, class, bitcoin::Network::Bitcoin);
+        let funding = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: script.into_inner(),
+            }],
+        };
+        let onchain = OnchainTxid {
+            txid: funding.txid(),
+            status: OnchainStatus::Blockchain(900),
+            date_time: None,
+        };
+
+        let mut wallet = Wallet::from(settings);
+        wallet.update_utxos(bset![UtxoTxid {
+            onchain,
+            value: 100_000,
+            vout: 0,
+            addr_src,
+        }]);
+        wallet.update_complete(
+            &bmap! { addr_src => bset![TxidMeta { onchain, fee: None }] },
+            &[funding],
+        );
+        let header = BlockHeader {
+            version: 1,
+            prev_blockhash: zero!(),
+            merkle_root: zero!(),
+            time: Utc::now().timestamp() as u32,
+            bits: 0,
+            nonce: 0,
+        };
+        wallet.update_last_block(&HeaderNotification {
+            height: 1000,
+            header,
+        });
+        let mut headers = HeaderIndex::default();
+        headers.insert(1000, &header);
+        (wallet, headers)
+    }
+
+    fn payment(script_pubkey: Script, amount: u64) -> Payment {
+        Payment {
+            script_pubkey,
+            amount,
+            subtract_fee: false,
+        }
+    }
+
+    #[test]
+    fn single_sighash_keeps_payment_order() {
+        let (wallet, headers) = wallet(&[DescriptorClass::SegwitV0], DescriptorClass::SegwitV0);
+        let other = Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4")
+            .unwrap()
+            .script_pubkey();
+        let mut request =
+            PaymentRequest::with(vec![payment(external(), 30_000), payment(other, 20_000)], 10.0);
+
+        let ordered = wallet.compose_payment(&request, &headers).unwrap();
+        let amounts = |composition: &Composition| -> Vec<u64> {
+            composition.psbt.outputs.iter().map(|output| output.amount).collect()
+        };
+        assert_eq!(amounts(&ordered)[0], 20_000);
+
+        // The only input is paired with the first payment
+        request.sighash_type = SighashType::Single;
+        let paired = wallet.compose_payment(&request, &headers).unwrap();
+        assert_eq!(amounts(&paired)[..2], [30_000, 20_000]);
+        assert_eq!(paired.psbt.outputs[0].script, external());
+    }
+
+    #[test]
+    fn change_of_secondary_class() {
+        let classes = [DescriptorClass::SegwitV0, DescriptorClass::TaprootC0];
+        let (wallet, headers) = wallet(&classes, DescriptorClass::TaprootC0);
+        let request = PaymentRequest::with(vec![payment(external(), 30_000)], 10.0);
+        let composition = wallet.compose_payment(&request, &headers).unwrap();
+
+        let change_script = wallet
+            .as_settings()
+            .script_pubkeys(DescriptorClass::TaprootC0, true, 0..=0)
+            .unwrap()[&composition.change_index]
+            .clone()
+            .into_inner();
+        let change = composition
+            .psbt
+            .outputs
+            .iter()
+            .find(|output| output.script == change_script)
+            .unwrap();
+        assert_eq!(Some(change.amount), composition.selection.change);
+        assert_eq!(composition.fee, 100_000 - 30_000 - change.amount);
+        // Taproot change output is larger than the segwit one of the primary class; the fee must
+        // cover it together with the key path spending signature of at least 16 vbytes
+        let unsigned_tx = PartiallySignedTransaction::from(composition.psbt.clone()).unsigned_tx;
+        assert!(composition.fee as f32 >= 10.0 * (unsigned_tx.vsize() + 16) as f32);
+        assert!(composition.fee as f32 >= 10.0 * composition.vsize);
+    }
+}
@@ -40,13 +40,22 @@ pub struct ElectrumServer {
 }
 
 impl ElectrumServer {
-    pub fn tls(preset: ElectrumPreset, network: PublicNetwork) -> ElectrumServer {
+    pub fn tls(preset: ElectrumPreset, network: &PublicNetwork) -> ElectrumServer {
         ElectrumServer {
             sec: ElectrumSec::Tls,
             server: preset.to_string(),
             port: preset.electrum_port(ElectrumSec::Tls, network),
         }
     }
+
+    /// Default server for the networks which are not served by any of the public presets.
+    pub fn local(network: &PublicNetwork) -> Option<ElectrumServer> {
+        network.electrum_server().map(|server| ElectrumServer {
+            sec: ElectrumSec::None,
+            server,
+            port: network.electrum_port(),
+        })
+    }
 }
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
@@ -74,13 +83,13 @@ impl ElectrumPreset {
         &[ElectrumPreset::MyCitadel, ElectrumPreset::Blockstream]
     }
 
-    pub fn electrum_port(self, sec: ElectrumSec, network: PublicNetwork) -> u16 {
+    pub fn electrum_port(self, sec: ElectrumSec, network: &PublicNetwork) -> u16 {
         match (self, sec, network) {
             (ElectrumPreset::MyCitadel, _, network) => network.electrum_port(),
-            (ElectrumPreset::Blockstream, ElectrumSec::None, PublicNetwork::Mainnet) => 110,
-            (ElectrumPreset::Blockstream, ElectrumSec::None, PublicNetwork::Testnet) => 143,
-            (ElectrumPreset::Blockstream, ElectrumSec::Tls, PublicNetwork::Mainnet) => 700,
-            (ElectrumPreset::Blockstream, ElectrumSec::Tls, PublicNetwork::Testnet) => 993,
+            (ElectrumPreset::Blockstream, ElectrumSec::None, &PublicNetwork::Mainnet) => 110,
+            (ElectrumPreset::Blockstream, ElectrumSec::None, &PublicNetwork::Testnet) => 143,
+            (ElectrumPreset::Blockstream, ElectrumSec::Tls, &PublicNetwork::Mainnet) => 700,
+            (ElectrumPreset::Blockstream, ElectrumSec::Tls, &PublicNetwork::Testnet) => 993,
             (_, _, network) => network.electrum_port(),
         }
     }
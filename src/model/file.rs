@@ -9,18 +9,47 @@
 // a copy of the AGPL-3.0 License along with this software. If not, see
 // <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
 
-use std::io::{Seek, Write};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::{fs, io};
 
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
 use strict_encoding::{StrictDecode, StrictEncode};
 
-use crate::model::{Wallet, WalletSettings};
+use crate::model::migrations::{WalletSettingsV0, WalletV1};
+use crate::model::Wallet;
 
+/// Equals to first 4 bytes of SHA256("mycitadel:wallet")
+/// = cb4457b50453266826e663ddb7bd598c3ff3a6261e941e1eabe790e36a4544b7
+/// Check with `echo -n "mycitadel:wallet" | shasum -a 256`
+const WALLET_DOC_MAGIC: [u8; 4] = [0xcb, 0x44, 0x57, 0xb5];
+
+/// Magic of wallet files which did not have format version in their header.
 /// Equals to first 4 bytes of SHA256("mycitadel:wallet:v1")
 /// = a4546a8ef3a51f1faf2dab1517346e9d84b249f7f52d29339b4ee53fe870d14f
 /// Check with `echo -n "mycitadel:wallet:v1" | shasum -a 256`
-const WALLET_DOC_MAGIC: [u8; 4] = [0xa4, 0x54, 0x6a, 0x8e];
+const WALLET_LEGACY_DOC_MAGIC: [u8; 4] = [0xa4, 0x54, 0x6a, 0x8e];
+
+/// Current version of the wallet file format. See [`crate::model::migrations`] for the history
+/// of the format versions.
+const WALLET_DOC_VERSION: u16 = 2;
+
+/// Equals to first 4 bytes of SHA256("mycitadel:wallet:encrypted:v1")
+/// = 395ba27f6e34386c248c435770a6cfed40ea60a4b8c14b858369301b803e24cf
+/// Check with `echo -n "mycitadel:wallet:encrypted:v1" | shasum -a 256`
+const WALLET_ENCRYPTED_DOC_MAGIC: [u8; 4] = [0x39, 0x5b, 0xa2, 0x7f];
+
+/// Scrypt cost parameters used for newly encrypted documents (log2 N, r, p).
+const KDF_PARAMS: (u8, u32, u32) = (15, 8, 1);
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// Encrypted container header: magic, scrypt log2 N, r & p (little-endian), salt and nonce.
+/// The whole header is authenticated as associated data.
+const HEADER_LEN: usize = 4 + 1 + 4 + 4 + SALT_LEN + NONCE_LEN;
+
+/// Number of backup copies kept for each document file.
+pub const BACKUP_COUNT: usize = 5;
 
 pub struct RefWrap<'doc, T>(pub(self) &'doc T)
 where
@@ -35,28 +64,13 @@ where
     }
 }
 
-#[derive(StrictDecode)]
-pub struct DocReader<T>
-where
-    T: StrictDecode,
-{
-    pub(self) magic: [u8; 4],
-    pub(self) data: T,
-}
-
-impl<T> DocReader<T>
-where
-    T: StrictDecode,
-{
-    pub fn magic_u32(&self) -> u32 { u32::from_be_bytes(self.magic) }
-}
-
 #[derive(StrictEncode)]
 pub struct DocWriter<'doc, T>
 where
     T: StrictEncode,
 {
     pub(self) magic: [u8; 4],
+    pub(self) version: u16,
     pub(self) data: RefWrap<'doc, T>,
 }
 
@@ -65,9 +79,10 @@ where
     T: StrictEncode,
     RefWrap<'doc, T>: StrictEncode,
 {
-    pub fn with(magic: [u8; 4], data: &'doc T) -> Self {
+    pub fn with(magic: [u8; 4], version: u16, data: &'doc T) -> Self {
         DocWriter {
             magic,
+            version,
             data: RefWrap(data),
         }
     }
@@ -80,77 +95,347 @@ pub enum Error {
     File(io::Error),
     #[from]
     Encoding(strict_encoding::Error),
-    #[display("incorrect file format or future version (expected {expected:#X}, got {actual:#X})")]
+    #[display("incorrect file format (expected {expected:#X}, got {actual:#X})")]
     Magic { expected: u32, actual: u32 },
+    #[display(
+        "file was created by a newer version of the application (file format version {version}, \
+         while the latest supported version is {supported})"
+    )]
+    FutureVersion { version: u16, supported: u16 },
+    #[display("unknown file format version {0}")]
+    UnknownVersion(u16),
     #[display("extra data after the end of file")]
     DataNotEntirelyConsumed,
+    #[display("the file is encrypted and requires a passphrase")]
+    PassphraseRequired,
+    #[display("wrong passphrase or corrupted file")]
+    Decryption,
+    #[display("invalid key derivation parameters")]
+    KeyDerivation,
+    #[display("no backup copies of the file were found")]
+    NoBackups,
+}
+
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Path of the backup copy number `no` (starting from 1, which is the most recent one).
+pub fn backup_path(path: impl AsRef<Path>, no: usize) -> PathBuf {
+    suffixed_path(path.as_ref(), &format!(".{}", no))
+}
+
+/// Shifts existing backups by one, dropping the oldest, and backs up the current file.
+fn rotate_backups(path: &Path) -> io::Result<()> {
+    for no in (1..BACKUP_COUNT).rev() {
+        let from = backup_path(path, no);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, no + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+/// Writes data to a temporary file, flushes it to the disk and atomically replaces the target
+/// with it, so a crash at any moment leaves either the old or the new file in place.
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = suffixed_path(path, ".tmp");
+    let res = fs::File::create(&tmp).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()
+    });
+    if let Err(err) = res {
+        let _ = fs::remove_file(&tmp);
+        return Err(err);
+    }
+    if path.exists() {
+        rotate_backups(path)?;
+    }
+    fs::rename(&tmp, path)?;
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], (log_n, r, p): (u8, u32, u32)) -> Result<Key, Error> {
+    let params = scrypt::Params::new(log_n, r, p).map_err(|_| Error::KeyDerivation)?;
+    let mut key = Key::default();
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .map_err(|_| Error::KeyDerivation)?;
+    Ok(key)
+}
+
+/// Wraps serialized document into a passphrase-encrypted container.
+pub fn encrypt(magic: [u8; 4], data: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    let (log_n, r, p) = KDF_PARAMS;
+    let salt: [u8; SALT_LEN] = rand::random();
+    let nonce: [u8; NONCE_LEN] = rand::random();
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend(magic);
+    header.push(log_n);
+    header.extend(r.to_le_bytes());
+    header.extend(p.to_le_bytes());
+    header.extend(salt);
+    header.extend(nonce);
+
+    let key = derive_key(passphrase, &salt, KDF_PARAMS)?;
+    let cipher = XChaCha20Poly1305::new(&key);
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce), Payload {
+            msg: data,
+            aad: &header,
+        })
+        .expect("wallet document exceeds AEAD message size limit");
+
+    header.extend(ciphertext);
+    Ok(header)
+}
+
+/// Unwraps serialized document from a passphrase-encrypted container. Does not check the magic
+/// number.
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    if data.len() < HEADER_LEN {
+        return Err(Error::Decryption);
+    }
+    let (header, ciphertext) = data.split_at(HEADER_LEN);
+    let log_n = header[4];
+    let mut int = [0u8; 4];
+    int.copy_from_slice(&header[5..9]);
+    let r = u32::from_le_bytes(int);
+    int.copy_from_slice(&header[9..13]);
+    let p = u32::from_le_bytes(int);
+    let salt = &header[13..13 + SALT_LEN];
+    let nonce = &header[13 + SALT_LEN..];
+
+    // Refuse cost parameters which would exhaust memory or CPU on a crafted file
+    if log_n > KDF_PARAMS.0 + 5 || r > 32 || p > 16 {
+        return Err(Error::KeyDerivation);
+    }
+    let key = derive_key(passphrase, salt, (log_n, r, p))?;
+    let cipher = XChaCha20Poly1305::new(&key);
+    cipher
+        .decrypt(XNonce::from_slice(nonce), Payload {
+            msg: ciphertext,
+            aad: header,
+        })
+        .map_err(|_| Error::Decryption)
+}
+
+/// Decodes strict-encoded data, failing if the data are not entirely consumed.
+pub fn decode_exact<T>(data: &[u8]) -> Result<T, Error>
+where
+    T: StrictDecode,
+{
+    let mut cursor = io::Cursor::new(data);
+    let doc = T::strict_decode(&mut cursor)?;
+    if cursor.position() != data.len() as u64 {
+        return Err(Error::DataNotEntirelyConsumed);
+    }
+    Ok(doc)
 }
 
 pub trait FileDocument
 where
-    Self: From<Self::FallbackDocType>,
+    Self: Sized,
 {
     const DOC_MAGIC: [u8; 4];
 
-    const FILE_EXT: &'static str;
+    /// Magic number of the files created before the format version was put into the header.
+    const LEGACY_DOC_MAGIC: [u8; 4];
+
+    /// Format versions which may be found in the files with [`Self::LEGACY_DOC_MAGIC`], in the
+    /// order they should be tried.
+    const LEGACY_VERSIONS: &'static [u16];
 
-    type FallbackDocType: StrictDecode;
+    /// Current version of the document format.
+    const DOC_VERSION: u16;
+
+    /// Magic number of the passphrase-encrypted container for the document.
+    const ENCRYPTED_DOC_MAGIC: [u8; 4];
+
+    const FILE_EXT: &'static str;
 
     fn magic_u32() -> u32 { u32::from_be_bytes(Self::DOC_MAGIC) }
 
+    /// Decodes document data of a previous format version and migrates them into the current
+    /// version.
+    fn migrate(version: u16, data: &[u8]) -> Result<Self, Error>;
+
     fn file_name(base: &str, order_no: usize) -> String {
         let mut path = PathBuf::from(format!("{}-{}", base, order_no));
         path.set_extension(Self::FILE_EXT);
         path.display().to_string()
     }
 
-    fn read_file(path: impl AsRef<Path>) -> Result<Self, Error>
+    /// Lists existing backups of the file, starting from the most recent one.
+    fn backups(path: impl AsRef<Path>) -> Vec<PathBuf> {
+        (1..=BACKUP_COUNT)
+            .map(|no| backup_path(path.as_ref(), no))
+            .filter(|backup| backup.exists())
+            .collect()
+    }
+
+    /// Restores the file from the most recent readable backup. The damaged file is kept with
+    /// `.damaged` suffix.
+    fn restore_backup(path: impl AsRef<Path>, passphrase: Option<&str>) -> Result<Self, Error>
     where
         Self: StrictDecode,
     {
-        let mut file = fs::OpenOptions::new()
-            .create(false)
-            .write(false)
-            .read(true)
-            .open(&path)?;
-        let doc = DocReader::<Self>::strict_decode(&mut file)
-            .map_err(Error::from)
-            .and_then(|doc| {
-                if fs::metadata(path)?.len() != file.stream_position()? {
-                    return Err(Error::DataNotEntirelyConsumed);
+        let path = path.as_ref();
+        let mut error = None;
+        for backup in Self::backups(path) {
+            match Self::read_file_with(&backup, passphrase) {
+                Ok(doc) => {
+                    if path.exists() {
+                        fs::rename(path, suffixed_path(path, ".damaged"))?;
+                    }
+                    fs::copy(&backup, path)?;
+                    return Ok(doc);
                 }
-                Ok(doc)
-            })
-            .or_else(|_| {
-                file.rewind()?;
-                DocReader::<Self::FallbackDocType>::strict_decode(&mut file).map(|r| DocReader::<
-                    Self,
-                > {
-                    magic: r.magic,
-                    data: r.data.into(),
-                })
-            })?;
-        if doc.magic != Self::DOC_MAGIC {
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        Err(error.unwrap_or(Error::NoBackups))
+    }
+
+    fn is_encrypted(path: impl AsRef<Path>) -> Result<bool, Error> {
+        let data = fs::read(path)?;
+        Ok(data.starts_with(&Self::ENCRYPTED_DOC_MAGIC))
+    }
+
+    fn read_file(path: impl AsRef<Path>) -> Result<Self, Error>
+    where
+        Self: StrictDecode,
+    {
+        Self::read_file_with(path, None)
+    }
+
+    /// Reads the document, decrypting it with the passphrase if the file is encrypted.
+    fn read_file_with(path: impl AsRef<Path>, passphrase: Option<&str>) -> Result<Self, Error>
+    where
+        Self: StrictDecode,
+    {
+        let data = fs::read(path)?;
+        if data.starts_with(&Self::ENCRYPTED_DOC_MAGIC) {
+            let passphrase = passphrase.ok_or(Error::PassphraseRequired)?;
+            Self::decode_doc(&decrypt(&data, passphrase)?)
+        } else {
+            Self::decode_doc(&data)
+        }
+    }
+
+    fn decode_doc(data: &[u8]) -> Result<Self, Error>
+    where
+        Self: StrictDecode,
+    {
+        if data.len() < 4 {
             return Err(Error::Magic {
                 expected: Self::magic_u32(),
-                actual: doc.magic_u32(),
+                actual: 0,
             });
         }
-        Ok(doc.data)
+        let (magic, data) = data.split_at(4);
+
+        if magic == Self::DOC_MAGIC {
+            if data.len() < 2 {
+                return Err(strict_encoding::Error::DataIntegrityError(s!(
+                    "missed file format version"
+                ))
+                .into());
+            }
+            let (version, data) = data.split_at(2);
+            match u16::from_le_bytes([version[0], version[1]]) {
+                version if version == Self::DOC_VERSION => decode_exact(data),
+                version if version > Self::DOC_VERSION => Err(Error::FutureVersion {
+                    version,
+                    supported: Self::DOC_VERSION,
+                }),
+                version => Self::migrate(version, data),
+            }
+        } else if magic == Self::LEGACY_DOC_MAGIC {
+            let mut error = None;
+            for version in Self::LEGACY_VERSIONS {
+                match Self::migrate(*version, data) {
+                    Ok(doc) => return Ok(doc),
+                    Err(err) => {
+                        error.get_or_insert(err);
+                    }
+                }
+            }
+            Err(error.unwrap_or(Error::UnknownVersion(0)))
+        } else {
+            Err(Error::Magic {
+                expected: Self::magic_u32(),
+                actual: u32::from_be_bytes([magic[0], magic[1], magic[2], magic[3]]),
+            })
+        }
     }
 
     fn write_file(&self, path: impl AsRef<Path>) -> Result<usize, Error>
     where
         Self: Sized + StrictEncode,
     {
-        let doc = DocWriter::with(Self::DOC_MAGIC, self);
-        let file = fs::File::create(path)?;
-        doc.strict_encode(file).map_err(Error::Encoding)
+        self.write_file_with(path, None)
+    }
+
+    /// Writes the document, encrypting it if a passphrase is given. The previous version of the
+    /// file is kept as a backup.
+    fn write_file_with(
+        &self,
+        path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+    ) -> Result<usize, Error>
+    where
+        Self: Sized + StrictEncode,
+    {
+        let mut data = vec![];
+        DocWriter::with(Self::DOC_MAGIC, Self::DOC_VERSION, self).strict_encode(&mut data)?;
+        if let Some(passphrase) = passphrase {
+            data = encrypt(Self::ENCRYPTED_DOC_MAGIC, &data, passphrase)?;
+        }
+        write_atomic(path.as_ref(), &data)?;
+        Ok(data.len())
+    }
+
+    /// Writes the document with a new passphrase, or unencrypted if `None` is given, and removes
+    /// all backups of the file. Backups keep the previous encryption state, so leaving them in
+    /// place would keep copies of the document readable without the new passphrase.
+    fn rekey_file(&self, path: impl AsRef<Path>, passphrase: Option<&str>) -> Result<usize, Error>
+    where
+        Self: Sized + StrictEncode,
+    {
+        let path = path.as_ref();
+        let len = self.write_file_with(path, passphrase)?;
+        for backup in Self::backups(path) {
+            fs::remove_file(backup)?;
+        }
+        Ok(len)
     }
 }
 
 impl FileDocument for Wallet {
     const DOC_MAGIC: [u8; 4] = WALLET_DOC_MAGIC;
+    const ENCRYPTED_DOC_MAGIC: [u8; 4] = WALLET_ENCRYPTED_DOC_MAGIC;
+    const LEGACY_DOC_MAGIC: [u8; 4] = WALLET_LEGACY_DOC_MAGIC;
+    const LEGACY_VERSIONS: &'static [u16] = &[1, 0];
+    const DOC_VERSION: u16 = WALLET_DOC_VERSION;
     const FILE_EXT: &'static str = "mcw";
-    type FallbackDocType = WalletSettings;
+
+    fn migrate(version: u16, data: &[u8]) -> Result<Self, Error> {
+        match version {
+            0 => decode_exact::<WalletSettingsV0>(data)
+                .map(WalletV1::from)
+                .map(Wallet::from),
+            1 => decode_exact::<WalletV1>(data).map(Wallet::from),
+            unknown => Err(Error::UnknownVersion(unknown)),
+        }
+    }
 }
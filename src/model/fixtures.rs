@@ -0,0 +1,61 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime SA, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! Wallet data shared by the unit tests.
+
+use std::str::FromStr;
+
+use bitcoin::util::bip32::ExtendedPubKey;
+use bitcoin::{Address, Script};
+use wallet::hd::{Bip43, TerminalStep};
+
+use crate::model::{
+    DescriptorClass, ElectrumPreset, ElectrumServer, PublicNetwork, Signer, SpendingCondition,
+    WalletSettings,
+};
+
+/// BIP-32 test vector 1 master key.
+pub const XPUB: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+
+/// BIP-32 test vector 1 master key for the test networks.
+pub const TPUB: &str = "tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp";
+
+/// Single-signature mainnet wallet with the [`XPUB`] signer and the given descriptor classes.
+pub fn settings(classes: &[DescriptorClass]) -> WalletSettings {
+    network_settings(PublicNetwork::Mainnet, classes)
+}
+
+/// Single-signature wallet on the given `network` with the [`XPUB`] or [`TPUB`] signer and the
+/// given descriptor classes.
+pub fn network_settings(network: PublicNetwork, classes: &[DescriptorClass]) -> WalletSettings {
+    let xpub = match network.is_testnet() {
+        false => XPUB,
+        true => TPUB,
+    };
+    let xpub = ExtendedPubKey::from_str(xpub).unwrap();
+    let signer = Signer::with_xpub(xpub, &Bip43::singlesig_segwit0(), network.clone());
+    WalletSettings::with(
+        [signer],
+        [(0, SpendingCondition::default())],
+        classes.iter().copied(),
+        vec![TerminalStep::range(0u8, 1u8), TerminalStep::Wildcard],
+        network.clone(),
+        ElectrumServer::tls(ElectrumPreset::Blockstream, &network),
+    )
+    .unwrap()
+}
+
+/// Script pubkey which does not belong to the wallet.
+pub fn external() -> Script {
+    Address::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq")
+        .unwrap()
+        .script_pubkey()
+}
@@ -0,0 +1,321 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime SA, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bitcoin::BlockHeader;
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::model::file;
+
+/// Equals to first 4 bytes of SHA256("mycitadel:headers")
+/// = 3b4ee4e1564d9f0c4fefc50d1056162c393ad881837bcabada04a2de4efaf269
+/// Check with `echo -n "mycitadel:headers" | shasum -a 256`
+const HEADERS_MAGIC: [u8; 4] = [0x3b, 0x4e, 0xe4, 0xe1];
+
+/// Equals to first 4 bytes of SHA256("mycitadel:headers:encrypted")
+/// = 40759f9834538c5eec65db7506d8a5b21934030d04b14d3c2e16762ad051b3ef
+/// Check with `echo -n "mycitadel:headers:encrypted" | shasum -a 256`
+const HEADERS_ENCRYPTED_MAGIC: [u8; 4] = [0x40, 0x75, 0x9f, 0x98];
+
+/// Size of a single index record: block height and block timestamp, both little-endian `u32`.
+const RECORD_LEN: usize = 8;
+
+/// Average time between blocks used to extrapolate timestamps of unknown blocks.
+const BLOCK_INTERVAL: i64 = 600;
+
+/// Reference mainnet block used for the estimates when the index has no known blocks.
+const REFERENCE_BLOCK: (u32, u32) = (733961, 1651158666);
+
+/// Index of block timestamps by block height, covering the blocks touched by the wallet
+/// transactions and the last known chain tip.
+///
+/// The index is kept in a compact binary file next to the wallet file, consisting of the magic
+/// number followed by fixed-size records sorted by height. Since the index discloses when the
+/// wallet was used, the file of an encrypted wallet is encrypted with the same passphrase.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct HeaderIndex(BTreeMap<u32, u32>);
+
+impl HeaderIndex {
+    /// Path of the index file for the wallet stored at `wallet_path`.
+    pub fn index_path(wallet_path: impl AsRef<Path>) -> PathBuf {
+        wallet_path.as_ref().with_extension("mch")
+    }
+
+    /// Reads index of the wallet stored at `wallet_path`, decrypting it with the wallet
+    /// `passphrase` if the index is encrypted. Missing file produces an empty index.
+    pub fn read_for(
+        wallet_path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+    ) -> Result<HeaderIndex, file::Error> {
+        let path = HeaderIndex::index_path(wallet_path);
+        if !path.exists() {
+            return Ok(HeaderIndex::default());
+        }
+        let data = fs::read(path)?;
+        if !data.starts_with(&HEADERS_ENCRYPTED_MAGIC) {
+            return HeaderIndex::decode(&data);
+        }
+        let passphrase = passphrase.ok_or(file::Error::PassphraseRequired)?;
+        HeaderIndex::decode(&file::decrypt(&data, passphrase)?)
+    }
+
+    /// Writes index for the wallet stored at `wallet_path`, encrypting it with the wallet
+    /// `passphrase`, if any. Since the index is a cache which can be always restored from the
+    /// electrum server, no backups are kept.
+    pub fn write_for(
+        &self,
+        wallet_path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+    ) -> Result<(), file::Error> {
+        let data = match passphrase {
+            None => self.encode(),
+            Some(passphrase) => file::encrypt(HEADERS_ENCRYPTED_MAGIC, &self.encode(), passphrase)?,
+        };
+        fs::write(HeaderIndex::index_path(wallet_path), data)?;
+        Ok(())
+    }
+
+    pub fn decode(data: &[u8]) -> Result<HeaderIndex, file::Error> {
+        if data.len() < 4 || data[..4] != HEADERS_MAGIC {
+            let mut actual = [0u8; 4];
+            actual[..data.len().min(4)].copy_from_slice(&data[..data.len().min(4)]);
+            return Err(file::Error::Magic {
+                expected: u32::from_be_bytes(HEADERS_MAGIC),
+                actual: u32::from_be_bytes(actual),
+            });
+        }
+        let records = &data[4..];
+        if records.len() % RECORD_LEN != 0 {
+            return Err(file::Error::DataNotEntirelyConsumed);
+        }
+        let mut int = [0u8; 4];
+        Ok(HeaderIndex(
+            records
+                .chunks(RECORD_LEN)
+                .map(|record| {
+                    int.copy_from_slice(&record[..4]);
+                    let height = u32::from_le_bytes(int);
+                    int.copy_from_slice(&record[4..]);
+                    (height, u32::from_le_bytes(int))
+                })
+                .collect(),
+        ))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(4 + self.0.len() * RECORD_LEN);
+        data.extend(HEADERS_MAGIC);
+        for (height, time) in &self.0 {
+            data.extend(height.to_le_bytes());
+            data.extend(time.to_le_bytes());
+        }
+        data
+    }
+
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    pub fn contains(&self, height: u32) -> bool { self.0.contains_key(&height) }
+
+    pub fn heights(&self) -> BTreeSet<u32> { self.0.keys().copied().collect() }
+
+    /// Adds block header at the given height; returns whether the index was changed.
+    pub fn insert(&mut self, height: u32, header: &BlockHeader) -> bool {
+        self.0.insert(height, header.time) != Some(header.time)
+    }
+
+    pub fn extend(&mut self, headers: impl IntoIterator<Item = (u32, BlockHeader)>) -> bool {
+        headers
+            .into_iter()
+            .fold(false, |changed, (height, header)| {
+                self.insert(height, &header) | changed
+            })
+    }
+
+    /// Exact timestamp of the block at the given height, if the block is present in the index.
+    pub fn time_at(&self, height: u32) -> Option<DateTime<Utc>> {
+        self.0
+            .get(&height)
+            .map(|time| timestamp_to_utc(*time as i64))
+    }
+
+    /// Timestamp of the block at the given height. Exact for the blocks present in the index;
+    /// otherwise extrapolated from the closest known block.
+    pub fn estimate_time(&self, height: u32) -> DateTime<Utc> {
+        let (known_height, known_time) = self
+            .0
+            .range(..=height)
+            .next_back()
+            .into_iter()
+            .chain(self.0.range(height..).next())
+            .min_by_key(|(h, _)| (**h as i64 - height as i64).abs())
+            .map(|(h, t)| (*h, *t))
+            .unwrap_or(REFERENCE_BLOCK);
+        timestamp_to_utc(known_time as i64 + (height as i64 - known_height as i64) * BLOCK_INTERVAL)
+    }
+
+    /// Height of the first block with the timestamp not less than `date_time`. Exact if the date
+    /// falls within the range of known blocks with no gaps; otherwise extrapolated from the
+    /// closest known block.
+    pub fn estimate_height(&self, date_time: DateTime<Utc>) -> u32 {
+        let timestamp = date_time.timestamp();
+        let before = self
+            .0
+            .iter()
+            .rev()
+            .find(|(_, time)| (**time as i64) < timestamp);
+        let after = self.0.iter().find(|(_, time)| **time as i64 >= timestamp);
+        match (before, after) {
+            // Both neighbouring blocks are known
+            (Some((h1, _)), Some((h2, _))) if h1 + 1 == *h2 => *h2,
+            (_, Some((height, time))) | (Some((height, time)), None) => {
+                extrapolate_height((*height, *time), timestamp)
+            }
+            (None, None) => extrapolate_height(REFERENCE_BLOCK, timestamp),
+        }
+    }
+}
+
+fn timestamp_to_utc(timestamp: i64) -> DateTime<Utc> {
+    DateTime::from_utc(NaiveDateTime::from_timestamp(timestamp, 0), Utc)
+}
+
+fn extrapolate_height(known: (u32, u32), timestamp: i64) -> u32 {
+    let blocks = (timestamp - known.1 as i64) as f64 / BLOCK_INTERVAL as f64;
+    (known.0 as i64 + blocks.ceil() as i64).max(0) as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::OnchainStatus;
+
+    const TIME: u32 = 1_650_000_000;
+
+    fn header(time: u32) -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_blockhash: zero!(),
+            merkle_root: zero!(),
+            time,
+            bits: 0,
+            nonce: 0,
+        }
+    }
+
+    /// Index with blocks 100..=102 and 200, where blocks were mined 500 seconds apart.
+    fn index() -> HeaderIndex {
+        let mut headers = HeaderIndex::default();
+        assert!(headers.extend(
+            [100, 101, 102, 200]
+                .into_iter()
+                .map(|height| (height, header(TIME + (height - 100) * 500)))
+        ));
+        headers
+    }
+
+    fn utc(timestamp: u32) -> DateTime<Utc> { timestamp_to_utc(timestamp as i64) }
+
+    #[test]
+    fn encoding_roundtrip() {
+        let headers = index();
+        let data = headers.encode();
+        assert_eq!(data.len(), 4 + 4 * RECORD_LEN);
+        assert_eq!(data[..4], HEADERS_MAGIC);
+        assert_eq!(HeaderIndex::decode(&data).unwrap(), headers);
+
+        let empty = HeaderIndex::default().encode();
+        assert_eq!(empty, HEADERS_MAGIC.to_vec());
+        assert!(HeaderIndex::decode(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn decoding_errors() {
+        let data = index().encode();
+        assert!(matches!(
+            HeaderIndex::decode(&data[..data.len() - 1]),
+            Err(file::Error::DataNotEntirelyConsumed)
+        ));
+        assert!(matches!(
+            HeaderIndex::decode(&data[1..]),
+            Err(file::Error::Magic { .. })
+        ));
+        assert!(matches!(
+            HeaderIndex::decode(&data[..2]),
+            Err(file::Error::Magic { .. })
+        ));
+    }
+
+    #[test]
+    fn insert_reports_changes() {
+        let mut headers = index();
+        assert!(!headers.insert(100, &header(TIME)));
+        assert!(headers.insert(100, &header(TIME + 1)));
+        assert!(!headers.extend([(101, header(TIME + 500))]));
+        assert_eq!(headers.time_at(100), Some(utc(TIME + 1)));
+        assert_eq!(headers.time_at(103), None);
+    }
+
+    #[test]
+    fn time_estimates() {
+        let headers = index();
+        // Known blocks are exact
+        assert_eq!(headers.estimate_time(101), utc(TIME + 500));
+        assert_eq!(headers.estimate_time(200), utc(TIME + 50_000));
+        // Unknown blocks are extrapolated from the closest known one
+        assert_eq!(headers.estimate_time(110), utc(TIME + 1_000 + 8 * 600));
+        assert_eq!(headers.estimate_time(190), utc(TIME + 50_000 - 10 * 600));
+        assert_eq!(headers.estimate_time(90), utc(TIME - 10 * 600));
+        assert_eq!(headers.estimate_time(210), utc(TIME + 50_000 + 10 * 600));
+
+        let (height, time) = REFERENCE_BLOCK;
+        assert_eq!(
+            HeaderIndex::default().estimate_time(height + 6),
+            utc(time + 3_600)
+        );
+    }
+
+    #[test]
+    fn height_estimates() {
+        let headers = index();
+        // Neighbouring blocks are known: first block at or after the date
+        assert_eq!(headers.estimate_height(utc(TIME + 500)), 101);
+        assert_eq!(headers.estimate_height(utc(TIME + 501)), 102);
+        assert_eq!(headers.estimate_height(utc(TIME + 250)), 101);
+        // Within a gap the estimate is extrapolated back from the next known block
+        assert_eq!(headers.estimate_height(utc(TIME + 50_000 - 1_200)), 198);
+        // Outside of the known range the estimate is extrapolated from the closest block
+        assert_eq!(headers.estimate_height(utc(TIME - 6_000)), 90);
+        assert_eq!(headers.estimate_height(utc(TIME + 50_000 + 6_000)), 210);
+
+        let (height, time) = REFERENCE_BLOCK;
+        assert_eq!(
+            HeaderIndex::default().estimate_height(utc(time + 3_600)),
+            height + 6
+        );
+    }
+
+    #[test]
+    fn status_time_estimate() {
+        let headers = index();
+        let local = |timestamp| DateTime::<chrono::Local>::from(utc(timestamp));
+        assert_eq!(
+            OnchainStatus::Blockchain(102).date_time_est(&headers),
+            local(TIME + 1_000)
+        );
+        assert_eq!(
+            OnchainStatus::Blockchain(105).date_time_est(&headers),
+            local(TIME + 1_000 + 3 * 600)
+        );
+    }
+}
@@ -0,0 +1,147 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime SA, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! BIP-329 wallet labels export format.
+
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display(lowercase)]
+pub enum LabelType {
+    Tx,
+    Addr,
+    Pubkey,
+    Input,
+    Output,
+    Xpub,
+}
+
+impl FromStr for LabelType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "tx" => LabelType::Tx,
+            "addr" => LabelType::Addr,
+            "pubkey" => LabelType::Pubkey,
+            "input" => LabelType::Input,
+            "output" => LabelType::Output,
+            "xpub" => LabelType::Xpub,
+            other => return Err(Error::UnknownType(other.to_owned())),
+        })
+    }
+}
+
+/// Single BIP-329 label record.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub struct Label {
+    pub ty: LabelType,
+    /// Reference to the labelled item: txid, address, pubkey, `txid:index` or xpub.
+    pub reference: String,
+    pub label: String,
+    /// Key origin information of the descriptor the item belongs to.
+    pub origin: Option<String>,
+    /// Applies to outputs only: whether the output may be spent by the wallet.
+    pub spendable: Option<bool>,
+}
+
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// I/O error while processing labels: {0}
+    #[from]
+    Io(io::Error),
+
+    /// invalid JSON in label record: {0}
+    #[from]
+    Json(serde_json::Error),
+
+    /// label record is not a JSON object
+    NotObject,
+
+    /// label record misses required field `{0}`
+    NoField(&'static str),
+
+    /// unknown label type `{0}`
+    UnknownType(String),
+
+    /// line {0}: {1}
+    Line(usize, Box<Error>),
+}
+
+impl Label {
+    pub fn with(ty: LabelType, reference: impl ToString, label: impl ToString) -> Label {
+        Label {
+            ty,
+            reference: reference.to_string(),
+            label: label.to_string(),
+            origin: None,
+            spendable: None,
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<Label, Error> {
+        let value: Value = serde_json::from_str(line)?;
+        let obj = value.as_object().ok_or(Error::NotObject)?;
+        let field = |name: &'static str| obj.get(name).and_then(Value::as_str);
+        Ok(Label {
+            ty: field("type").ok_or(Error::NoField("type"))?.parse()?,
+            reference: field("ref").ok_or(Error::NoField("ref"))?.to_owned(),
+            label: field("label").unwrap_or_default().to_owned(),
+            origin: field("origin").map(str::to_owned),
+            spendable: obj.get("spendable").and_then(Value::as_bool),
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut obj = Map::new();
+        obj.insert(s!("type"), Value::from(self.ty.to_string()));
+        obj.insert(s!("ref"), Value::from(self.reference.clone()));
+        obj.insert(s!("label"), Value::from(self.label.clone()));
+        if let Some(origin) = &self.origin {
+            obj.insert(s!("origin"), Value::from(origin.clone()));
+        }
+        if let Some(spendable) = self.spendable {
+            obj.insert(s!("spendable"), Value::from(spendable));
+        }
+        Value::Object(obj).to_string()
+    }
+}
+
+/// Reads labels from a JSONL stream, skipping empty lines.
+pub fn read_labels(reader: impl BufRead) -> Result<Vec<Label>, Error> {
+    let mut labels = vec![];
+    for (no, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let label = Label::parse(&line).map_err(|err| Error::Line(no + 1, Box::new(err)))?;
+        labels.push(label);
+    }
+    Ok(labels)
+}
+
+/// Writes labels as a JSONL stream.
+pub fn write_labels<'label>(
+    mut writer: impl Write,
+    labels: impl IntoIterator<Item = &'label Label>,
+) -> Result<usize, io::Error> {
+    let mut count = 0usize;
+    for label in labels {
+        writeln!(writer, "{}", label.to_json())?;
+        count += 1;
+    }
+    Ok(count)
+}
@@ -0,0 +1,176 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime SA, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! Data layouts used by the previous versions of the wallet file format and migrations from them
+//! towards the current version.
+//!
+//! Format versions:
+//! - v0: wallet settings only, without the format version in the file header;
+//! - v1: wallet settings with cached blockchain data, without the format version in the file
+//!   header;
+//! - v2: versioned header; adds gap limit, frozen outputs, UTXO & address labels and descriptor
+//!   class of each wallet address.
+
+use std::collections::BTreeMap;
+
+use ::wallet::address::AddressCompat;
+use ::wallet::hd::UnhardenedIndex;
+use ::wallet::psbt::Psbt;
+use bitcoin::{BlockHash, Transaction};
+
+use crate::model::{
+    AddressSource, AddressValue, DescriptorClass, ElectrumServer, HistoryEntry, OnchainTxid,
+    PublicNetwork, Signer, UtxoTxid, WalletDescriptor, WalletEphemerals, WalletState,
+};
+
+/// Layout of [`crate::model::WalletSettings`] before the gap limit was introduced (format v0 and
+/// v1).
+#[derive(Clone, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct WalletSettingsV0 {
+    pub network: PublicNetwork,
+    pub core: WalletDescriptor,
+    pub signers: Vec<Signer>,
+    pub electrum: ElectrumServer,
+}
+
+/// Layout of [`AddressSource`] before the descriptor class was recorded (format v1).
+#[derive(Copy, Clone, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct AddressSourceV1 {
+    pub address: AddressCompat,
+    pub change: bool,
+    pub index: UnhardenedIndex,
+}
+
+#[derive(Copy, Clone, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct AddressValueV1 {
+    pub addr_src: AddressSourceV1,
+    pub value: u64,
+}
+
+#[derive(Clone, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct HistoryEntryV1 {
+    pub onchain: OnchainTxid,
+    pub tx: Transaction,
+    pub credit: BTreeMap<u32, AddressValueV1>,
+    pub debit: BTreeMap<u32, AddressSourceV1>,
+    pub payers: BTreeMap<u32, (Option<String>, Option<AddressValueV1>)>,
+    pub beneficiaries: BTreeMap<u32, String>,
+    pub fee: Option<u64>,
+    pub comment: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct UtxoTxidV1 {
+    pub onchain: OnchainTxid,
+    pub value: u64,
+    pub vout: u32,
+    pub addr_src: AddressSourceV1,
+}
+
+/// Layout of [`crate::model::Wallet`] in format v1. Sets are kept as vectors, which have the same
+/// strict encoding.
+#[derive(Clone, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct WalletV1 {
+    pub settings: WalletSettingsV0,
+    pub last_indexes: BTreeMap<UnhardenedIndex, UnhardenedIndex>,
+    pub last_block: BlockHash,
+    pub height: u32,
+    pub state: WalletState,
+    pub ephemerals: WalletEphemerals,
+    pub utxos: Vec<UtxoTxidV1>,
+    pub history: Vec<HistoryEntryV1>,
+    pub wip: Vec<Psbt>,
+}
+
+/// Migration v0 -> v1: settings-only files get empty blockchain data, which is restored with the
+/// next sync.
+impl From<WalletSettingsV0> for WalletV1 {
+    fn from(settings: WalletSettingsV0) -> Self {
+        WalletV1 {
+            settings,
+            last_indexes: empty!(),
+            last_block: zero!(),
+            height: 0,
+            state: zero!(),
+            ephemerals: zero!(),
+            utxos: vec![],
+            history: vec![],
+            wip: vec![],
+        }
+    }
+}
+
+// Migration v1 -> v2 for the blockchain data. Format v1 tracked addresses of the primary
+// descriptor class only, so all of them are attributed to it.
+
+impl AddressSourceV1 {
+    pub fn upgrade(self, class: DescriptorClass) -> AddressSource {
+        AddressSource {
+            address: self.address,
+            change: self.change,
+            index: self.index,
+            class,
+        }
+    }
+}
+
+impl AddressValueV1 {
+    pub fn upgrade(self, class: DescriptorClass) -> AddressValue {
+        AddressValue {
+            addr_src: self.addr_src.upgrade(class),
+            value: self.value,
+        }
+    }
+}
+
+impl HistoryEntryV1 {
+    pub fn upgrade(self, class: DescriptorClass) -> HistoryEntry {
+        HistoryEntry {
+            onchain: self.onchain,
+            tx: self.tx,
+            credit: self
+                .credit
+                .into_iter()
+                .map(|(vout, value)| (vout, value.upgrade(class)))
+                .collect(),
+            debit: self
+                .debit
+                .into_iter()
+                .map(|(vout, addr_src)| (vout, addr_src.upgrade(class)))
+                .collect(),
+            payers: self
+                .payers
+                .into_iter()
+                .map(|(vin, (payer, value))| (vin, (payer, value.map(|v| v.upgrade(class)))))
+                .collect(),
+            beneficiaries: self.beneficiaries,
+            fee: self.fee,
+            comment: self.comment,
+        }
+    }
+}
+
+impl UtxoTxidV1 {
+    pub fn upgrade(self, class: DescriptorClass) -> UtxoTxid {
+        UtxoTxid {
+            onchain: self.onchain,
+            value: self.value,
+            vout: self.vout,
+            addr_src: self.addr_src.upgrade(class),
+        }
+    }
+}
@@ -9,32 +9,52 @@
 // a copy of the AGPL-3.0 License along with this software. If not, see
 // <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
 
+pub mod bump;
+mod coinselect;
+pub mod compose;
 mod electrum;
 pub mod file;
+#[cfg(test)]
+mod fixtures;
+mod headers;
+pub mod labels;
+pub mod migrations;
 mod onchain;
 pub mod psbt;
 mod sign;
+mod spend;
 mod taptree;
 mod template;
 mod types;
+#[cfg(feature = "ui")]
 mod ui;
 mod unsatisfiable;
 mod wallet;
 mod xkey;
 
+pub use bump::{BumpMethod, FeeBump};
+pub use coinselect::{
+    split_evenly, BranchAndBound, CoinSelectParams, CoinSelectStrategy, CoinSelection,
+    CoinSelector, Knapsack, LargestFirst, Manual, OldestFirst, Privacy, SendAll, TXIN_BASE_VSIZE,
+};
+pub use compose::{Composition, Payment, PaymentRequest};
 pub use electrum::{ElectrumPreset, ElectrumSec, ElectrumServer};
 pub use file::FileDocument;
+pub use headers::HeaderIndex;
+pub use labels::{Label, LabelType};
 pub use onchain::{
     AddressSource, AddressSummary, AddressValue, HistoryEntry, OnchainStatus, OnchainTxid, Prevout,
     UtxoTxid,
 };
 pub use sign::XprivSigner;
+pub use spend::{BlockTime, PathStatus, TipError, MAX_TIP_AGE};
 pub use taptree::ToTapTree;
 pub use template::{Requirement, WalletTemplate};
 pub use types::{
-    DescriptorClass, Error, HardwareDevice, HardwareList, OriginFormat, Ownership, PublicNetwork,
-    Signer, SigsReq, TimelockDuration, TimelockReq, TimelockedSigs,
+    CustomSignet, DescriptorClass, Error, HardwareDevice, HardwareList, OriginFormat, Ownership,
+    PublicNetwork, Signer, SigsReq, TimelockDuration, TimelockReq, TimelockedSigs,
 };
+#[cfg(feature = "ui")]
 pub use ui::Notification;
 pub use unsatisfiable::Unsatisfiable;
 pub use xkey::{
@@ -44,5 +64,6 @@ pub use xkey::{
 
 pub use self::wallet::{
     DerivationStandardExt, DerivationType, DescriptorError, SpendingCondition, Wallet,
-    WalletDescriptor, WalletEphemerals, WalletSettings, WalletState,
+    WalletDescriptor, WalletEphemerals, WalletSettings, WalletState, DEFAULT_GAP_LIMIT,
+    MAX_GAP_LIMIT,
 };
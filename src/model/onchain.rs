@@ -15,10 +15,11 @@ use std::collections::BTreeMap;
 use ::wallet::address::AddressCompat;
 use ::wallet::hd::{DerivationSubpath, SegmentIndexes, UnhardenedIndex};
 use bitcoin::{OutPoint, Transaction, Txid};
-use chrono::{DateTime, NaiveDateTime, Utc};
-use gtk::gdk;
+use chrono::{DateTime, Utc};
 use wallet::scripts::PubkeyScript;
 
+use crate::model::{DescriptorClass, HeaderIndex};
+
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct AddressSummary {
     pub addr_src: AddressSource,
@@ -53,6 +54,8 @@ pub struct AddressSource {
     pub address: AddressCompat,
     pub change: bool,
     pub index: UnhardenedIndex,
+    /// Descriptor class which produced the address.
+    pub class: DescriptorClass,
 }
 
 impl AddressSource {
@@ -63,12 +66,14 @@ impl AddressSource {
         script: &PubkeyScript,
         index: UnhardenedIndex,
         change: bool,
+        class: DescriptorClass,
         network: bitcoin::Network,
     ) -> AddressSource {
         AddressSource {
             address: AddressCompat::from_script(script, network).expect("invalid script"),
             change,
             index,
+            class,
         }
     }
 
@@ -153,17 +158,20 @@ impl OnchainStatus {
 
     pub fn is_mined(self) -> bool { self != OnchainStatus::Mempool }
 
-    // TODO: Do a binary file indexed by height, representing date/time information for each height
-    pub fn date_time_est(self) -> DateTime<chrono::Local> {
+    pub fn height(self) -> Option<u32> {
         match self {
-            OnchainStatus::Mempool => return chrono::Local::now(),
+            OnchainStatus::Blockchain(height) => Some(height),
+            OnchainStatus::Mempool => None,
+        }
+    }
+
+    /// Estimates mining time using the `headers` index; time of the blocks absent from the index
+    /// is extrapolated from the closest known block.
+    pub fn date_time_est(self, headers: &HeaderIndex) -> DateTime<chrono::Local> {
+        match self {
+            OnchainStatus::Mempool => chrono::Local::now(),
             OnchainStatus::Blockchain(height) => {
-                let reference_height = 733961;
-                let reference_time = 1651158666_i32;
-                let height_diff = height as i32 - reference_height;
-                let timestamp = reference_time.saturating_add(height_diff * 600);
-                let block_time = NaiveDateTime::from_timestamp(timestamp as i64, 0);
-                DateTime::<chrono::Local>::from(DateTime::<Utc>::from_utc(block_time, Utc))
+                DateTime::<chrono::Local>::from(headers.estimate_time(height))
             }
         }
     }
@@ -202,19 +210,21 @@ impl PartialOrd for OnchainTxid {
 }
 
 impl OnchainTxid {
-    pub fn date_time_est(self) -> DateTime<chrono::Local> {
+    pub fn date_time_est(self, headers: &HeaderIndex) -> DateTime<chrono::Local> {
         self.date_time()
-            .unwrap_or_else(|| self.status.date_time_est())
+            .unwrap_or_else(|| self.status.date_time_est(headers))
     }
 
     pub fn date_time(self) -> Option<DateTime<chrono::Local>> {
         self.date_time.map(DateTime::<chrono::Local>::from)
     }
 
-    pub fn mining_info(self) -> String {
+    pub fn mining_info(self, headers: &HeaderIndex) -> String {
         match self.status {
             OnchainStatus::Mempool => s!("pending"),
-            OnchainStatus::Blockchain(_) => format!("{}", self.date_time_est().format("%F %l %P")),
+            OnchainStatus::Blockchain(_) => {
+                format!("{}", self.date_time_est(headers).format("%F %l %P"))
+            }
         }
     }
 }
@@ -260,20 +270,20 @@ impl HistoryEntry {
         }
     }
 
-    pub fn color(&self) -> gdk::RGBA {
-        match self.balance() {
-            x if x > 0 => gdk::RGBA::new(38.0 / 256.0, 162.0 / 256.0, 105.0 / 256.0, 1.0),
-            x if x < 0 => gdk::RGBA::new(165.0 / 256.0, 29.0 / 256.0, 45.0 / 256.0, 1.0),
-            0 => gdk::RGBA::new(119.0 / 256.0, 118.0 / 256.0, 123.0 / 256.0, 1.0),
-            _ => unreachable!(),
-        }
+    pub fn date_time_est(&self, headers: &HeaderIndex) -> DateTime<chrono::Local> {
+        self.onchain.date_time_est(headers)
     }
 
-    pub fn date_time_est(&self) -> DateTime<chrono::Local> { self.onchain.date_time_est() }
-
     pub fn date_time(&self) -> Option<DateTime<chrono::Local>> { self.onchain.date_time() }
 
-    pub fn mining_info(&self) -> String { self.onchain.mining_info() }
+    pub fn mining_info(&self, headers: &HeaderIndex) -> String {
+        self.onchain.mining_info(headers)
+    }
+
+    /// Fee rate in sats per vbyte, if the transaction fee is known.
+    pub fn fee_rate(&self) -> Option<f32> {
+        self.fee.map(|fee| fee as f32 / self.tx.vsize() as f32)
+    }
 
     pub fn value_credited(&self) -> u64 { self.credit.values().map(|addr| addr.value).sum() }
 
@@ -330,11 +340,13 @@ pub struct UtxoTxid {
 impl UtxoTxid {
     pub fn outpoint(&self) -> OutPoint { OutPoint::new(self.onchain.txid, self.vout) }
 
-    pub fn date_time_est(self) -> DateTime<chrono::Local> { self.onchain.date_time_est() }
+    pub fn date_time_est(self, headers: &HeaderIndex) -> DateTime<chrono::Local> {
+        self.onchain.date_time_est(headers)
+    }
 
     pub fn date_time(self) -> Option<DateTime<chrono::Local>> { self.onchain.date_time() }
 
-    pub fn mining_info(self) -> String { self.onchain.mining_info() }
+    pub fn mining_info(self, headers: &HeaderIndex) -> String { self.onchain.mining_info(headers) }
 }
 
 impl From<&UtxoTxid> for Prevout {
@@ -344,6 +356,7 @@ impl From<&UtxoTxid> for Prevout {
             amount: utxo.value,
             change: utxo.addr_src.change,
             index: utxo.addr_src.index,
+            class: utxo.addr_src.class,
         }
     }
 }
@@ -358,6 +371,8 @@ pub struct Prevout {
     pub amount: u64,
     pub change: bool,
     pub index: UnhardenedIndex,
+    /// Class of the wallet descriptor which produced the spent output.
+    pub class: DescriptorClass,
 }
 
 impl Prevout {
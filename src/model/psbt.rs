@@ -9,12 +9,113 @@
 // a copy of the AGPL-3.0 License along with this software. If not, see
 // <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
 
+use std::collections::BTreeMap;
+
+use amplify::Wrapper;
 use bitcoin::psbt::raw::ProprietaryKey;
-use bitcoin::util::bip32::Fingerprint;
-use wallet::psbt::Psbt;
+use bitcoin::psbt::{self as psbt_v0, PartiallySignedTransaction, PsbtSighashType};
+use bitcoin::secp256k1::SECP256K1;
+use bitcoin::util::bip32::{ChildNumber, DerivationPath, Fingerprint};
+use bitcoin::util::sighash::SchnorrSighashType;
+use bitcoin::util::taproot::TapLeafHash;
+use bitcoin::{EcdsaSighashType, Transaction, TxOut};
+use miniscript::psbt::PsbtExt;
+use miniscript::ToPublicKey;
+use wallet::descriptors::InputDescriptor;
+use wallet::hd::UnhardenedIndex;
+use wallet::locks::{LockTime, SeqNo};
+use wallet::psbt::{construct, Construct, Psbt};
+use wallet::scripts::PubkeyScript;
+
+use crate::model::{DescriptorClass, Prevout, Wallet};
 
 pub const MC_PSBT_GLOBAL_SIGNER_NAME: u8 = 0;
 
+/// Coin type used by the testnet keys in BIP-44 and similar derivation schemes.
+const TESTNET_COIN_TYPE: ChildNumber = ChildNumber::Hardened { index: 1 };
+
+/// Signature hash type requested from the signers. Applies to both ECDSA and Schnorr signatures;
+/// for the taproot inputs `All` is represented by the default sighash type.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[display(doc_comments)]
+pub enum SighashType {
+    /// ALL
+    All,
+    /// NONE
+    None,
+    /// SINGLE
+    Single,
+    /// ALL|ANYONECANPAY
+    AllPlusAnyoneCanPay,
+    /// NONE|ANYONECANPAY
+    NonePlusAnyoneCanPay,
+    /// SINGLE|ANYONECANPAY
+    SinglePlusAnyoneCanPay,
+}
+
+impl Default for SighashType {
+    fn default() -> Self { SighashType::All }
+}
+
+impl SighashType {
+    pub fn all() -> &'static [SighashType] {
+        &[
+            SighashType::All,
+            SighashType::None,
+            SighashType::Single,
+            SighashType::AllPlusAnyoneCanPay,
+            SighashType::NonePlusAnyoneCanPay,
+            SighashType::SinglePlusAnyoneCanPay,
+        ]
+    }
+
+    /// Parses consensus-encoded sighash type. Default sighash type is read as `All` for `taproot`
+    /// inputs and is not valid for the others.
+    pub fn from_consensus(sighash_type: u32, taproot: bool) -> Option<SighashType> {
+        Some(match sighash_type {
+            0x00 if taproot => SighashType::All,
+            0x01 => SighashType::All,
+            0x02 => SighashType::None,
+            0x03 => SighashType::Single,
+            0x81 => SighashType::AllPlusAnyoneCanPay,
+            0x82 => SighashType::NonePlusAnyoneCanPay,
+            0x83 => SighashType::SinglePlusAnyoneCanPay,
+            _ => return None,
+        })
+    }
+
+    /// Whether the signature commits to each output only if the transaction has an output with
+    /// the same index as the signed input.
+    pub fn is_single(self) -> bool {
+        matches!(
+            self,
+            SighashType::Single | SighashType::SinglePlusAnyoneCanPay
+        )
+    }
+
+    pub fn ecdsa(self) -> EcdsaSighashType {
+        match self {
+            SighashType::All => EcdsaSighashType::All,
+            SighashType::None => EcdsaSighashType::None,
+            SighashType::Single => EcdsaSighashType::Single,
+            SighashType::AllPlusAnyoneCanPay => EcdsaSighashType::AllPlusAnyoneCanPay,
+            SighashType::NonePlusAnyoneCanPay => EcdsaSighashType::NonePlusAnyoneCanPay,
+            SighashType::SinglePlusAnyoneCanPay => EcdsaSighashType::SinglePlusAnyoneCanPay,
+        }
+    }
+
+    pub fn schnorr(self) -> SchnorrSighashType {
+        match self {
+            SighashType::All => SchnorrSighashType::Default,
+            SighashType::None => SchnorrSighashType::None,
+            SighashType::Single => SchnorrSighashType::Single,
+            SighashType::AllPlusAnyoneCanPay => SchnorrSighashType::AllPlusAnyoneCanPay,
+            SighashType::NonePlusAnyoneCanPay => SchnorrSighashType::NonePlusAnyoneCanPay,
+            SighashType::SinglePlusAnyoneCanPay => SchnorrSighashType::SinglePlusAnyoneCanPay,
+        }
+    }
+}
+
 pub trait McKeys {
     fn signer_name(&self, master_fp: Fingerprint) -> Option<String>;
 
@@ -47,3 +148,477 @@ impl McKeys for Psbt {
         *entry = name.as_bytes().to_vec();
     }
 }
+
+/// Leaves only the tap leaf at `depth` with a script of `script_len` bytes in the PSBT inputs,
+/// such that signers produce signatures for the chosen spending path only. Inputs where the leaf
+/// can't be identified unambiguously are left untouched.
+pub fn retain_tap_leaf(psbt: &mut Psbt, depth: u8, script_len: usize) {
+    for input in &mut psbt.inputs {
+        let matching = input
+            .tap_scripts
+            .iter()
+            .filter(|(control_block, (script, _))| {
+                control_block.merkle_branch.as_inner().len() == depth as usize
+                    && script.len() == script_len
+            })
+            .map(|(control_block, _)| control_block.clone())
+            .collect::<Vec<_>>();
+        if matching.len() != 1 {
+            continue;
+        }
+        input
+            .tap_scripts
+            .retain(|control_block, _| control_block == &matching[0]);
+        let leaf_hashes = input
+            .tap_scripts
+            .values()
+            .map(|(script, ver)| TapLeafHash::from_script(script, *ver))
+            .collect::<Vec<_>>();
+        for (leaves, _) in input.tap_key_origins.values_mut() {
+            leaves.retain(|leaf| leaf_hashes.contains(leaf));
+        }
+        // Keys which are used only in the removed leaves are not needed anymore; the internal
+        // key is kept
+        input
+            .tap_key_origins
+            .retain(|pk, (leaves, _)| !leaves.is_empty() || input.tap_internal_key == Some(*pk));
+    }
+}
+
+/// Requests signatures with `sighash_type` for all PSBT inputs. The sighash type of the taproot
+/// inputs is left unspecified for `All`, producing default (64-byte) Schnorr signatures.
+pub fn set_sighash_type(psbt: &mut Psbt, sighash_type: SighashType) {
+    for input in &mut psbt.inputs {
+        input.sighash_type = match (input.tap_internal_key, sighash_type) {
+            (Some(_), SighashType::All) => None,
+            (Some(_), _) => Some(PsbtSighashType::from(sighash_type.schnorr())),
+            (None, _) => Some(PsbtSighashType::from(sighash_type.ecdsa())),
+        };
+    }
+}
+
+/// Inputs requesting signatures with sighash types other than `ALL`, with the description of the
+/// requested sighash type. Such signatures do not commit to the whole transaction, allowing
+/// anybody to alter it after signing.
+pub fn non_default_sighashes(psbt: &Psbt) -> BTreeMap<usize, String> {
+    psbt.inputs
+        .iter()
+        .enumerate()
+        .filter_map(|(no, input)| {
+            let sighash_type = input.sighash_type?.to_u32();
+            match SighashType::from_consensus(sighash_type, input.tap_internal_key.is_some()) {
+                Some(SighashType::All) => None,
+                Some(known) => Some((no, known.to_string())),
+                None => Some((no, format!("non-standard {:#04x}", sighash_type))),
+            }
+        })
+        .collect()
+}
+
+/// Signatures which are present in a PSBT and which are required from a single signer.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SignerStatus {
+    pub master_fp: Fingerprint,
+    /// Signer name, if it is known to the PSBT.
+    pub name: Option<String>,
+    pub present: u32,
+    pub required: u32,
+}
+
+/// Lists signers of the PSBT inputs with the number of their signatures. Signers are identified
+/// by the master key fingerprints, which are recovered from the account xpubs if the key origins
+/// use the account fingerprint.
+pub fn signer_status(psbt: &Psbt) -> Vec<SignerStatus> {
+    // Information on required signatures, indexed by terminal keys
+    let mut signing_keys =
+        BTreeMap::<bitcoin::PublicKey, (Fingerprint, Fingerprint, u32, u32)>::new();
+    for input in &psbt.inputs {
+        for (pk, (_, (master_fp, _))) in &input.tap_key_origins {
+            let key = pk.to_public_key();
+            let (fp, _, present, required) =
+                signing_keys.entry(key).or_insert((zero!(), zero!(), 0, 0));
+            *fp = *master_fp;
+            *required += 1;
+            *present += input.tap_key_sig.map(|_| 1u32).unwrap_or_default()
+                + input
+                    .tap_script_sigs
+                    .keys()
+                    .filter(|(xpk, _)| xpk == pk)
+                    .count() as u32;
+        }
+        for (pk, (master_fp, _)) in &input.bip32_derivation {
+            let key = bitcoin::PublicKey::new(*pk);
+            let (fp, _, present, required) =
+                signing_keys.entry(key).or_insert((zero!(), zero!(), 0, 0));
+            *fp = *master_fp;
+            *required += 1;
+            *present += input
+                .partial_sigs
+                .get(&key)
+                .map(|_| 1u32)
+                .unwrap_or_default();
+        }
+    }
+
+    for (account_xpub, (master_fp, _)) in &psbt.xpub {
+        for (fp, account_fp, ..) in signing_keys.values_mut() {
+            if fp == master_fp {
+                *account_fp = account_xpub.fingerprint();
+            } else if *fp == account_xpub.fingerprint() {
+                *account_fp = *fp;
+                *fp = *master_fp;
+            }
+        }
+    }
+
+    signing_keys
+        .into_iter()
+        .fold(
+            BTreeMap::<Fingerprint, (u32, u32)>::new(),
+            |mut signers, (_, (master_fp, _, p, r))| {
+                let (present, required) = signers.entry(master_fp).or_default();
+                *present += p;
+                *required += r;
+                signers
+            },
+        )
+        .into_iter()
+        .map(|(master_fp, (present, required))| SignerStatus {
+            master_fp,
+            name: psbt.signer_name(master_fp),
+            present,
+            required,
+        })
+        .collect()
+}
+
+/// Checks whether PSBT keys are derived with the testnet coin type (`1h`) of BIP-44 and similar
+/// derivation schemes.
+pub fn uses_testnet_keys(psbt: &Psbt) -> bool {
+    let is_testnet =
+        |derivation: &DerivationPath| derivation.len() > 1 && derivation[1] == TESTNET_COIN_TYPE;
+    psbt.inputs.iter().any(|input| {
+        input
+            .tap_key_origins
+            .values()
+            .any(|(_, (_, derivation))| is_testnet(derivation))
+            || input
+                .bip32_derivation
+                .values()
+                .any(|(_, derivation)| is_testnet(derivation))
+    })
+}
+
+/// Finalizes all PSBT inputs and extracts the signed transaction.
+pub fn finalize(psbt: &Psbt) -> Result<Transaction, Vec<miniscript::psbt::Error>> {
+    let mut psbt = PartiallySignedTransaction::from(psbt.clone());
+    psbt.finalize_mut(SECP256K1)?;
+    Ok(psbt.extract_tx())
+}
+
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum ConstructError {
+    /// transaction has no inputs.
+    NoInputs,
+
+    /// inputs of {input} sats are insufficient to pay {output} sats of outputs and fee.
+    Inflation { input: u64, output: u64 },
+
+    /// {0}
+    #[from]
+    Miniscript(miniscript::Error),
+
+    /// {0}
+    #[from]
+    Construct(construct::Error),
+}
+
+impl Wallet {
+    /// Descriptor class of the change output of a transaction spending `prevouts`: the primary
+    /// descriptor class if the transaction spends outputs of that class, or the first of the
+    /// spent classes otherwise. Returns `None` if there are no `prevouts`.
+    pub fn change_class(&self, prevouts: &[Prevout]) -> Option<DescriptorClass> {
+        let primary = self.as_settings().primary_class();
+        match prevouts.iter().map(|prevout| prevout.class).min() {
+            Some(_) if prevouts.iter().any(|prevout| prevout.class == primary) => Some(primary),
+            class => class,
+        }
+    }
+
+    /// Constructs PSBT spending `prevouts` to `outputs` and paying `fee`. Spent outputs may belong
+    /// to different descriptor classes of the wallet. The remaining funds go to the change output
+    /// with `change_index`, which is derived from the [`Wallet::change_class`].
+    ///
+    /// Inputs are grouped by their descriptor class and outputs follow `outputs`, with the change
+    /// output being the last one; BIP-69 ordering is left to the caller.
+    pub fn construct_psbt(
+        &self,
+        lock_time: LockTime,
+        prevouts: &[Prevout],
+        outputs: &[(PubkeyScript, u64)],
+        seq_no: SeqNo,
+        change_index: UnhardenedIndex,
+        fee: u64,
+    ) -> Result<Psbt, ConstructError> {
+        let input = prevouts.iter().map(|prevout| prevout.amount).sum::<u64>();
+        let output = outputs.iter().map(|(_, value)| value).sum::<u64>() + fee;
+        let change = input
+            .checked_sub(output)
+            .ok_or(ConstructError::Inflation { input, output })?;
+
+        let mut groups = BTreeMap::<DescriptorClass, (Vec<InputDescriptor>, u64)>::new();
+        for prevout in prevouts {
+            let (inputs, value) = groups.entry(prevout.class).or_default();
+            inputs.push(InputDescriptor {
+                outpoint: prevout.outpoint,
+                terminal: prevout.terminal(),
+                seq_no,
+                tweak: None,
+                sighash_type: EcdsaSighashType::All,
+            });
+            *value += prevout.amount;
+        }
+        let change_class = self.change_class(prevouts).ok_or(ConstructError::NoInputs)?;
+
+        // The PSBT constructor works with a single descriptor, so inputs of each class are
+        // constructed separately, with their whole value going either to the fee or to the
+        // change output. The latter carries derivation data from the class descriptor and gets
+        // the actual change amount once all inputs are merged.
+        let mut psbt: Option<PartiallySignedTransaction> = None;
+        let mut change_output = None;
+        for (class, (inputs, value)) in groups {
+            let descriptor = self.as_settings().descriptor_for_class(class)?;
+            let group_fee = if class == change_class { 0 } else { value };
+            let mut part = PartiallySignedTransaction::from(Psbt::construct(
+                SECP256K1,
+                &descriptor,
+                lock_time,
+                &inputs,
+                &[],
+                change_index,
+                group_fee,
+                self,
+            )?);
+            if class == change_class {
+                change_output = part.unsigned_tx.output.pop().zip(part.outputs.pop());
+            }
+            part.unsigned_tx.output.clear();
+            part.outputs.clear();
+            match psbt.as_mut() {
+                None => psbt = Some(part),
+                Some(joined) => {
+                    joined.unsigned_tx.input.extend(part.unsigned_tx.input);
+                    joined.inputs.extend(part.inputs);
+                    joined.xpub.extend(part.xpub);
+                }
+            }
+        }
+        let mut psbt = psbt.expect("descriptor class groups are not empty");
+
+        for (script_pubkey, value) in outputs {
+            psbt.unsigned_tx.output.push(TxOut {
+                value: *value,
+                script_pubkey: script_pubkey.clone().into_inner(),
+            });
+            psbt.outputs.push(psbt_v0::Output::default());
+        }
+        if change > 0 {
+            let (txout, output) =
+                change_output.expect("change output is always present for non-zero inputs");
+            psbt.unsigned_tx.output.push(TxOut {
+                value: change,
+                ..txout
+            });
+            psbt.outputs.push(output);
+        }
+
+        Ok(Psbt::from(psbt))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::psbt::PartiallySignedTransaction;
+    use bitcoin::secp256k1::XOnlyPublicKey;
+    use bitcoin::{OutPoint, Script, TxIn};
+    use wallet::hd::SegmentIndexes;
+
+    use super::*;
+    use crate::model::fixtures::{external, settings};
+    use crate::model::{AddressSource, OnchainStatus, OnchainTxid, UtxoTxid};
+    use crate::worker::electrum::TxidMeta;
+
+    /// Wallet with segwit and nested segwit descriptors, which received 100 000 sats to each of
+    /// them in a confirmed transaction. Returns the wallet and its unspent outputs.
+    fn wallet() -> (Wallet, Vec<UtxoTxid>) {
+        let classes = [DescriptorClass::SegwitV0, DescriptorClass::NestedV0];
+        let settings = settings(&classes);
+        let addr_srcs = classes.map(|class| {
+            let index = UnhardenedIndex::zero();
+            let script = settings.script_pubkeys(class, false, 0..=0).unwrap()[&index].clone();
+            AddressSource::with(&script, index, false, class, bitcoin::Network::Bitcoin)
+        });
+        let funding = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn::default()],
+            output: addr_srcs
+                .iter()
+                .map(|addr_src| TxOut {
+                    value: 100_000,
+                    script_pubkey: addr_src.address.script_pubkey().into_inner(),
+                })
+                .collect(),
+        };
+        let onchain = OnchainTxid {
+            txid: funding.txid(),
+            status: OnchainStatus::Blockchain(100),
+            date_time: None,
+        };
+        let utxos = addr_srcs
+            .iter()
+            .zip(0u32..)
+            .map(|(addr_src, vout)| UtxoTxid {
+                onchain,
+                value: 100_000,
+                vout,
+                addr_src: *addr_src,
+            })
+            .collect::<Vec<_>>();
+
+        let mut wallet = Wallet::from(settings);
+        wallet.update_utxos(utxos.iter().copied().collect());
+        let meta = bset![TxidMeta { onchain, fee: None }];
+        wallet.update_complete(
+            &addr_srcs.iter().map(|addr_src| (*addr_src, meta.clone())).collect(),
+            &[funding],
+        );
+        (wallet, utxos)
+    }
+
+    fn change_script(wallet: &Wallet, class: DescriptorClass, index: UnhardenedIndex) -> Script {
+        let no = index.first_index() as u16;
+        wallet.as_settings().script_pubkeys(class, true, no..=no).unwrap()[&index]
+            .clone()
+            .into_inner()
+    }
+
+    fn psbt(inputs: usize) -> Psbt {
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: (0..inputs)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(zero!(), vout as u32),
+                    ..TxIn::default()
+                })
+                .collect(),
+            output: vec![],
+        };
+        Psbt::from(PartiallySignedTransaction::from_unsigned_tx(tx).unwrap())
+    }
+
+    #[test]
+    fn default_sighash_is_taproot_only() {
+        assert_eq!(SighashType::from_consensus(0x00, true), Some(SighashType::All));
+        assert_eq!(SighashType::from_consensus(0x00, false), None);
+        assert_eq!(SighashType::from_consensus(0x01, false), Some(SighashType::All));
+        assert_eq!(SighashType::from_consensus(0x01, true), Some(SighashType::All));
+
+        let internal_key = XOnlyPublicKey::from_str(
+            "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let mut psbt = psbt(3);
+        psbt.inputs[0].tap_internal_key = Some(internal_key);
+        psbt.inputs[0].sighash_type = Some(PsbtSighashType::from_u32(0x00));
+        psbt.inputs[1].sighash_type = Some(PsbtSighashType::from_u32(0x00));
+        psbt.inputs[2].sighash_type = Some(PsbtSighashType::from_u32(0x83));
+        assert_eq!(
+            non_default_sighashes(&psbt),
+            bmap! {
+                1 => s!("non-standard 0x00"),
+                2 => s!("SINGLE|ANYONECANPAY")
+            }
+        );
+    }
+
+    #[test]
+    fn construct_mixed_classes() {
+        let (wallet, utxos) = wallet();
+        let prevouts = utxos.iter().map(Prevout::from).collect::<Vec<_>>();
+        let change_index = UnhardenedIndex::from(5u8);
+        let lock_time = LockTime::with_height(700_000).unwrap();
+        let payment = (PubkeyScript::from(external()), 150_000);
+        let psbt = wallet
+            .construct_psbt(lock_time, &prevouts, &[payment], SeqNo::new_rbf(), change_index, 1_000)
+            .unwrap();
+
+        assert_eq!(psbt.inputs.len(), 2);
+        for utxo in &utxos {
+            let input = psbt
+                .inputs
+                .iter()
+                .find(|input| input.previous_outpoint == utxo.outpoint())
+                .unwrap();
+            let script_pubkey = utxo.addr_src.address.script_pubkey().into_inner();
+            let witness_utxo = input.witness_utxo.as_ref().unwrap();
+            assert_eq!(witness_utxo.script_pubkey, script_pubkey);
+            assert_eq!(witness_utxo.value, utxo.value);
+            assert_eq!(
+                input.non_witness_utxo.as_ref().unwrap().output[utxo.vout as usize],
+                *witness_utxo
+            );
+            match utxo.addr_src.class {
+                DescriptorClass::NestedV0 => {
+                    let redeem_script = input.redeem_script.as_ref().unwrap();
+                    assert_eq!(Script::new_p2sh(&redeem_script.script_hash()), script_pubkey);
+                }
+                _ => assert_eq!(input.redeem_script, None),
+            }
+            // Inputs are derived at the receive path of their own class descriptor
+            let (_, (_, path)) = input.bip32_derivation.iter().next().unwrap();
+            let terminal = path.as_ref().iter().rev().take(2).copied().collect::<Vec<_>>();
+            assert_eq!(terminal, vec![ChildNumber::from(0), ChildNumber::from(0)]);
+            assert_eq!(input.bip32_derivation.len(), 1);
+        }
+
+        // Change goes to the primary (segwit) descriptor class
+        assert_eq!(psbt.outputs.len(), 2);
+        let change = psbt
+            .outputs
+            .iter()
+            .find(|output| output.script != external())
+            .unwrap();
+        assert_eq!(change.amount, 200_000 - 150_000 - 1_000);
+        assert_eq!(change.script, change_script(&wallet, DescriptorClass::SegwitV0, change_index));
+        let (_, (_, path)) = change.bip32_derivation.iter().next().unwrap();
+        let terminal = path.as_ref().iter().rev().take(2).copied().collect::<Vec<_>>();
+        assert_eq!(terminal, vec![ChildNumber::from(5), ChildNumber::from(1)]);
+    }
+
+    #[test]
+    fn construct_secondary_class_change() {
+        let (wallet, utxos) = wallet();
+        let prevouts = utxos
+            .iter()
+            .filter(|utxo| utxo.addr_src.class == DescriptorClass::NestedV0)
+            .map(Prevout::from)
+            .collect::<Vec<_>>();
+        assert_eq!(wallet.change_class(&prevouts), Some(DescriptorClass::NestedV0));
+        let change_index = UnhardenedIndex::zero();
+        let lock_time = LockTime::with_height(700_000).unwrap();
+        let psbt = wallet
+            .construct_psbt(lock_time, &prevouts, &[], SeqNo::new_rbf(), change_index, 1_000)
+            .unwrap();
+
+        assert_eq!(psbt.outputs.len(), 1);
+        let change = &psbt.outputs[0];
+        assert_eq!(change.amount, 99_000);
+        assert_eq!(change.script, change_script(&wallet, DescriptorClass::NestedV0, change_index));
+        assert!(change.redeem_script.is_some());
+    }
+}
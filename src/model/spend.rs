@@ -0,0 +1,506 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime SA, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! Spending wallet funds through a specific spending condition (spending path).
+
+use ::wallet::locks::{LockTime, SeqNo};
+use bitcoin::consensus::encode::VarInt;
+use bitcoin::util::taproot::{TAPROOT_CONTROL_BASE_SIZE, TAPROOT_CONTROL_NODE_SIZE};
+use chrono::{DateTime, Duration, Utc};
+use miniscript::{Descriptor, DescriptorTrait};
+use rand::Rng;
+
+use crate::model::{
+    DescriptorClass, HeaderIndex, SpendingCondition, TimelockReq, TimelockedSigs, UtxoTxid, Wallet,
+    WalletSettings,
+};
+
+/// Median time past, which is used by consensus to check time-based locks (BIP-113), lags
+/// behind the timestamp of the chain tip by approximately this number of blocks.
+const MEDIAN_TIME_PAST_LAG: i64 = 6;
+
+/// Maximal age of the last known block (in seconds) for it to be used as a basis for the
+/// transaction lock time. Matches Bitcoin Core anti-fee-sniping rules.
+pub const MAX_TIP_AGE: i64 = 8 * 60 * 60;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum TipError {
+    /// wallet is not synchronized with the blockchain yet.
+    Unknown,
+
+    /// last known block {0} is too old; please wait for the wallet to synchronize.
+    Stale(u32),
+}
+
+/// Height and timestamp of a block.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct BlockTime {
+    pub height: u32,
+    pub time: DateTime<Utc>,
+}
+
+impl BlockTime {
+    /// Uses exact block time from the index if present, or an estimate otherwise.
+    pub fn with(height: u32, headers: &HeaderIndex) -> BlockTime {
+        BlockTime {
+            height,
+            time: headers.estimate_time(height),
+        }
+    }
+
+    /// Estimated median time past of the block.
+    pub fn median_time_past(self) -> DateTime<Utc> {
+        self.time - Duration::seconds(MEDIAN_TIME_PAST_LAG * 600)
+    }
+}
+
+/// Availability of a spending condition for the wallet funds.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[display(doc_comments)]
+pub enum PathStatus {
+    /// available
+    Available,
+
+    /// {0} of the coins are available
+    Partial(usize),
+
+    /// available after block {0}
+    AfterHeight(u32),
+
+    /// available after {0}
+    AfterDate(DateTime<Utc>),
+
+    /// coins are not mature yet
+    Immature,
+}
+
+impl PathStatus {
+    pub fn is_available(self) -> bool {
+        matches!(self, PathStatus::Available | PathStatus::Partial(_))
+    }
+}
+
+impl TimelockReq {
+    /// Transaction lock time satisfying absolute timelocks.
+    pub fn lock_time(self) -> Option<LockTime> {
+        match self {
+            TimelockReq::AfterDate(date_time) => {
+                LockTime::with_unix_timestamp(date_time.timestamp() as u32)
+            }
+            TimelockReq::AfterHeight(height) => LockTime::with_height(height),
+            _ => None,
+        }
+    }
+
+    /// Input sequence number satisfying relative timelocks.
+    pub fn seq_no(self) -> Option<SeqNo> {
+        match self {
+            TimelockReq::AfterPeriod(duration) => Some(SeqNo::with_time(duration.intervals())),
+            TimelockReq::AfterBlock(blocks) => Some(SeqNo::with_height(blocks)),
+            _ => None,
+        }
+    }
+
+    /// Checks whether output mined in block `mined` (`None` for unconfirmed outputs) can be spent
+    /// with this timelock in the block following the `tip`.
+    pub fn is_satisfied(self, tip: BlockTime, mined: Option<BlockTime>) -> bool {
+        match (self, mined) {
+            (TimelockReq::Anytime, _) => true,
+            (TimelockReq::AfterHeight(height), _) => height <= tip.height,
+            (TimelockReq::AfterDate(date_time), _) => date_time < tip.median_time_past(),
+            (TimelockReq::AfterBlock(_) | TimelockReq::AfterPeriod(_), None) => false,
+            (TimelockReq::AfterBlock(blocks), Some(mined)) => {
+                tip.height + 1 >= mined.height + blocks as u32
+            }
+            // Both times are median time past, so the lag cancels out
+            (TimelockReq::AfterPeriod(duration), Some(mined)) => {
+                tip.time - mined.time >= Duration::seconds(duration.intervals() as i64 * 512)
+            }
+        }
+    }
+}
+
+impl SpendingCondition {
+    pub fn timelock(self) -> TimelockReq {
+        match self {
+            SpendingCondition::Sigs(TimelockedSigs { timelock, .. }) => timelock,
+        }
+    }
+}
+
+impl WalletSettings {
+    /// Spending conditions committed to by the wallet descriptors, in DFS order. Single-signer
+    /// wallets use the signer key directly and have the only spending path.
+    pub fn spending_paths(&self) -> Vec<SpendingCondition> {
+        if self.signers().len() <= 1 {
+            return vec![SpendingCondition::default()];
+        }
+        self.spending_conditions()
+            .iter()
+            .map(|(_, condition)| *condition)
+            .collect()
+    }
+
+    /// Maximal weight of the input witness (or scriptSig) satisfying spending path number `path`.
+    pub fn path_satisfaction_weight(
+        &self,
+        class: DescriptorClass,
+        path: usize,
+    ) -> Result<usize, miniscript::Error> {
+        let descriptor = self.descriptor_for_class(class)?;
+        let max_weight = descriptor.max_satisfaction_weight()?;
+        let paths = self.spending_paths();
+        if paths.len() <= 1 {
+            return Ok(max_weight);
+        }
+
+        // Pre-taproot scripts commit to all the spending paths at once, and the witness (or
+        // scriptSig) satisfying a specific path is bounded only by the whole script satisfaction
+        let tr = match &descriptor {
+            Descriptor::Tr(tr) => tr,
+            _ => return Ok(max_weight),
+        };
+        let (depth, ms) = tr.iter_scripts().nth(path).ok_or_else(|| {
+            miniscript::Error::Unexpected(s!("spending path is absent from the tap tree"))
+        })?;
+        let script_len = ms.script_size();
+        let control_block = TAPROOT_CONTROL_BASE_SIZE + TAPROOT_CONTROL_NODE_SIZE * depth as usize;
+        Ok(1 + ms.max_satisfaction_size()?
+            + VarInt(script_len as u64).len()
+            + script_len
+            + VarInt(control_block as u64).len()
+            + control_block)
+    }
+
+    /// Depth and script length of the tap leaf of spending path number `path`, for the taproot
+    /// descriptor class.
+    pub fn tap_leaf(
+        &self,
+        class: DescriptorClass,
+        path: usize,
+    ) -> Result<Option<(u8, usize)>, miniscript::Error> {
+        if class != DescriptorClass::TaprootC0 || self.signers().len() <= 1 {
+            return Ok(None);
+        }
+        match self.descriptor_for_class(class)? {
+            Descriptor::Tr(tr) => Ok(tr
+                .iter_scripts()
+                .nth(path)
+                .map(|(depth, ms)| (depth, ms.script_size()))),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Wallet {
+    /// Transaction lock time discouraging fee sniping, set to the last known block height as
+    /// Bitcoin Core does: with 10% probability it is moved back by up to 100 blocks, such that
+    /// transactions which were delayed (for instance, by offline signing) are not distinguishable.
+    /// Absolute timelock of the spending path overrides it when it requires a later lock time.
+    ///
+    /// Errors if the last known block is absent or more than [`MAX_TIP_AGE`] old.
+    pub fn lock_time(
+        &self,
+        timelock: TimelockReq,
+        headers: &HeaderIndex,
+    ) -> Result<LockTime, TipError> {
+        let height = self.height();
+        let tip_time = match height {
+            0 => return Err(TipError::Unknown),
+            height => headers.time_at(height).ok_or(TipError::Unknown)?,
+        };
+        if Utc::now() - tip_time > Duration::seconds(MAX_TIP_AGE) {
+            return Err(TipError::Stale(height));
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut lock_height = height;
+        if rng.gen_range(0..10) == 0 {
+            lock_height = lock_height.saturating_sub(rng.gen_range(0..100));
+        }
+        let lock_time =
+            LockTime::with_height(lock_height).expect("block height is always below threshold");
+
+        Ok(match timelock.lock_time() {
+            // Time-based lock can't be combined with a height-based one
+            Some(path_lock) if path_lock.is_time_based() => path_lock,
+            Some(path_lock) if path_lock.as_u32() > lock_time.as_u32() => path_lock,
+            _ => lock_time,
+        })
+    }
+
+    /// Unspent outputs which can be spent through the spending path with the given `timelock` in
+    /// the block following the `tip`.
+    pub fn path_utxos(
+        &self,
+        timelock: TimelockReq,
+        tip: BlockTime,
+        headers: &HeaderIndex,
+    ) -> Vec<UtxoTxid> {
+        self.spendable_utxos()
+            .filter(|utxo| {
+                let mined = utxo.onchain.status.height().map(|height| BlockTime {
+                    height,
+                    time: utxo
+                        .onchain
+                        .date_time
+                        .unwrap_or_else(|| headers.estimate_time(height)),
+                });
+                timelock.is_satisfied(tip, mined)
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Checks availability of the spending path with the given `timelock` for the wallet funds.
+    pub fn path_status(
+        &self,
+        timelock: TimelockReq,
+        tip: BlockTime,
+        headers: &HeaderIndex,
+    ) -> PathStatus {
+        match timelock {
+            TimelockReq::Anytime => PathStatus::Available,
+            TimelockReq::AfterHeight(height) if height > tip.height => {
+                PathStatus::AfterHeight(height)
+            }
+            TimelockReq::AfterDate(date_time) if !timelock.is_satisfied(tip, None) => {
+                PathStatus::AfterDate(date_time)
+            }
+            TimelockReq::AfterHeight(_) | TimelockReq::AfterDate(_) => PathStatus::Available,
+            TimelockReq::AfterBlock(_) | TimelockReq::AfterPeriod(_) => {
+                let total = self.spendable_utxos().count();
+                match self.path_utxos(timelock, tip, headers).len() {
+                    0 => PathStatus::Immature,
+                    count if count == total => PathStatus::Available,
+                    count => PathStatus::Partial(count),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::util::bip32::ExtendedPubKey;
+    use bitcoin::BlockHeader;
+    use chrono::TimeZone;
+    use electrum_client::HeaderNotification;
+    use wallet::hd::TerminalStep;
+
+    use super::*;
+    use crate::model::fixtures::{settings, XPUB};
+    use crate::model::{
+        AddressSource, ElectrumPreset, ElectrumServer, OnchainStatus, OnchainTxid, PublicNetwork,
+        Signer, SigsReq,
+    };
+
+    /// BIP-32 test vector 2 master key.
+    const XPUB2: &str = "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB";
+
+    const TIP: u32 = 740_000;
+
+    fn condition(sigs: SigsReq, timelock: TimelockReq) -> SpendingCondition {
+        SpendingCondition::Sigs(TimelockedSigs { sigs, timelock })
+    }
+
+    /// Two-signer wallet spendable by the first signer at any time and by the second one after
+    /// 144 blocks, ordered as the settings UI orders spending conditions.
+    fn multisig(class: DescriptorClass) -> WalletSettings {
+        let signers = [XPUB, XPUB2].map(|xpub| {
+            let xpub = ExtendedPubKey::from_str(xpub).unwrap();
+            Signer::with_xpub(xpub, &class.bip43(2), PublicNetwork::Mainnet)
+        });
+        let conditions = [
+            (1, condition(SigsReq::Specific(signers[0].master_fp), TimelockReq::Anytime)),
+            (2, condition(SigsReq::Specific(signers[1].master_fp), TimelockReq::AfterBlock(144))),
+        ];
+        WalletSettings::with(
+            signers,
+            conditions,
+            [class],
+            vec![TerminalStep::range(0u8, 1u8), TerminalStep::Wildcard],
+            PublicNetwork::Mainnet,
+            ElectrumServer::tls(ElectrumPreset::Blockstream, &PublicNetwork::Mainnet),
+        )
+        .unwrap()
+    }
+
+    /// Wallet with two coins mined at heights 100 and 200.
+    fn wallet() -> Wallet {
+        let class = DescriptorClass::SegwitV0;
+        let settings = settings(&[class]);
+        let scripts = settings.script_pubkeys(class, false, 0..=1).unwrap();
+        let utxos = scripts.iter().zip([100, 200]).map(|((index, script), height)| UtxoTxid {
+            onchain: OnchainTxid {
+                txid: zero!(),
+                status: OnchainStatus::Blockchain(height),
+                date_time: None,
+            },
+            value: 10_000,
+            vout: height,
+            addr_src: AddressSource::with(script, *index, false, class, bitcoin::Network::Bitcoin),
+        });
+        let mut wallet = Wallet::from(settings);
+        wallet.update_utxos(utxos.collect());
+        wallet
+    }
+
+    /// Wallet synchronized up to the block at `TIP` with the given age in seconds, and the
+    /// header index containing the block.
+    fn synced(age: i64) -> (Wallet, HeaderIndex) {
+        let header = BlockHeader {
+            version: 1,
+            prev_blockhash: zero!(),
+            merkle_root: zero!(),
+            time: (Utc::now().timestamp() - age) as u32,
+            bits: 0,
+            nonce: 0,
+        };
+        let mut wallet = wallet();
+        wallet.update_last_block(&HeaderNotification {
+            height: TIP as usize,
+            header,
+        });
+        let mut headers = HeaderIndex::default();
+        headers.insert(TIP, &header);
+        (wallet, headers)
+    }
+
+    #[test]
+    fn spending_paths() {
+        let single = settings(&[DescriptorClass::SegwitV0]);
+        assert_eq!(single.spending_paths(), vec![SpendingCondition::default()]);
+
+        let multisig = multisig(DescriptorClass::SegwitV0);
+        let timelocks = multisig
+            .spending_paths()
+            .into_iter()
+            .map(SpendingCondition::timelock)
+            .collect::<Vec<_>>();
+        assert_eq!(timelocks, vec![TimelockReq::Anytime, TimelockReq::AfterBlock(144)]);
+    }
+
+    #[test]
+    fn taproot_path_weight() {
+        let settings = multisig(DescriptorClass::TaprootC0);
+        let class = DescriptorClass::TaprootC0;
+        // Leaves are `pk(A)` (34 bytes) and `and_v(v:pk(B),older(144))` (34 + 4 bytes) at depth
+        // 1; miniscript counts signatures as 73 bytes with the length prefix. Witness consists
+        // of the item count, signature, leaf script and control block of 33 + 32 bytes.
+        assert_eq!(settings.tap_leaf(class, 0).unwrap(), Some((1, 34)));
+        assert_eq!(settings.tap_leaf(class, 1).unwrap(), Some((1, 38)));
+        assert_eq!(settings.path_satisfaction_weight(class, 0).unwrap(), 1 + 73 + 1 + 34 + 1 + 65);
+        assert_eq!(settings.path_satisfaction_weight(class, 1).unwrap(), 1 + 73 + 1 + 38 + 1 + 65);
+        assert!(settings.path_satisfaction_weight(class, 2).is_err());
+    }
+
+    #[test]
+    fn pre_taproot_path_weight() {
+        for class in [DescriptorClass::SegwitV0, DescriptorClass::NestedV0] {
+            let settings = multisig(class);
+            let max_weight = settings
+                .descriptor_for_class(class)
+                .unwrap()
+                .max_satisfaction_weight()
+                .unwrap();
+            for path in 0..2 {
+                assert_eq!(settings.path_satisfaction_weight(class, path).unwrap(), max_weight);
+            }
+        }
+    }
+
+    #[test]
+    fn path_status() {
+        let wallet = wallet();
+        let headers = HeaderIndex::default();
+        let tip = BlockTime {
+            height: 300,
+            time: Utc::now(),
+        };
+        let status = |timelock| wallet.path_status(timelock, tip, &headers);
+
+        assert_eq!(status(TimelockReq::Anytime), PathStatus::Available);
+        assert_eq!(status(TimelockReq::AfterHeight(300)), PathStatus::Available);
+        assert_eq!(status(TimelockReq::AfterHeight(301)), PathStatus::AfterHeight(301));
+        let future = Utc::now() + Duration::days(1);
+        assert_eq!(status(TimelockReq::AfterDate(future)), PathStatus::AfterDate(future));
+        // Coins can be spent in the block following the tip
+        assert_eq!(status(TimelockReq::AfterBlock(101)), PathStatus::Available);
+        assert_eq!(status(TimelockReq::AfterBlock(102)), PathStatus::Partial(1));
+        assert_eq!(status(TimelockReq::AfterBlock(202)), PathStatus::Immature);
+        assert!(!status(TimelockReq::AfterBlock(202)).is_available());
+    }
+
+    #[test]
+    fn lock_time_back_off() {
+        let (wallet, headers) = synced(600);
+        let lock_heights = (0..1000)
+            .map(|_| wallet.lock_time(TimelockReq::Anytime, &headers).unwrap())
+            .inspect(|lock_time| assert!(!lock_time.is_time_based()))
+            .map(LockTime::as_u32)
+            .collect::<Vec<_>>();
+        assert!(lock_heights.iter().all(|height| (TIP - 99..=TIP).contains(height)));
+        // Back-off happens in every 10th transaction on average
+        assert!(lock_heights.contains(&TIP));
+        assert!(lock_heights.iter().any(|height| *height < TIP));
+    }
+
+    #[test]
+    fn lock_time_tip() {
+        let (wallet, _) = synced(600);
+        assert_eq!(
+            wallet.lock_time(TimelockReq::Anytime, &HeaderIndex::default()),
+            Err(TipError::Unknown)
+        );
+        assert_eq!(
+            Wallet::from(settings(&[DescriptorClass::SegwitV0]))
+                .lock_time(TimelockReq::Anytime, &HeaderIndex::default()),
+            Err(TipError::Unknown)
+        );
+
+        let (wallet, headers) = synced(MAX_TIP_AGE - 60);
+        assert!(wallet.lock_time(TimelockReq::Anytime, &headers).is_ok());
+        let (wallet, headers) = synced(MAX_TIP_AGE + 60);
+        assert_eq!(
+            wallet.lock_time(TimelockReq::Anytime, &headers),
+            Err(TipError::Stale(TIP))
+        );
+    }
+
+    #[test]
+    fn lock_time_path_override() {
+        let (wallet, headers) = synced(600);
+
+        let later = TimelockReq::AfterHeight(TIP + 10);
+        for _ in 0..100 {
+            assert_eq!(wallet.lock_time(later, &headers).unwrap().as_u32(), TIP + 10);
+        }
+
+        // Path lock below the back-off range does not reduce the anti-fee-sniping lock time
+        let earlier = TimelockReq::AfterHeight(TIP - 200);
+        for _ in 0..100 {
+            let lock_time = wallet.lock_time(earlier, &headers).unwrap().as_u32();
+            assert!((TIP - 99..=TIP).contains(&lock_time));
+        }
+
+        let date_time = Utc.timestamp(1_600_000_000, 0);
+        let by_date = TimelockReq::AfterDate(date_time);
+        for _ in 0..100 {
+            let lock_time = wallet.lock_time(by_date, &headers).unwrap();
+            assert!(lock_time.is_time_based());
+            assert_eq!(lock_time.as_u32(), 1_600_000_000);
+        }
+    }
+}
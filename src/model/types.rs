@@ -13,6 +13,7 @@ use std::cmp::Ordering;
 use std::collections::BTreeMap;
 use std::fmt::{self, Display, Formatter};
 use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
 
 use bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPubKey, Fingerprint};
 use bitcoin::Network;
@@ -20,6 +21,7 @@ use chrono::{DateTime, Utc};
 use hwi::error::Error as HwiError;
 use hwi::HWIDevice;
 use miniscript::descriptor::DescriptorType;
+use strict_encoding::{StrictDecode, StrictEncode};
 use wallet::hd::standards::DerivationBlockchain;
 use wallet::hd::{
     AccountStep, Bip43, DerivationStandard, HardenedIndex, SegmentIndexes, TerminalStep,
@@ -29,8 +31,7 @@ use wallet::hd::{
 use crate::model::XpubkeyCore;
 
 // TODO: Move to descriptor wallet or BPro
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
-#[derive(StrictEncode, StrictDecode)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
@@ -45,6 +46,61 @@ pub enum PublicNetwork {
 
     #[display("signet")]
     Signet,
+
+    #[display("regtest")]
+    Regtest,
+
+    #[display("custom signet")]
+    CustomSignet(CustomSignet),
+}
+
+// Encoded manually, since the derived decoder initializes the custom signet variant with a
+// numbered field
+impl StrictEncode for PublicNetwork {
+    fn strict_encode<E: Write>(&self, mut e: E) -> Result<usize, strict_encoding::Error> {
+        Ok(match self {
+            PublicNetwork::Mainnet => 0u8.strict_encode(e)?,
+            PublicNetwork::Testnet => 1u8.strict_encode(e)?,
+            PublicNetwork::Signet => 2u8.strict_encode(e)?,
+            PublicNetwork::Regtest => 3u8.strict_encode(e)?,
+            PublicNetwork::CustomSignet(signet) => strict_encode_list!(e; 4u8, signet),
+        })
+    }
+}
+
+impl StrictDecode for PublicNetwork {
+    fn strict_decode<D: Read>(mut d: D) -> Result<Self, strict_encoding::Error> {
+        Ok(match u8::strict_decode(&mut d)? {
+            0 => PublicNetwork::Mainnet,
+            1 => PublicNetwork::Testnet,
+            2 => PublicNetwork::Signet,
+            3 => PublicNetwork::Regtest,
+            4 => PublicNetwork::CustomSignet(CustomSignet::strict_decode(d)?),
+            wrong => {
+                return Err(strict_encoding::Error::EnumValueNotKnown(
+                    "PublicNetwork",
+                    wrong as usize,
+                ))
+            }
+        })
+    }
+}
+
+/// Parameters of a signet which is different from the default public one. Since all signets
+/// share the same genesis block and electrum servers do not report the network magic, the signet
+/// is identified by its electrum server only.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct CustomSignet {
+    /// Default electrum server host.
+    pub electrum_server: String,
+    /// Default electrum server port.
+    pub electrum_port: u16,
 }
 
 impl From<PublicNetwork> for Network {
@@ -56,20 +112,20 @@ impl From<&PublicNetwork> for Network {
         match network {
             PublicNetwork::Mainnet => Network::Bitcoin,
             PublicNetwork::Testnet => Network::Testnet,
-            PublicNetwork::Signet => Network::Signet,
+            PublicNetwork::Signet | PublicNetwork::CustomSignet(_) => Network::Signet,
+            PublicNetwork::Regtest => Network::Regtest,
         }
     }
 }
 
-impl TryFrom<Network> for PublicNetwork {
-    type Error = ();
-    fn try_from(network: Network) -> Result<Self, Self::Error> {
-        Ok(match network {
+impl From<Network> for PublicNetwork {
+    fn from(network: Network) -> Self {
+        match network {
             Network::Bitcoin => PublicNetwork::Mainnet,
             Network::Testnet => PublicNetwork::Testnet,
             Network::Signet => PublicNetwork::Signet,
-            Network::Regtest => return Err(()),
-        })
+            Network::Regtest => PublicNetwork::Regtest,
+        }
     }
 }
 
@@ -81,8 +137,10 @@ impl From<&PublicNetwork> for DerivationBlockchain {
     fn from(network: &PublicNetwork) -> Self {
         match network {
             PublicNetwork::Mainnet => DerivationBlockchain::Bitcoin,
-            PublicNetwork::Testnet => DerivationBlockchain::Testnet,
-            PublicNetwork::Signet => DerivationBlockchain::Testnet,
+            PublicNetwork::Testnet
+            | PublicNetwork::Signet
+            | PublicNetwork::Regtest
+            | PublicNetwork::CustomSignet(_) => DerivationBlockchain::Testnet,
         }
     }
 }
@@ -92,15 +150,33 @@ impl Default for PublicNetwork {
 }
 
 impl PublicNetwork {
-    pub fn is_testnet(self) -> bool {
-        matches!(self, PublicNetwork::Testnet | PublicNetwork::Signet)
+    pub fn is_testnet(&self) -> bool { *self != PublicNetwork::Mainnet }
+
+    /// Whether the network is run locally or privately, such that public electrum servers do not
+    /// serve it.
+    pub fn is_local(&self) -> bool {
+        matches!(
+            self,
+            PublicNetwork::Regtest | PublicNetwork::CustomSignet(_)
+        )
     }
 
-    pub fn electrum_port(self) -> u16 {
+    pub fn electrum_port(&self) -> u16 {
         match self {
             PublicNetwork::Mainnet => 50001,
             PublicNetwork::Testnet => 60001,
             PublicNetwork::Signet => 60601,
+            PublicNetwork::Regtest => 60401,
+            PublicNetwork::CustomSignet(signet) => signet.electrum_port,
+        }
+    }
+
+    /// Default electrum server for networks which are not served by the public presets.
+    pub fn electrum_server(&self) -> Option<String> {
+        match self {
+            PublicNetwork::Regtest => Some(s!("localhost")),
+            PublicNetwork::CustomSignet(signet) => Some(signet.electrum_server.clone()),
+            _ => None,
         }
     }
 }
@@ -168,7 +244,10 @@ impl HardwareList {
         for device in HWIDevice::enumerate().map_err(Error::NoDevices)? {
             let fingerprint = Fingerprint::from(&device.fingerprint[..]);
 
-            let derivation = scheme.to_account_derivation(default_account.into(), network.into());
+            let derivation = scheme.to_account_derivation(
+                default_account.into(),
+                DerivationBlockchain::from(&network),
+            );
             let derivation_string = derivation.to_string();
             match device.get_xpub(
                 &derivation_string.parse().expect(
@@ -178,7 +257,7 @@ impl HardwareList {
             ) {
                 Ok(hwikey) => {
                     let xpub = ExtendedPubKey {
-                        network: network.into(),
+                        network: Network::from(&network),
                         depth: hwikey.xpub.depth,
                         parent_fingerprint: hwikey.xpub.parent_fingerprint,
                         child_number: hwikey.xpub.child_number,
@@ -199,7 +278,7 @@ impl HardwareList {
                         device.device_type,
                         device.model,
                         scheme.clone(),
-                        network,
+                        network.clone(),
                         err,
                     ));
                 }
@@ -224,7 +303,7 @@ impl Display for OriginFormat {
             OriginFormat::Master => f.write_str("m/"),
             OriginFormat::SubMaster(account) => Display::fmt(account, f),
             OriginFormat::Standard(scheme, _, network) => {
-                Display::fmt(&scheme.to_origin_derivation((*network).into()), f)
+                Display::fmt(&scheme.to_origin_derivation(network.into()), f)
             }
             OriginFormat::CustomAccount(path) => Display::fmt(path, f),
             OriginFormat::Custom(path) => Display::fmt(path, f),
@@ -423,17 +502,22 @@ impl Signer {
     }
 }
 
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
 #[derive(StrictEncode, StrictDecode)]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
     serde(crate = "serde_crate")
 )]
+#[display(doc_comments)]
 pub enum DescriptorClass {
+    /// Legacy (P2PKH/P2SH)
     PreSegwit,
+    /// Segwit v0 (P2WPKH/P2WSH)
     SegwitV0,
+    /// Nested segwit (P2SH-P2WPKH/P2SH-P2WSH)
     NestedV0,
+    /// Taproot (P2TR)
     TaprootC0,
 }
 
@@ -578,3 +662,31 @@ pub struct TimelockedSigs {
     pub sigs: SigsReq,
     pub timelock: TimelockReq,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn network_encoding() {
+        let custom = PublicNetwork::CustomSignet(CustomSignet {
+            electrum_server: s!("localhost"),
+            electrum_port: 60601,
+        });
+        for (tag, network) in [
+            PublicNetwork::Mainnet,
+            PublicNetwork::Testnet,
+            PublicNetwork::Signet,
+            PublicNetwork::Regtest,
+            custom,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let data = network.strict_serialize().unwrap();
+            assert_eq!(data[0], tag as u8);
+            assert_eq!(PublicNetwork::strict_deserialize(&data).unwrap(), network);
+        }
+        assert!(PublicNetwork::strict_deserialize([5u8]).is_err());
+    }
+}
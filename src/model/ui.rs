@@ -9,10 +9,25 @@
 // a copy of the AGPL-3.0 License along with this software. If not, see
 // <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
 
-use gtk::MessageType;
+//! Presentation-specific parts of the model, which are available with `ui` feature only.
+
+use gtk::{gdk, MessageType};
+
+use crate::model::HistoryEntry;
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct Notification {
     pub msg: String,
     pub ty: MessageType,
 }
+
+impl HistoryEntry {
+    pub fn color(&self) -> gdk::RGBA {
+        match self.balance() {
+            x if x > 0 => gdk::RGBA::new(38.0 / 256.0, 162.0 / 256.0, 105.0 / 256.0, 1.0),
+            x if x < 0 => gdk::RGBA::new(165.0 / 256.0, 29.0 / 256.0, 45.0 / 256.0, 1.0),
+            0 => gdk::RGBA::new(119.0 / 256.0, 118.0 / 256.0, 123.0 / 256.0, 1.0),
+            _ => unreachable!(),
+        }
+    }
+}
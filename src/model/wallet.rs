@@ -12,18 +12,20 @@
 use std::collections::btree_map::Entry;
 use std::collections::{BTreeMap, BTreeSet};
 use std::io::{Read, Write};
+use std::mem;
 use std::ops::{Deref, RangeInclusive};
+use std::str::FromStr;
 
 use amplify::Wrapper;
 use bitcoin::secp256k1::SECP256K1;
-use bitcoin::util::bip32::{ChildNumber, DerivationPath, Fingerprint};
-use bitcoin::{Address, BlockHash, Network, PublicKey, Script, Transaction, TxOut, Txid};
+use bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPubKey, Fingerprint};
+use bitcoin::{Address, BlockHash, Network, OutPoint, PublicKey, Script, Transaction, TxOut, Txid};
 use chrono::{DateTime, Utc};
 use electrum_client::HeaderNotification;
 use miniscript::descriptor::{DescriptorType, Sh, Wsh};
 use miniscript::policy::compiler::CompilerError;
 use miniscript::policy::concrete::{Policy, PolicyError};
-use miniscript::{Descriptor, Legacy, Segwitv0, Tap};
+use miniscript::{Descriptor, ForEachKey, Legacy, Segwitv0, Tap, TranslatePk};
 use strict_encoding::{StrictDecode, StrictEncode};
 use wallet::descriptors::DescrVariants;
 use wallet::hd::standards::DerivationBlockchain;
@@ -42,11 +44,20 @@ use super::{
     DescriptorClass, PublicNetwork, Signer, SigsReq, TimelockReq, TimelockedSigs, ToTapTree,
     Unsatisfiable, XpubkeyCore,
 };
+use crate::model::migrations::{WalletSettingsV0, WalletV1};
 use crate::model::{
-    AddressSource, AddressSummary, AddressValue, ElectrumServer, HistoryEntry, Prevout, UtxoTxid,
+    AddressSource, AddressSummary, AddressValue, CoinSelectParams, CoinSelectStrategy,
+    CoinSelection, CoinSelector, ElectrumServer, HeaderIndex, HistoryEntry, Label, LabelType,
+    Manual, OnchainTxid, UtxoTxid,
 };
 use crate::worker::electrum::TxidMeta;
 
+/// Gap limit used by default, matching BIP-44 recommendation.
+pub const DEFAULT_GAP_LIMIT: u16 = 20;
+
+/// Maximal gap limit supported by the wallet.
+pub const MAX_GAP_LIMIT: u16 = 1000;
+
 // TODO: Move to bpro library
 #[derive(Getters, Clone, Debug)]
 #[derive(StrictEncode, StrictDecode)]
@@ -70,6 +81,10 @@ pub struct Wallet {
     ephemerals: WalletEphemerals,
 
     utxos: BTreeSet<UtxoTxid>,
+    /// Outputs which must not be spent by the automatic coin selection.
+    frozen: BTreeSet<OutPoint>,
+    utxo_labels: BTreeMap<OutPoint, String>,
+    address_labels: BTreeMap<Script, String>,
     history: BTreeSet<HistoryEntry>,
     wip: Vec<Psbt>,
 }
@@ -84,12 +99,44 @@ impl From<WalletSettings> for Wallet {
             state: zero!(),
             ephemerals: zero!(),
             utxos: bset![],
+            frozen: bset![],
+            utxo_labels: bmap![],
+            address_labels: bmap![],
             history: bset![],
             wip: vec![],
         }
     }
 }
 
+impl From<WalletV1> for Wallet {
+    fn from(v1: WalletV1) -> Self {
+        let settings = WalletSettings::from(v1.settings);
+        let class = settings.primary_class();
+        Wallet {
+            settings,
+            last_indexes: v1.last_indexes,
+            last_block: v1.last_block,
+            height: v1.height,
+            state: v1.state,
+            ephemerals: v1.ephemerals,
+            utxos: v1
+                .utxos
+                .into_iter()
+                .map(|utxo| utxo.upgrade(class))
+                .collect(),
+            frozen: bset![],
+            utxo_labels: bmap![],
+            address_labels: bmap![],
+            history: v1
+                .history
+                .into_iter()
+                .map(|entry| entry.upgrade(class))
+                .collect(),
+            wip: v1.wip,
+        }
+    }
+}
+
 impl Wallet {
     pub fn as_settings(&self) -> &WalletSettings { &self.settings }
     pub fn to_settings(&self) -> WalletSettings { self.settings.clone() }
@@ -97,6 +144,13 @@ impl Wallet {
 
     pub fn tx_count(&self) -> usize { self.history.len() }
 
+    pub fn history_txids(&self) -> BTreeSet<Txid> {
+        self.history
+            .iter()
+            .map(|entry| entry.onchain.txid)
+            .collect()
+    }
+
     pub fn next_default_index(&self) -> UnhardenedIndex {
         self.last_indexes
             .get(&UnhardenedIndex::zero())
@@ -118,10 +172,10 @@ impl Wallet {
         prev_index != new_index
     }
 
-    pub fn indexed_address(&self, index: UnhardenedIndex) -> Address {
-        let (descriptor, _) = self
+    pub fn indexed_address(&self, class: DescriptorClass, index: UnhardenedIndex) -> Address {
+        let descriptor = self
             .as_settings()
-            .descriptors_all()
+            .descriptor_for_class(class)
             .expect("invalid wallet descriptor");
         DescriptorExt::<PublicKey>::address(&descriptor, &SECP256K1, &[
             UnhardenedIndex::zero(),
@@ -130,41 +184,219 @@ impl Wallet {
         .expect("unable to derive address for the wallet descriptor")
     }
 
-    pub fn next_address(&self) -> Address { self.indexed_address(self.next_default_index()) }
+    pub fn next_address(&self, class: DescriptorClass) -> Address {
+        self.indexed_address(class, self.next_default_index())
+    }
 
-    // TODO: Implement multiple coinselect algorithms
-    pub fn coinselect(&self, value: u64) -> Option<(BTreeSet<Prevout>, u64)> {
-        let mut prevouts = self.utxos.iter().map(Prevout::from).collect::<Vec<_>>();
-        prevouts.sort_by_key(|p| p.amount);
-        let mut acc = 0u64;
-        let mut take_next = true;
-        let prevouts = prevouts
-            .into_iter()
-            .take_while(|p| {
-                let take_this = take_next;
-                acc += p.amount;
-                take_next = acc < value;
-                take_this
-            })
-            .collect::<Vec<_>>();
-        // Going from back to remove small inputs if larger inputs are enough
-        let mut acc = 0u64;
-        let mut take_next = true;
-        let prevouts = prevouts
+    pub fn is_frozen(&self, outpoint: OutPoint) -> bool { self.frozen.contains(&outpoint) }
+
+    /// Freezes or unfreezes an output. Returns whether the wallet was changed.
+    pub fn set_frozen(&mut self, outpoint: OutPoint, frozen: bool) -> bool {
+        match frozen {
+            true => self.frozen.insert(outpoint),
+            false => self.frozen.remove(&outpoint),
+        }
+    }
+
+    pub fn utxo_label(&self, outpoint: OutPoint) -> Option<&str> {
+        self.utxo_labels.get(&outpoint).map(String::as_str)
+    }
+
+    /// Sets a label for an output; empty label removes it. Returns whether the wallet was
+    /// changed.
+    pub fn set_utxo_label(&mut self, outpoint: OutPoint, label: impl ToString) -> bool {
+        let label = label.to_string();
+        let label = label.trim();
+        if label.is_empty() {
+            return self.utxo_labels.remove(&outpoint).is_some();
+        }
+        self.utxo_labels
+            .insert(outpoint, label.to_owned())
+            .as_deref()
+            != Some(label)
+    }
+
+    pub fn address_label(&self, script_pubkey: &Script) -> Option<&str> {
+        self.address_labels.get(script_pubkey).map(String::as_str)
+    }
+
+    /// Sets a label for an address; empty label removes it. Returns whether the wallet was
+    /// changed.
+    pub fn set_address_label(&mut self, script_pubkey: Script, label: impl ToString) -> bool {
+        let label = label.to_string();
+        let label = label.trim();
+        if label.is_empty() {
+            return self.address_labels.remove(&script_pubkey).is_some();
+        }
+        self.address_labels
+            .insert(script_pubkey, label.to_owned())
+            .as_deref()
+            != Some(label)
+    }
+
+    /// Sets a comment for a transaction from the wallet history; empty comment removes it.
+    /// Returns whether the wallet was changed.
+    pub fn set_tx_comment(&mut self, txid: Txid, comment: impl ToString) -> bool {
+        let comment = comment.to_string();
+        let comment = Some(comment.trim().to_owned()).filter(|c| !c.is_empty());
+        let mut changed = false;
+        self.history = mem::take(&mut self.history)
             .into_iter()
-            .rev()
-            .take_while(|p| {
-                let take_this = take_next;
-                acc += p.amount;
-                take_next = acc < value;
-                take_this
+            .map(|mut entry| {
+                if entry.onchain.txid == txid && entry.comment != comment {
+                    entry.comment = comment.clone();
+                    changed = true;
+                }
+                entry
             })
             .collect();
-        if acc < value {
-            None
-        } else {
-            Some((prevouts, acc))
+        changed
+    }
+
+    /// Exports BIP-329 labels for transactions, addresses, outputs and signer xpubs of the
+    /// wallet. Frozen outputs are exported as non-spendable even if they have no label.
+    pub fn export_labels(&self) -> Vec<Label> {
+        let network = Network::from(self.settings.network());
+        let mut labels = vec![];
+        labels.extend(self.history.iter().filter_map(|entry| {
+            entry
+                .comment
+                .as_ref()
+                .map(|comment| Label::with(LabelType::Tx, entry.onchain.txid, comment))
+        }));
+        labels.extend(
+            self.address_labels
+                .iter()
+                .filter_map(|(script, label)| {
+                    Address::from_script(script, network).map(|address| (address, label))
+                })
+                .map(|(address, label)| Label::with(LabelType::Addr, address, label)),
+        );
+        let outpoints = self
+            .utxo_labels
+            .keys()
+            .chain(&self.frozen)
+            .collect::<BTreeSet<_>>();
+        labels.extend(outpoints.into_iter().map(|outpoint| Label {
+            spendable: Some(!self.frozen.contains(outpoint)),
+            ..Label::with(
+                LabelType::Output,
+                outpoint,
+                self.utxo_label(*outpoint).unwrap_or_default(),
+            )
+        }));
+        labels.extend(
+            self.settings
+                .signers()
+                .iter()
+                .map(|signer| Label::with(LabelType::Xpub, signer.xpub, &signer.name)),
+        );
+        labels
+    }
+
+    /// Imports BIP-329 labels. Labels for unknown transactions, outputs and xpubs, addresses from
+    /// other networks, and label types which have no use in the wallet (inputs and pubkeys) are
+    /// ignored. Returns number of the labels which were applied.
+    pub fn import_labels(&mut self, labels: impl IntoIterator<Item = Label>) -> usize {
+        let network = Network::from(self.settings.network());
+        let mut count = 0usize;
+        for label in labels {
+            let applied = match label.ty {
+                LabelType::Tx => Txid::from_str(&label.reference)
+                    .ok()
+                    .filter(|txid| self.history.iter().any(|e| e.onchain.txid == *txid))
+                    .map(|txid| self.set_tx_comment(txid, &label.label))
+                    .is_some(),
+                LabelType::Addr => Address::from_str(&label.reference)
+                    .ok()
+                    .filter(|address| address.is_valid_for_network(network))
+                    .map(|address| self.set_address_label(address.script_pubkey(), &label.label))
+                    .is_some(),
+                LabelType::Output => OutPoint::from_str(&label.reference)
+                    .ok()
+                    .filter(|outpoint| {
+                        self.utxos.iter().any(|utxo| utxo.outpoint() == *outpoint)
+                            || self.history.iter().any(|entry| {
+                                entry.onchain.txid == outpoint.txid
+                                    && entry.debit.contains_key(&outpoint.vout)
+                            })
+                    })
+                    .map(|outpoint| {
+                        self.set_utxo_label(outpoint, &label.label);
+                        if let Some(spendable) = label.spendable {
+                            self.set_frozen(outpoint, !spendable);
+                        }
+                    })
+                    .is_some(),
+                LabelType::Xpub => ExtendedPubKey::from_str(&label.reference)
+                    .ok()
+                    .and_then(|xpub| {
+                        self.settings
+                            .signers()
+                            .iter()
+                            .find(|signer| signer.xpub == xpub)
+                            .cloned()
+                    })
+                    .filter(|_| !label.label.trim().is_empty())
+                    .map(|mut signer| {
+                        signer.name = label.label.trim().to_owned();
+                        self.settings.update_signer(signer)
+                    })
+                    .is_some(),
+                LabelType::Input | LabelType::Pubkey => false,
+            };
+            if applied {
+                count += 1;
+            }
         }
+        count
+    }
+
+    /// Unspent outputs which are not frozen.
+    pub fn spendable_utxos(&self) -> impl Iterator<Item = &UtxoTxid> {
+        self.utxos
+            .iter()
+            .filter(move |utxo| !self.frozen.contains(&utxo.outpoint()))
+    }
+
+    /// Selects wallet outputs covering `target` out of the `candidates` (which are either
+    /// [`Wallet::spendable_utxos`] or [`Wallet::path_utxos`]) with the given strategy. If
+    /// branch-and-bound is unable to find a changeless solution, falls back to the knapsack
+    /// solver; the strategy which was actually used is reported by [`CoinSelection::strategy`].
+    pub fn coinselect(
+        &self,
+        strategy: CoinSelectStrategy,
+        candidates: &[UtxoTxid],
+        target: u64,
+        params: &CoinSelectParams,
+    ) -> Option<CoinSelection> {
+        strategy
+            .selector()
+            .select(candidates, target, params)
+            .or_else(|| match strategy {
+                CoinSelectStrategy::BranchAndBound => CoinSelectStrategy::Knapsack
+                    .selector()
+                    .select(candidates, target, params),
+                _ => None,
+            })
+    }
+
+    /// Spends exactly the hand-picked outputs, provided they cover `target`. Outputs which are
+    /// not among the `candidates` (frozen, unknown or not spendable through the selected
+    /// spending condition) are ignored.
+    pub fn coinselect_manual(
+        &self,
+        candidates: &[UtxoTxid],
+        outpoints: &BTreeSet<OutPoint>,
+        target: u64,
+        params: &CoinSelectParams,
+    ) -> Option<CoinSelection> {
+        let candidates = candidates
+            .iter()
+            .filter(|utxo| outpoints.contains(&utxo.outpoint()))
+            .copied()
+            .collect::<Vec<_>>();
+        Manual.select(&candidates, target, params)
     }
 
     pub fn address_info(&self) -> Vec<AddressSummary> {
@@ -235,18 +467,18 @@ impl Wallet {
 
     pub fn update_utxos(&mut self, batch: BTreeSet<UtxoTxid>) { self.utxos.extend(batch); }
 
+    /// Updates wallet history with the data from the electrum server. `addr_buffer` must contain
+    /// the complete history of the wallet addresses, while `tx_buffer` may contain only
+    /// transactions which are not yet known to the wallet history. Comments and other data added
+    /// to the existing history entries by the user are preserved.
     pub fn update_complete(
         &mut self,
         addr_buffer: &BTreeMap<AddressSource, BTreeSet<TxidMeta>>,
         tx_buffer: &[Transaction],
     ) {
-        // TODO: Remove this call and do a "smart" history update operation
-        self.history = bset![];
-        self.state.volume = 0;
         self.state.balance = self.utxos.iter().map(|utxo| utxo.value).sum::<u64>();
 
         // 0. Check last used addresses
-        self.last_indexes = zero!();
         for (addr_src, set) in addr_buffer {
             if set.is_empty() {
                 continue;
@@ -258,9 +490,15 @@ impl Wallet {
             *idx = *idx.deref().max(&addr_src.index);
         }
 
-        // 1. Build reverse index
-        let txid2tx = tx_buffer
-            .iter()
+        // 1. Build reverse index, reusing transactions from the existing history
+        let known = mem::take(&mut self.history)
+            .into_iter()
+            .map(|entry| (entry.onchain.txid, entry))
+            .collect::<BTreeMap<_, _>>();
+        let txid2tx = known
+            .values()
+            .map(|entry| &entry.tx)
+            .chain(tx_buffer)
             .map(|tx| (tx.txid(), tx))
             .collect::<BTreeMap<_, _>>();
         let txid2meta = addr_buffer
@@ -282,8 +520,15 @@ impl Wallet {
                 .map(|addr| (no as u32, addr))
         };
 
-        // 2. Create one history entry per transaction
-        for tx in tx_buffer {
+        // 2. Create one history entry per transaction; transactions which are not present in the
+        //    address history anymore (like replaced mempool transactions) are dropped
+        self.state.volume = 0;
+        for (txid, meta) in txid2meta {
+            let tx = match txid2tx.get(&txid) {
+                Some(tx) => *tx,
+                None => continue,
+            };
+
             let debit = tx
                 .output
                 .iter()
@@ -305,25 +550,63 @@ impl Wallet {
                 .filter_map(txout2addr)
                 .collect();
 
-            let meta = txid2meta[&tx.txid()];
+            let prev = known.get(&txid);
             let entry = HistoryEntry {
                 onchain: meta.onchain,
                 tx: tx.clone(),
                 credit,
                 debit,
-                payers: empty!(),
-                beneficiaries: empty!(),
-                fee: meta.fee,
-                comment: None,
+                payers: prev.map(|entry| entry.payers.clone()).unwrap_or_default(),
+                beneficiaries: prev
+                    .map(|entry| entry.beneficiaries.clone())
+                    .unwrap_or_default(),
+                fee: meta.fee.or_else(|| prev.and_then(|entry| entry.fee)),
+                comment: prev.and_then(|entry| entry.comment.clone()),
             };
             self.state.volume += entry.value_credited();
             self.history.insert(entry);
         }
     }
 
+    /// Sets exact mining time of the wallet transactions and UTXOs from the block header index.
+    pub fn update_timestamps(&mut self, headers: &HeaderIndex) {
+        let date_time = |onchain: &OnchainTxid| {
+            onchain
+                .status
+                .height()
+                .and_then(|height| headers.time_at(height))
+        };
+        self.history = mem::take(&mut self.history)
+            .into_iter()
+            .map(|mut entry| {
+                entry.onchain.date_time = date_time(&entry.onchain);
+                entry
+            })
+            .collect();
+        self.utxos = mem::take(&mut self.utxos)
+            .into_iter()
+            .map(|mut utxo| {
+                utxo.onchain.date_time = date_time(&utxo.onchain);
+                utxo
+            })
+            .collect();
+    }
+
     pub fn update_electrum(&mut self, electrum: ElectrumServer) -> bool {
         self.settings.update_electrum(electrum)
     }
+
+    pub fn update_gap_limit(&mut self, gap_limit: u16) -> bool {
+        self.settings.update_gap_limit(gap_limit)
+    }
+
+    /// Maximal address index which may be given out without risking funds sent to it to be
+    /// missed by the address discovery.
+    pub fn max_safe_index(&self) -> UnhardenedIndex {
+        let next = self.next_default_index().first_index();
+        let gap_limit = self.settings.gap_limit().max(1) as u32;
+        UnhardenedIndex::from_index(next + gap_limit - 1).unwrap_or(UnhardenedIndex::largest())
+    }
 }
 
 impl ResolveTx for Wallet {
@@ -365,11 +648,37 @@ pub enum DescriptorError {
     serde(crate = "serde_crate")
 )]
 pub struct WalletSettings {
-    #[getter(as_copy)]
     network: PublicNetwork,
     core: WalletDescriptor,
     signers: Vec<Signer>,
     electrum: ElectrumServer,
+    /// Number of consecutive unused addresses after which address discovery stops.
+    #[getter(as_copy)]
+    gap_limit: u16,
+}
+
+impl From<WalletSettingsV0> for WalletSettings {
+    fn from(v0: WalletSettingsV0) -> Self {
+        WalletSettings {
+            network: v0.network,
+            core: v0.core,
+            signers: v0.signers,
+            electrum: v0.electrum,
+            gap_limit: DEFAULT_GAP_LIMIT,
+        }
+    }
+}
+
+/// Produces settings in the layout of the previous format versions, dropping the gap limit.
+impl From<WalletSettings> for WalletSettingsV0 {
+    fn from(settings: WalletSettings) -> Self {
+        WalletSettingsV0 {
+            network: settings.network,
+            core: settings.core,
+            signers: settings.signers,
+            electrum: settings.electrum,
+        }
+    }
 }
 
 impl Deref for WalletSettings {
@@ -426,12 +735,14 @@ impl WalletSettings {
         network: PublicNetwork,
         electrum: ElectrumServer,
     ) -> Result<WalletSettings, DescriptorError> {
+        let testnet = network.is_testnet();
         let mut descriptor = WalletSettings {
             signers: empty!(),
             network,
             electrum,
+            gap_limit: DEFAULT_GAP_LIMIT,
             core: WalletDescriptor {
-                testnet: network.is_testnet(),
+                testnet,
                 descriptor_classes: empty!(),
                 terminal,
                 signing_keys: empty!(),
@@ -545,6 +856,16 @@ impl WalletSettings {
         }
     }
 
+    pub fn update_gap_limit(&mut self, gap_limit: u16) -> bool {
+        let gap_limit = gap_limit.clamp(1, MAX_GAP_LIMIT);
+        if self.gap_limit != gap_limit {
+            self.gap_limit = gap_limit;
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn update_electrum(&mut self, electrum: ElectrumServer) -> bool {
         if self.electrum != electrum {
             self.electrum = electrum;
@@ -575,6 +896,60 @@ impl WalletSettings {
         ))
     }
 
+    /// BIP-388 wallet policy for the descriptor class. Returns descriptor template, where keys
+    /// are replaced with `@N/**` placeholders numbered in the order of their first appearance,
+    /// and the list of keys with their origins which the placeholders refer to.
+    pub fn wallet_policy(
+        &self,
+        class: DescriptorClass,
+    ) -> Result<(String, Vec<String>), miniscript::Error> {
+        let descriptor = self.descriptor_for_class(class)?;
+        let mut xpubs = Vec::<ExtendedPubKey>::new();
+        descriptor.for_each_key(|key| {
+            let xpub = key.as_key().account_xpub;
+            if !xpubs.contains(&xpub) {
+                xpubs.push(xpub);
+            }
+            true
+        });
+
+        let placeholder = |account: &TrackingAccount| {
+            xpubs
+                .iter()
+                .position(|xpub| *xpub == account.account_xpub)
+                .map(|no| format!("@{}/**", no))
+                .ok_or_else(|| miniscript::Error::Unexpected(s!("unknown descriptor key")))
+        };
+        let template = descriptor
+            .translate_pk(placeholder, placeholder)?
+            .to_string();
+        // Wallet policy templates are not checksummed
+        let template = template
+            .split('#')
+            .next()
+            .expect("split always returns at least one item")
+            .to_owned();
+
+        let keys = xpubs
+            .into_iter()
+            .map(|xpub| {
+                let signer = self
+                    .signers
+                    .iter()
+                    .find(|signer| signer.xpub == xpub)
+                    .expect("descriptor keys are constructed from the wallet signers");
+                format!(
+                    "[{}{}]{}",
+                    signer.master_fp,
+                    signer.origin.to_string().trim_start_matches('m'),
+                    xpub
+                )
+            })
+            .collect();
+
+        Ok((template, keys))
+    }
+
     pub fn descriptor_for_class(
         &self,
         class: DescriptorClass,
@@ -597,16 +972,7 @@ impl WalletSettings {
         }
 
         // 1. Construct accounts
-        let accounts: BTreeMap<Fingerprint, TrackingAccount> = self
-            .signers
-            .iter()
-            .map(|signer| {
-                (
-                    signer.fingerprint(),
-                    signer.to_tracking_account(self.terminal.clone()),
-                )
-            })
-            .collect();
+        let accounts = self.tracking_accounts();
 
         // 2. Construct policy fragments
         let mut dfs_tree = self
@@ -625,7 +991,7 @@ impl WalletSettings {
             )?;
 
             return Descriptor::new_tr(
-                TrackingAccount::unsatisfiable((self.network, self.terminal.clone())),
+                TrackingAccount::unsatisfiable((self.network.clone(), self.terminal.clone())),
                 Some(tree.to_tap_tree()?),
             );
         }
@@ -709,12 +1075,34 @@ impl WalletSettings {
         Ok(Descriptor::Sh(Sh::new(ms)?))
     }
 
+    pub(super) fn tracking_accounts(&self) -> BTreeMap<Fingerprint, TrackingAccount> {
+        self.signers
+            .iter()
+            .map(|signer| {
+                (
+                    signer.fingerprint(),
+                    signer.to_tracking_account(self.terminal.clone()),
+                )
+            })
+            .collect()
+    }
+
+    /// Descriptor class used for the change outputs and, by default, for the new addresses.
+    pub fn primary_class(&self) -> DescriptorClass {
+        *self
+            .descriptor_classes
+            .iter()
+            .next()
+            .expect("wallet core without descriptor class")
+    }
+
     pub fn script_pubkeys(
         &self,
+        class: DescriptorClass,
         change: bool,
         range: RangeInclusive<u16>,
     ) -> Result<BTreeMap<UnhardenedIndex, PubkeyScript>, miniscript::Error> {
-        let (descriptor, _) = self.descriptors_all()?;
+        let descriptor = self.descriptor_for_class(class)?;
         let len = DescriptorExt::<PublicKey>::derive_pattern_len(&descriptor)
             .expect("internal inconsistency in wallet descriptor");
         debug_assert!(len >= 2);
@@ -742,15 +1130,16 @@ impl WalletSettings {
 
     pub fn addresses(
         &self,
+        class: DescriptorClass,
         change: bool,
         range: RangeInclusive<u16>,
     ) -> Result<BTreeMap<UnhardenedIndex, AddressCompat>, miniscript::Error> {
-        self.script_pubkeys(change, range)?
+        self.script_pubkeys(class, change, range)?
             .into_iter()
             .map(|(index, spk)| -> Result<_, _> {
                 Ok((
                     index,
-                    AddressCompat::from_script(&spk, self.network.into()).ok_or(
+                    AddressCompat::from_script(&spk, Network::from(&self.network)).ok_or(
                         miniscript::Error::BadDescriptor(s!("address can't be generated")),
                     )?,
                 ))
@@ -1099,3 +1488,112 @@ impl StrictDecode for WalletEphemerals {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bitcoin::TxIn;
+
+    use super::*;
+    use crate::model::fixtures::{external, network_settings, settings};
+    use crate::model::OnchainStatus;
+
+    fn label(ty: LabelType, reference: String, label: &str, spendable: Option<bool>) -> Label {
+        Label {
+            ty,
+            reference,
+            label: label.to_owned(),
+            origin: None,
+            spendable,
+        }
+    }
+
+    #[test]
+    fn output_labels_require_wallet_outputs() {
+        let class = DescriptorClass::SegwitV0;
+        let settings = settings(&[class]);
+        let index = UnhardenedIndex::zero();
+        let script = settings.script_pubkeys(class, false, 0..=0).unwrap()[&index].clone();
+        let addr_src = AddressSource::with(&script, index, false, class, Network::Bitcoin);
+
+        // Pays the wallet twice; the second output is already spent
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn::default()],
+            output: vec![
+                TxOut {
+                    value: 10_000,
+                    script_pubkey: script.clone().into_inner(),
+                },
+                TxOut {
+                    value: 20_000,
+                    script_pubkey: external(),
+                },
+                TxOut {
+                    value: 30_000,
+                    script_pubkey: script.into_inner(),
+                },
+            ],
+        };
+        let txid = tx.txid();
+        let onchain = OnchainTxid {
+            txid,
+            status: OnchainStatus::Blockchain(100),
+            date_time: None,
+        };
+        let mut wallet = Wallet::from(settings);
+        wallet.update_utxos(bset![UtxoTxid {
+            onchain,
+            value: 10_000,
+            vout: 0,
+            addr_src,
+        }]);
+        wallet.update_complete(
+            &bmap! { addr_src => bset![TxidMeta { onchain, fee: None }] },
+            &[tx],
+        );
+
+        let unspent = OutPoint::new(txid, 0);
+        let foreign = OutPoint::new(txid, 1);
+        let spent = OutPoint::new(txid, 2);
+        let unknown = OutPoint::new(zero!(), 0);
+        let count = wallet.import_labels([
+            label(LabelType::Output, unspent.to_string(), "unspent", Some(false)),
+            label(LabelType::Output, foreign.to_string(), "foreign", Some(false)),
+            label(LabelType::Output, spent.to_string(), "spent", None),
+            label(LabelType::Output, unknown.to_string(), "unknown", Some(false)),
+        ]);
+        assert_eq!(count, 2);
+        assert_eq!(wallet.utxo_label(unspent), Some("unspent"));
+        assert!(wallet.is_frozen(unspent));
+        assert_eq!(wallet.utxo_label(spent), Some("spent"));
+        assert_eq!(wallet.utxo_label(foreign), None);
+        assert!(!wallet.is_frozen(foreign));
+        assert_eq!(wallet.utxo_label(unknown), None);
+        assert!(!wallet.is_frozen(unknown));
+    }
+
+    #[test]
+    fn address_labels_match_network() {
+        let class = DescriptorClass::SegwitV0;
+        for network in [PublicNetwork::Mainnet, PublicNetwork::Testnet, PublicNetwork::Signet] {
+            let settings = network_settings(network.clone(), &[class]);
+            let index = UnhardenedIndex::zero();
+            let script = settings.script_pubkeys(class, false, 0..=0).unwrap()[&index].clone();
+            let own = AddressSource::with(&script, index, false, class, network.clone().into());
+            let foreign = match network {
+                PublicNetwork::Mainnet => Network::Testnet,
+                _ => Network::Bitcoin,
+            };
+            let foreign = AddressSource::with(&script, index, false, class, foreign);
+
+            let mut wallet = Wallet::from(settings);
+            let count = wallet.import_labels([
+                label(LabelType::Addr, own.address.to_string(), "own", None),
+                label(LabelType::Addr, foreign.address.to_string(), "foreign", None),
+            ]);
+            assert_eq!(count, 1, "{}", network);
+            assert_eq!(wallet.address_label(&script), Some("own"));
+        }
+    }
+}
@@ -195,7 +195,11 @@ where
             .as_ref()
             .and_then(KeyVersion::network::<DefaultResolver>)
         {
-            Some(slip_network) if slip_network != xpub.network => {
+            // SLIP-132 distinguishes only mainnet from test networks
+            Some(slip_network)
+                if (slip_network == bitcoin::Network::Bitcoin)
+                    != (xpub.network == bitcoin::Network::Bitcoin) =>
+            {
                 return Err(XpubRequirementError::NetworkMismatch {
                     slip_network,
                     bip_network: xpub.network,
@@ -293,7 +297,9 @@ where
                     .transpose()
                     .map_err(|err| NonStandardDerivation::UnhardenedCoinType(err.0))?
                 {
-                    if standard_network != network {
+                    if (standard_network == bitcoin::Network::Bitcoin)
+                        != (network == bitcoin::Network::Bitcoin)
+                    {
                         return Ok(Err(XpubRequirementError::NetworkMismatch {
                             slip_network: network,
                             bip_network: standard_network,
@@ -108,7 +108,7 @@ impl Update for Component {
                 model.set_property("updating", true);
                 let derivation = self.model.scheme.to_account_derivation(
                     ChildNumber::from_hardened_idx(account).expect("wrong account number"),
-                    self.model.network.into(),
+                    (&self.model.network).into(),
                 );
                 let testnet = self.model.network.is_testnet();
                 let sender = self.sender.clone();
@@ -183,6 +183,7 @@ impl Widget for Component {
             stream.emit(msg);
         });
         let scheme = model.scheme.clone();
+        let network = model.network.clone();
         let sender2 = sender.clone();
         widgets.refresh_btn.connect_clicked(move |_| {
             sender2
@@ -190,10 +191,11 @@ impl Widget for Component {
                 .expect("broken channel in devices dialog");
             // TODO: This fixes the schema used in the wallet once and forever
             let scheme = scheme.clone();
+            let network = network.clone();
             let sender = sender2.clone();
             // TODO: move enumeration into Refresh event processing
             std::thread::spawn(move || {
-                let result = HardwareList::enumerate(&scheme, model.network, HardenedIndex::zero());
+                let result = HardwareList::enumerate(&scheme, network, HardenedIndex::zero());
                 sender
                     .send(Msg::Devices(result))
                     .expect("broken channel in devices dialog");
@@ -11,7 +11,7 @@
 
 use std::ffi::OsStr;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use ::wallet::psbt::Psbt;
 use bitcoin::consensus::Decodable;
@@ -21,9 +21,12 @@ use gtk::{ApplicationWindow, ResponseType};
 use relm::{init, Relm, StreamHandle, Update, Widget};
 
 use super::{Msg, ViewModel, Widgets};
-use crate::model::{FileDocument, PublicNetwork, Wallet};
+use crate::model::{file, FileDocument, PublicNetwork, Wallet};
 use crate::view::launch::Page;
-use crate::view::{about, error_dlg, file_create_dlg, file_open_dlg, psbt, settings, wallet};
+use crate::view::{
+    about, custom_signet_dlg, error_dlg, file_create_dlg, file_open_dlg, passphrase_dlg, psbt,
+    question_dlg, settings, wallet,
+};
 
 /// Main [`relm`] component of the application
 ///
@@ -51,23 +54,84 @@ impl Component {
     }
 
     fn open_wallet(&mut self, path: PathBuf) -> bool {
-        match Wallet::read_file(&path) {
+        let mut passphrase = None;
+        let result = loop {
+            let details = match Wallet::read_file_with(&path, passphrase.as_deref()) {
+                Err(file::Error::PassphraseRequired) => None,
+                // A damaged encrypted file fails authentication just like a wrong passphrase
+                // does; if a backup opens with the same passphrase, the file is damaged
+                Err(file::Error::Decryption)
+                    if Wallet::backups(&path).iter().any(|backup| {
+                        Wallet::read_file_with(backup, passphrase.as_deref()).is_ok()
+                    }) =>
+                {
+                    break Err(file::Error::Decryption)
+                }
+                Err(file::Error::Decryption) => Some("Wrong passphrase, please try again."),
+                result => break result,
+            };
+            passphrase = match passphrase_dlg(
+                self.widgets.as_root(),
+                "Encrypted wallet",
+                &format!("Enter passphrase to open {}", path.display()),
+                details,
+            ) {
+                None => return false,
+                some => some,
+            };
+        };
+        let wallet = match result {
+            Ok(wallet) => wallet,
+            Err(err) => match self.recover_wallet(&path, passphrase.as_deref(), err) {
+                Some(wallet) => wallet,
+                None => return false,
+            },
+        };
+        let wallet = init::<wallet::Component>((wallet, path, passphrase))
+            .expect("unable to instantiate wallet settings");
+        self.window_count += 1;
+        wallet.emit(wallet::Msg::RegisterLauncher(self.stream.clone()));
+        self.wallets.push(wallet);
+        true
+    }
+
+    fn recover_wallet(
+        &self,
+        path: &Path,
+        passphrase: Option<&str>,
+        err: file::Error,
+    ) -> Option<Wallet> {
+        let message = format!("Unable to open wallet file {}", path.display());
+        if Wallet::backups(path).is_empty()
+            || !question_dlg(
+                self.widgets.as_root(),
+                "Error opening wallet",
+                &message,
+                Some(&format!(
+                    "{}.\n\nThe wallet file may be damaged. Do you want to restore it from the \
+                     latest good backup copy?",
+                    err
+                )),
+            )
+        {
+            error_dlg(
+                self.widgets.as_root(),
+                "Error opening wallet",
+                &message,
+                Some(&err.to_string()),
+            );
+            return None;
+        }
+        match Wallet::restore_backup(path, passphrase) {
+            Ok(wallet) => Some(wallet),
             Err(err) => {
                 error_dlg(
                     self.widgets.as_root(),
-                    "Error opening wallet",
-                    &format!("Unable to open wallet file {}", path.display()),
+                    "Error restoring wallet",
+                    "Unable to restore wallet from the backup copies",
                     Some(&err.to_string()),
                 );
-                false
-            }
-            Ok(wallet) => {
-                let wallet = init::<wallet::Component>((wallet, path))
-                    .expect("unable to instantiate wallet settings");
-                self.window_count += 1;
-                wallet.emit(wallet::Msg::RegisterLauncher(self.stream.clone()));
-                self.wallets.push(wallet);
-                true
+                None
             }
         }
     }
@@ -155,6 +219,13 @@ impl Update for Component {
                 // TODO: Remove PSBT window from the list of windows
             }
             Msg::Template(index) => {
+                let network = match self.widgets.network() {
+                    Some(network) => network,
+                    None => match custom_signet_dlg(self.widgets.as_root()) {
+                        Some(signet) => PublicNetwork::CustomSignet(signet),
+                        None => return,
+                    },
+                };
                 if let Some(path) = file_create_dlg(
                     Some(self.widgets.as_root()),
                     "Create wallet",
@@ -162,7 +233,7 @@ impl Update for Component {
                     "*.mcw",
                     &Wallet::file_name("citadel", self.wallet_count),
                 ) {
-                    let template = self.widgets.template(index);
+                    let template = self.widgets.template(index, network);
                     self.wallet_count += 1;
                     self.widgets.hide();
                     self.wallet_settings
@@ -16,7 +16,8 @@ use gladis::Gladis;
 use gtk::gdk_pixbuf::Pixbuf;
 use gtk::prelude::*;
 use gtk::{
-    Adjustment, ApplicationWindow, Button, Image, ListBox, Notebook, RecentChooserWidget, Switch,
+    Adjustment, ApplicationWindow, Button, ComboBoxText, Image, ListBox, Notebook,
+    RecentChooserWidget, Switch,
 };
 use relm::Relm;
 
@@ -32,7 +33,7 @@ pub struct Widgets {
     pages: Notebook,
     hwcount_adj: Adjustment,
     taproot_swch: Switch,
-    testnet_swch: Switch,
+    network_cmb: ComboBoxText,
     rgb_swch: Switch,
     create_box: ListBox,
     import_box: ListBox,
@@ -63,20 +64,24 @@ impl Widgets {
 
     fn is_taproot(&self) -> bool { self.taproot_swch.is_active() }
 
-    fn network(&self) -> PublicNetwork {
-        match self.testnet_swch.is_active() {
-            true => PublicNetwork::Testnet,
-            false => PublicNetwork::Mainnet,
+    /// Returns selected network, or `None` if a custom signet was selected, whose parameters have
+    /// to be provided by the user.
+    pub fn network(&self) -> Option<PublicNetwork> {
+        match self.network_cmb.active_id().as_deref() {
+            Some("testnet") => Some(PublicNetwork::Testnet),
+            Some("signet") => Some(PublicNetwork::Signet),
+            Some("regtest") => Some(PublicNetwork::Regtest),
+            Some("custom") => None,
+            _ => Some(PublicNetwork::Mainnet),
         }
     }
 
-    pub fn template(&self, index: i32) -> WalletTemplate {
+    pub fn template(&self, index: i32, network: PublicNetwork) -> WalletTemplate {
         let class = if self.is_taproot() {
             DescriptorClass::TaprootC0
         } else {
             DescriptorClass::SegwitV0
         };
-        let network = self.network();
         match index {
             0 => WalletTemplate::singlesig(class, network, false),
             1 => WalletTemplate::singlesig(class, network, true),
@@ -27,6 +27,8 @@ use gtk::{
     MessageType, ResponseType,
 };
 
+use crate::model::{CustomSignet, PublicNetwork};
+
 pub trait NotificationBoxExt {
     fn notification_box(&self) -> &gtk::Box;
     fn main_dialog(&self) -> &gtk::Dialog;
@@ -100,6 +102,110 @@ pub fn error_dlg(
     msg_dlg(parent, MessageType::Error, title, message, details);
 }
 
+/// Asks the user a yes/no question; returns `true` if the user has agreed.
+pub fn question_dlg(
+    parent: &impl IsA<gtk::Window>,
+    title: &str,
+    message: &str,
+    details: Option<&str>,
+) -> bool {
+    let dlg = MessageDialog::new(
+        Some(parent),
+        DialogFlags::all(),
+        MessageType::Question,
+        ButtonsType::YesNo,
+        message,
+    );
+    dlg.set_title(title);
+    dlg.set_secondary_text(details);
+    let resp = dlg.run();
+    dlg.close();
+    resp == ResponseType::Yes
+}
+
+/// Asks the user for a passphrase; returns `None` if the dialog was cancelled.
+pub fn passphrase_dlg(
+    parent: &impl IsA<gtk::Window>,
+    title: &str,
+    message: &str,
+    details: Option<&str>,
+) -> Option<String> {
+    let dlg = MessageDialog::new(
+        Some(parent),
+        DialogFlags::all(),
+        MessageType::Question,
+        ButtonsType::OkCancel,
+        message,
+    );
+    dlg.set_title(title);
+    dlg.set_secondary_text(details);
+    dlg.set_default_response(ResponseType::Ok);
+
+    let entry = gtk::Entry::new();
+    entry.set_visibility(false);
+    entry.set_activates_default(true);
+    if let Ok(area) = dlg.message_area().downcast::<gtk::Box>() {
+        area.pack_end(&entry, false, false, 0);
+    }
+    entry.show();
+
+    let resp = dlg.run();
+    let passphrase = entry.text().to_string();
+    dlg.close();
+    if resp != ResponseType::Ok {
+        return None;
+    }
+    Some(passphrase)
+}
+
+/// Asks the user for the parameters of a custom signet; returns `None` if the dialog was
+/// cancelled or no server was provided.
+pub fn custom_signet_dlg(parent: &impl IsA<gtk::Window>) -> Option<CustomSignet> {
+    let dlg = MessageDialog::new(
+        Some(parent),
+        DialogFlags::all(),
+        MessageType::Question,
+        ButtonsType::OkCancel,
+        "Custom signet parameters",
+    );
+    dlg.set_title("Custom signet");
+    dlg.set_secondary_text(Some("Provide the electrum server indexing the signet."));
+    dlg.set_default_response(ResponseType::Ok);
+
+    let server_fld = gtk::Entry::new();
+    server_fld.set_text("localhost");
+    let port_fld = gtk::SpinButton::with_range(1.0, u16::MAX as f64, 1.0);
+    port_fld.set_value(PublicNetwork::Signet.electrum_port() as f64);
+    port_fld.set_activates_default(true);
+    if let Ok(area) = dlg.message_area().downcast::<gtk::Box>() {
+        area.pack_end(&port_fld, false, false, 0);
+        area.pack_end(&server_fld, false, false, 0);
+    }
+    server_fld.show();
+    port_fld.show();
+
+    let resp = dlg.run();
+    let electrum_server = server_fld.text().to_string();
+    let electrum_port = port_fld.value() as u16;
+    dlg.close();
+    if resp != ResponseType::Ok {
+        return None;
+    }
+    if electrum_server.trim().is_empty() {
+        error_dlg(
+            parent,
+            "Custom signet",
+            "Invalid electrum server",
+            Some("The electrum server indexing the signet must be provided"),
+        );
+        return None;
+    }
+    Some(CustomSignet {
+        electrum_server,
+        electrum_port,
+    })
+}
+
 pub fn file_dlg(
     parent: Option<&impl IsA<gtk::Window>>,
     title: &str,
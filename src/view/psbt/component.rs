@@ -14,18 +14,16 @@ use std::{fs, io, thread};
 
 use bitcoin::consensus::Encodable;
 use bitcoin::psbt::PartiallySignedTransaction;
-use bitcoin::secp256k1::SECP256K1;
 use electrum_client::ElectrumApi;
 use gladis::Gladis;
 use gtk::prelude::ListModelExt;
 use gtk::{ApplicationWindow, MessageType};
 use hwi::HWIDevice;
-use miniscript::psbt::PsbtExt;
 use relm::{init, Cast, Channel, Relm, Sender, StreamHandle, Update, Widget};
 
 use super::sign_row::Signing;
 use super::{xpriv_dlg, ModelParam, Msg, SignMsg, ViewModel, Widgets};
-use crate::model::{ElectrumPreset, ElectrumServer};
+use crate::model::{psbt, ElectrumPreset, ElectrumServer};
 use crate::view::psbt::PublishMsg;
 use crate::view::{error_dlg, file_save_dlg, launch, msg_dlg};
 use crate::worker::electrum::electrum_connect;
@@ -90,10 +88,8 @@ impl Component {
     }
 
     pub fn finalize(&mut self) -> Result<(), Vec<miniscript::psbt::Error>> {
-        let mut psbt = PartiallySignedTransaction::from(self.model.psbt().clone());
         self.model.clear_finalized_tx();
-        psbt.finalize_mut(&SECP256K1)?;
-        let tx = psbt.extract_tx();
+        let tx = psbt::finalize(self.model.psbt())?;
         self.model.set_finalized_tx(tx);
         self.widgets.update_ui(&self.model);
         Ok(())
@@ -109,7 +105,9 @@ impl Component {
             let tx = tx.clone();
             let sender = self.publisher_sender.clone();
             // TODO: Allow selecting Electrum server
-            let electrum = ElectrumServer::tls(ElectrumPreset::Blockstream, self.model.network());
+            let network = self.model.network();
+            let electrum = ElectrumServer::local(network)
+                .unwrap_or_else(|| ElectrumServer::tls(ElectrumPreset::Blockstream, network));
             thread::spawn(move || {
                 let _ = match electrum_connect(&electrum.to_string())
                     .and_then(|client| client.transaction_broadcast(&tx))
@@ -220,13 +218,16 @@ impl Update for Component {
                 self.widgets.publish_restore(false);
             }
 
+            // The signet item also represents custom signets, which are kept on toggling
+            Msg::Network(PublicNetwork::Signet)
+                if matches!(self.model.network(), PublicNetwork::CustomSignet(_)) => {}
             Msg::Network(network) => {
-                if self.model.network() == network {
+                if *self.model.network() == network {
                     return;
                 }
+                self.widgets.update_network(&network);
+                self.widgets.update_addresses(&self.model.psbt(), &network);
                 self.model.set_network(network);
-                self.widgets.update_network(network);
-                self.widgets.update_addresses(&self.model.psbt(), network);
             }
 
             Msg::Launch(msg) => {
@@ -281,6 +282,20 @@ impl Widget for Component {
         widgets.bind_signing_model(relm, model.signing());
         widgets.update_ui(&model);
         widgets.show();
+        if widgets.update_sighashes(model.psbt()) {
+            msg_dlg(
+                widgets.as_root(),
+                MessageType::Warning,
+                "Non-standard signature type",
+                "Some of the transaction inputs request signatures which do not commit to the \
+                 whole transaction",
+                Some(
+                    "Such signatures allow anybody to modify the transaction after signing, for \
+                     instance changing its outputs. Please verify with other signers why this \
+                     signature type is used before signing.",
+                ),
+            );
+        }
 
         let mut component = Component {
             model,
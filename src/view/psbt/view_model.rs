@@ -9,17 +9,13 @@
 // a copy of the AGPL-3.0 License along with this software. If not, see
 // <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
 
-use std::collections::BTreeMap;
 use std::path::PathBuf;
 
-use bitcoin::util::bip32::{ChildNumber, Fingerprint};
 use bitcoin::Transaction;
-use miniscript::ToPublicKey;
 use wallet::psbt::Psbt;
 
 use super::sign_row::SigningModel;
-use crate::model::psbt::McKeys;
-use crate::model::PublicNetwork;
+use crate::model::{psbt, PublicNetwork};
 use crate::view::psbt::sign_row::Signing;
 
 #[derive(Debug)]
@@ -37,7 +33,7 @@ impl ModelParam {
 
     pub fn network(&self) -> PublicNetwork {
         match self {
-            ModelParam::Open(_, _, network) | ModelParam::Create(_, network) => *network,
+            ModelParam::Open(_, _, network) | ModelParam::Create(_, network) => network.clone(),
         }
     }
 
@@ -55,7 +51,6 @@ pub struct ViewModel {
     finalized_tx: Option<Transaction>,
     path: Option<PathBuf>,
     signing: SigningModel,
-    #[getter(as_copy)]
     network: PublicNetwork,
     #[getter(prefix = "is_", as_copy)]
     modified: bool,
@@ -78,70 +73,15 @@ impl ViewModel {
     pub fn parse_psbt(&mut self) {
         self.signing.clear();
 
-        // Information on required signatures, indexed by terminal keys
-        let mut signing_keys =
-            BTreeMap::<bitcoin::PublicKey, (Fingerprint, Fingerprint, u32, u32)>::new();
-        for input in &self.psbt.inputs {
-            for (pk, (_, (master_fp, derivation))) in &input.tap_key_origins {
-                if derivation.len() > 1 && derivation[1] == (ChildNumber::Hardened { index: 1 }) {
-                    self.network = PublicNetwork::Testnet;
-                }
-                let key = pk.to_public_key();
-                let (fp, _, present, required) =
-                    signing_keys.entry(key).or_insert((zero!(), zero!(), 0, 0));
-                *fp = *master_fp;
-                *required += 1;
-                *present += input.tap_key_sig.map(|_| 1u32).unwrap_or_default()
-                    + input
-                        .tap_script_sigs
-                        .keys()
-                        .filter(|(xpk, _)| xpk == pk)
-                        .count() as u32;
-            }
-            for (pk, (master_fp, derivation)) in &input.bip32_derivation {
-                if derivation.len() > 1 && derivation[1] == (ChildNumber::Hardened { index: 1 }) {
-                    self.network = PublicNetwork::Testnet;
-                }
-                let key = bitcoin::PublicKey::new(*pk);
-                let (fp, _, present, required) =
-                    signing_keys.entry(key).or_insert((zero!(), zero!(), 0, 0));
-                *fp = *master_fp;
-                *required += 1;
-                *present += input
-                    .partial_sigs
-                    .get(&bitcoin::PublicKey::new(*pk))
-                    .map(|_| 1u32)
-                    .unwrap_or_default();
-            }
+        if psbt::uses_testnet_keys(&self.psbt) && !self.network.is_testnet() {
+            self.network = PublicNetwork::Testnet;
         }
 
-        for (account_xpub, (master_fp, _)) in &self.psbt.xpub {
-            for (fp, account_fp, ..) in signing_keys.values_mut() {
-                if fp == master_fp {
-                    *account_fp = account_xpub.fingerprint();
-                } else if *fp == account_xpub.fingerprint() {
-                    *account_fp = *fp;
-                    *fp = *master_fp;
-                }
-            }
-        }
-
-        let signers = signing_keys.into_iter().fold(
-            BTreeMap::<Fingerprint, (u32, u32)>::new(),
-            |mut signers, (_, (master_fp, _, p, r))| {
-                let (present, required) = signers.entry(master_fp).or_default();
-                *present += p;
-                *required += r;
-                signers
-            },
-        );
-
-        for (signer_no, (master_fp, (present, required))) in signers.into_iter().enumerate() {
-            let name = self
-                .psbt
-                .signer_name(master_fp)
+        for (signer_no, signer) in psbt::signer_status(&self.psbt).into_iter().enumerate() {
+            let name = signer
+                .name
                 .unwrap_or_else(|| format!("Signer #{}", signer_no + 1));
-            let info = Signing::with(&name, master_fp, present, required);
+            let info = Signing::with(&name, signer.master_fp, signer.present, signer.required);
             self.signing.append(&info);
         }
     }
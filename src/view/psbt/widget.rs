@@ -20,13 +20,14 @@ use gladis::Gladis;
 use gtk::gdk_pixbuf::Pixbuf;
 use gtk::prelude::*;
 use gtk::{
-    gdk, ApplicationWindow, Button, Dialog, Entry, Expander, HeaderBar, Image, Label, LevelBar,
-    ListBox, ListStore, MenuItem, RadioMenuItem, TextView, TreeView,
+    gdk, ApplicationWindow, Button, Dialog, Entry, Expander, HeaderBar, Image, InfoBar, Label,
+    LevelBar, ListBox, ListStore, MenuItem, RadioMenuItem, TextView, TreeView,
 };
 use miniscript::{Legacy, Miniscript, Segwitv0};
 use relm::Relm;
 
 use super::{Msg, ViewModel};
+use crate::model::psbt::non_default_sighashes;
 use crate::model::PublicNetwork;
 use crate::view::launch::Page;
 use crate::view::psbt::sign_row;
@@ -40,6 +41,8 @@ pub struct Widgets {
 
     header_bar: HeaderBar,
     logo_img: Image,
+    sighash_bar: InfoBar,
+    sighash_lbl: Label,
     save_btn: Button,
     publish_btn: Button,
 
@@ -47,6 +50,7 @@ pub struct Widgets {
     mainnet_mi: RadioMenuItem,
     testnet_mi: RadioMenuItem,
     signet_mi: RadioMenuItem,
+    regtest_mi: RadioMenuItem,
 
     new_wallet_mi: MenuItem,
     new_template_mi: MenuItem,
@@ -177,6 +181,30 @@ impl Widgets {
         self.inputs_lbl.set_label(&format!("{}", psbt.inputs.len()));
 
         self.update_addresses(psbt, model.network());
+        self.update_sighashes(psbt);
+    }
+
+    /// Warns about inputs which signatures would not commit to the whole transaction. Returns
+    /// whether such inputs are present.
+    pub fn update_sighashes(&self, psbt: &Psbt) -> bool {
+        let sighashes = non_default_sighashes(psbt);
+        if sighashes.is_empty() {
+            self.sighash_bar.hide();
+            return false;
+        }
+        let inputs = sighashes
+            .iter()
+            .map(|(no, sighash)| format!("#{} ({})", no, sighash))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.sighash_lbl.set_text(&format!(
+            "Warning: inputs {} request signatures with non-ALL sighash type. Such signatures do \
+             not commit to the whole transaction and allow anybody to change it after signing; \
+             sign only if you know why this sighash type is used.",
+            inputs
+        ));
+        self.sighash_bar.show();
+        true
     }
 
     pub fn show(&self) { self.window.show() }
@@ -270,6 +298,16 @@ impl Widgets {
                 Msg::NoOp
             }
         );
+        connect!(
+            relm,
+            self.regtest_mi,
+            connect_toggled(mi),
+            if mi.is_active() {
+                Msg::Network(PublicNetwork::Regtest)
+            } else {
+                Msg::NoOp
+            }
+        );
 
         self.txid_fld.connect_icon_press(|entry, _, _| {
             let val = entry.text();
@@ -307,19 +345,24 @@ impl Widgets {
 
     pub fn set_unsaved(&self) { self.save_btn.set_sensitive(true); }
 
-    pub fn update_network(&self, network: PublicNetwork) {
+    pub fn update_network(&self, network: &PublicNetwork) {
         let network_name = network.to_string();
         let network_name = network_name[0..1].to_uppercase() + &network_name[1..];
         self.network_lbl.set_text(&network_name);
 
         self.mainnet_mi
-            .set_active(network == PublicNetwork::Mainnet);
+            .set_active(*network == PublicNetwork::Mainnet);
         self.testnet_mi
-            .set_active(network == PublicNetwork::Testnet);
-        self.signet_mi.set_active(network == PublicNetwork::Signet);
+            .set_active(*network == PublicNetwork::Testnet);
+        self.signet_mi.set_active(matches!(
+            network,
+            PublicNetwork::Signet | PublicNetwork::CustomSignet(_)
+        ));
+        self.regtest_mi
+            .set_active(*network == PublicNetwork::Regtest);
     }
 
-    pub fn update_addresses(&self, psbt: &Psbt, network: PublicNetwork) {
+    pub fn update_addresses(&self, psbt: &Psbt, network: &PublicNetwork) {
         self.address_store.clear();
         for output in &psbt.outputs {
             let address = Address::from_script(&output.script, network.into());
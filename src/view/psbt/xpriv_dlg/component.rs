@@ -45,8 +45,7 @@ impl Component {
             }
         };
 
-        let network = PublicNetwork::try_from(xpriv.network)
-            .expect("xpriv network always either mainnet or testnet");
+        let network = PublicNetwork::from(xpriv.network);
         if network.is_testnet() != self.model.testnet {
             self.widgets.show_error(
                 "network used by the PSBT and network of the provided private key does not match",
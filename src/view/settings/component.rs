@@ -19,7 +19,7 @@ use gtk::{Dialog, ResponseType};
 use relm::{init, Channel, Relm, StreamHandle, Update, Widget};
 
 use super::spending_row::Condition;
-use super::{xpub_dlg, Msg, ViewModel, Widgets};
+use super::{xpub_dlg, ElectrumModel, Msg, ViewModel, Widgets};
 use crate::model::{ElectrumPreset, PublicNetwork, Signer, WalletSettings};
 use crate::view::{devices, error_dlg, launch, wallet, NotificationBoxExt};
 
@@ -72,17 +72,13 @@ impl Component {
         }
 
         for signer in &self.model.signers {
-            let network =
-                PublicNetwork::try_from(signer.xpub.network).unwrap_or(PublicNetwork::Testnet);
+            let network = PublicNetwork::from(signer.xpub.network);
             if network.is_testnet() != self.model.network.is_testnet() {
                 return self.widgets.show_error(&format!(
                     "Wallet uses {} while signer {} requires {}",
                     self.model.network,
                     signer.fingerprint(),
-                    PublicNetwork::try_from(signer.xpub.network)
-                        .as_ref()
-                        .map(PublicNetwork::to_string)
-                        .unwrap_or(s!("regtest"))
+                    network
                 ));
             }
         }
@@ -144,7 +140,7 @@ impl Update for Component {
                     .and_then(|xpub| self.model.signer_by(xpub));
                 self.widgets.update_signer_details(
                     signer.map(|s| (s, self.model.derivation_for(s))),
-                    self.model.network,
+                    &self.model.network,
                     self.model.bip43(),
                 );
                 self.model.active_signer = signer.cloned();
@@ -161,8 +157,12 @@ impl Update for Component {
             }
             Msg::ElectrumSelect(preset) if self.model.electrum_model.electrum_preset != preset => {
                 self.model.electrum_model.electrum_preset = preset;
-                self.widgets
-                    .update_electrum(&mut self.model.electrum_model, false, false);
+                self.widgets.update_electrum(
+                    &mut self.model.electrum_model,
+                    &self.model.network,
+                    false,
+                    false,
+                );
                 return;
             }
             Msg::ElectrumEdit
@@ -170,8 +170,12 @@ impl Update for Component {
             {
                 self.model.electrum_model.electrum_preset = ElectrumPreset::Custom;
                 self.model.electrum_model.electrum_server = self.widgets.electrum_server();
-                self.widgets
-                    .update_electrum(&mut self.model.electrum_model, false, false);
+                self.widgets.update_electrum(
+                    &mut self.model.electrum_model,
+                    &self.model.network,
+                    false,
+                    false,
+                );
                 return;
             }
             Msg::ElectrumPortChange
@@ -179,14 +183,22 @@ impl Update for Component {
             {
                 self.model.electrum_model.electrum_preset = ElectrumPreset::Custom;
                 self.model.electrum_model.electrum_port = self.widgets.electrum_port();
-                self.widgets
-                    .update_electrum(&mut self.model.electrum_model, false, false);
+                self.widgets.update_electrum(
+                    &mut self.model.electrum_model,
+                    &self.model.network,
+                    false,
+                    false,
+                );
                 return;
             }
             Msg::ElectrumSecChange(sec) if sec != self.model.electrum_model.electrum_sec => {
                 self.model.electrum_model.electrum_sec = sec;
-                self.widgets
-                    .update_electrum(&mut self.model.electrum_model, false, false);
+                self.widgets.update_electrum(
+                    &mut self.model.electrum_model,
+                    &self.model.network,
+                    false,
+                    false,
+                );
                 return;
             }
             Msg::ElectrumTest => {
@@ -294,7 +306,7 @@ impl Update for Component {
                 self.model.signers.push(Signer::with_xpub(
                     xpub,
                     &self.model.bip43(),
-                    self.model.network,
+                    self.model.network.clone(),
                 ));
                 self.widgets.update_signers(&self.model.signers);
             }
@@ -303,7 +315,7 @@ impl Update for Component {
                     .remove_signer()
                     .map(|index| self.model.signers.remove(index));
                 self.widgets
-                    .update_signer_details(None, self.model.network, self.model.bip43());
+                    .update_signer_details(None, &self.model.network, self.model.bip43());
             }
             Msg::SignerFingerprintChange => {
                 let terminal = self.model.terminal_derivation();
@@ -402,11 +414,22 @@ impl Update for Component {
                         .update_descr_classes(&self.model.descriptor_classes);
                 }
             }
+            // The signet toggle also represents custom signets, which are kept on toggling
+            Msg::NetworkChange(PublicNetwork::Signet)
+                if matches!(self.model.network, PublicNetwork::CustomSignet(_)) => {}
             Msg::NetworkChange(network) if network != self.model.network => {
                 self.model.network = network;
-                self.widgets.update_network();
-                self.widgets
-                    .update_electrum(&mut self.model.electrum_model, false, false);
+                self.widgets.update_network(&self.model.network);
+                let local = self.model.network.is_local();
+                if local {
+                    self.model.electrum_model = ElectrumModel::new(&self.model.network);
+                }
+                self.widgets.update_electrum(
+                    &mut self.model.electrum_model,
+                    &self.model.network,
+                    local,
+                    local,
+                );
             }
             _ => {}
         }
@@ -431,8 +454,9 @@ impl Widget for Component {
             stream.emit(msg);
         });
 
-        let devices = init::<devices::Component>((model.bip43(), model.network, sender.clone()))
-            .expect("error in devices component");
+        let devices =
+            init::<devices::Component>((model.bip43(), model.network.clone(), sender.clone()))
+                .expect("error in devices component");
         let xpub_dlg = init::<xpub_dlg::Component>((model.bip43().into(), sender))
             .expect("error in xpub dialog component");
 
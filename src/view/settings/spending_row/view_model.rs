@@ -20,7 +20,7 @@ use gtk::subclass::prelude::ListModelImpl;
 use gtk::{gio, glib};
 
 use crate::model::{
-    Signer, SigsReq, SpendingCondition, TimelockDuration, TimelockReq, TimelockedSigs,
+    HeaderIndex, Signer, SigsReq, SpendingCondition, TimelockDuration, TimelockReq, TimelockedSigs,
 };
 
 // The actual data structure that stores our values. This is not accessible
@@ -42,6 +42,8 @@ pub struct ConditionInner {
     after_day: RefCell<u32>,
     after_month: RefCell<u32>,
     after_year: RefCell<u32>,
+    /// Block height of the absolute timelock; zero if the timelock is defined by the date.
+    after_height: RefCell<u32>,
 }
 
 impl Default for ConditionInner {
@@ -62,6 +64,7 @@ impl Default for ConditionInner {
             after_day: RefCell::new(1),
             after_month: RefCell::new(1),
             after_year: RefCell::new(2025),
+            after_height: RefCell::new(0),
         }
     }
 }
@@ -89,6 +92,9 @@ impl From<&ConditionInner> for TimelockReq {
             *inner.lock_older.borrow(),
         ) {
             (true, false, false) => TimelockReq::Anytime,
+            (_, true, false) if *inner.after_height.borrow() > 0 => {
+                TimelockReq::AfterHeight(*inner.after_height.borrow())
+            }
             (_, true, false) => {
                 let date = NaiveDate::from_ymd(
                     *inner.after_year.borrow() as i32,
@@ -200,6 +206,15 @@ impl ObjectImpl for ConditionInner {
                     2025,
                     flag,
                 ),
+                glib::ParamSpecUInt::new(
+                    "after-height",
+                    "AfterHeight",
+                    "AfterHeight",
+                    0,
+                    u32::MAX,
+                    0,
+                    flag,
+                ),
             ]
         });
 
@@ -304,6 +319,12 @@ impl ObjectImpl for ConditionInner {
                     .expect("type conformity checked by `Object::set_property`");
                 self.after_year.replace(value);
             }
+            "after-height" => {
+                let value = value
+                    .get()
+                    .expect("type conformity checked by `Object::set_property`");
+                self.after_height.replace(value);
+            }
             _ => unimplemented!(),
         }
     }
@@ -325,6 +346,7 @@ impl ObjectImpl for ConditionInner {
             "after-day" => self.after_day.borrow().to_value(),
             "after-month" => self.after_month.borrow().to_value(),
             "after-year" => self.after_year.borrow().to_value(),
+            "after-height" => self.after_height.borrow().to_value(),
             _ => unimplemented!(),
         }
     }
@@ -376,6 +398,7 @@ impl Condition {
 pub struct SpendingModelInner {
     pub conditions: RefCell<Vec<Condition>>,
     pub max_sigs: RefCell<u16>,
+    pub headers: RefCell<HeaderIndex>,
 }
 
 /// Basic declaration of our type for the GObject type system
@@ -417,6 +440,10 @@ impl SpendingModel {
         // TODO: Update specific conditions
     }
 
+    pub fn headers(&self) -> HeaderIndex { self.imp().headers.borrow().clone() }
+
+    pub fn set_headers(&self, headers: HeaderIndex) { *self.imp().headers.borrow_mut() = headers; }
+
     pub fn append(&self, obj: &Condition) {
         let imp = self.imp();
         let index = {
@@ -523,7 +550,7 @@ impl SpendingModel {
             matches!(
                 sc,
                 SpendingCondition::Sigs(TimelockedSigs {
-                    timelock: TimelockReq::AfterDate(_),
+                    timelock: TimelockReq::AfterDate(_) | TimelockReq::AfterHeight(_),
                     ..
                 })
             ),
@@ -561,7 +588,14 @@ impl SpendingModel {
             SpendingCondition::Sigs(TimelockedSigs {
                 timelock: TimelockReq::AfterDate(datetime),
                 ..
-            }) => Some(datetime),
+            }) => Some(*datetime),
+            SpendingCondition::Sigs(TimelockedSigs {
+                timelock: TimelockReq::AfterHeight(height),
+                ..
+            }) => {
+                cond.set_property("after-height", *height);
+                Some(self.imp().headers.borrow().estimate_time(*height))
+            }
             _ => None,
         }
         .map(|datetime| {
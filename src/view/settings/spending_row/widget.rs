@@ -9,6 +9,7 @@
 // a copy of the AGPL-3.0 License along with this software. If not, see
 // <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
 
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
 use gladis::Gladis;
 use gtk::glib::Binding;
 use gtk::prelude::*;
@@ -19,6 +20,7 @@ use gtk::{
 use relm::Sender;
 
 use super::Condition;
+use crate::model::HeaderIndex;
 
 #[derive(Clone, Gladis)]
 pub struct RowWidgets {
@@ -53,14 +55,14 @@ pub struct RowWidgets {
 }
 
 impl RowWidgets {
-    pub fn init(sender: Sender<()>, item: &glib::Object) -> gtk::Widget {
+    pub fn init(sender: Sender<()>, item: &glib::Object, headers: HeaderIndex) -> gtk::Widget {
         let glade_src = include_str!("spending_row.glade");
         let row_widgets = RowWidgets::from_string(glade_src).expect("glade file broken");
 
         let condition = item
             .downcast_ref::<Condition>()
             .expect("Row data is of wrong type");
-        row_widgets.bind_model(condition);
+        row_widgets.bind_model(condition, headers);
 
         // We need this hack since mpsc::Sender does not implement Sync, as required by the
         // glib event handler. However, since we `move` the object anyway, we do not need it to be
@@ -120,6 +122,7 @@ impl RowWidgets {
         row_widgets
             .calendar
             .connect_day_selected_double_click(move |cal| {
+                c.set_property("after-height", 0u32);
                 c.set_property("after-day", cal.day() as u32);
                 c.set_property("after-month", cal.month() as u32 + 1);
                 c.set_property("after-year", cal.year() as u32);
@@ -129,7 +132,7 @@ impl RowWidgets {
         row_widgets.spending_row.upcast::<gtk::Widget>()
     }
 
-    fn bind_model(&self, condition: &Condition) {
+    fn bind_model(&self, condition: &Condition, headers: HeaderIndex) {
         let flags_ro = glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE;
         let flags_rw = glib::BindingFlags::DEFAULT
             | glib::BindingFlags::SYNC_CREATE
@@ -333,11 +336,37 @@ impl RowWidgets {
             .flags(flags_ro)
             .build();
 
-        let fmtdate = |binding: &Binding, _: &glib::Value| -> Option<glib::Value> {
-            let year: u32 = binding.source().unwrap().property("after-year");
-            let month: u32 = binding.source().unwrap().property("after-month");
-            let day: u32 = binding.source().unwrap().property("after-day");
-            Some(format!("{}/{}/{}", year, month, day).to_value())
+        let fmtdate = move |binding: &Binding, _: &glib::Value| -> Option<glib::Value> {
+            let source = binding.source().unwrap();
+            let height: u32 = source.property("after-height");
+            if height > 0 {
+                let date = headers.estimate_time(height);
+                return Some(
+                    format!(
+                        "block {} (~{}/{}/{})",
+                        height,
+                        date.year(),
+                        date.month(),
+                        date.day()
+                    )
+                    .to_value(),
+                );
+            }
+            let year: u32 = source.property("after-year");
+            let month: u32 = source.property("after-month");
+            let day: u32 = source.property("after-day");
+            // Properties are updated one by one, so the date may be temporarily invalid
+            Some(
+                match NaiveDate::from_ymd_opt(year as i32, month, day) {
+                    Some(date) => {
+                        let date = DateTime::from_utc(date.and_hms(0, 0, 0), Utc);
+                        let height = headers.estimate_height(date);
+                        format!("{}/{}/{} (~block {})", year, month, day, height)
+                    }
+                    None => format!("{}/{}/{}", year, month, day),
+                }
+                .to_value(),
+            )
         };
 
         condition
@@ -353,6 +382,11 @@ impl RowWidgets {
         condition
             .bind_property("after-year", &self.calendar_lbl, "label")
             .flags(flags_ro)
+            .transform_to(fmtdate.clone())
+            .build();
+        condition
+            .bind_property("after-height", &self.calendar_lbl, "label")
+            .flags(flags_ro)
             .transform_to(fmtdate)
             .build();
     }
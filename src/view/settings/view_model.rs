@@ -23,7 +23,8 @@ use super::spending_row::SpendingModel;
 use super::Msg;
 use crate::model::{
     file, DescriptorClass, DescriptorError, ElectrumPreset, ElectrumSec, ElectrumServer,
-    FileDocument, HardwareList, PublicNetwork, Signer, Wallet, WalletSettings, WalletTemplate,
+    FileDocument, HardwareList, HeaderIndex, PublicNetwork, Signer, Wallet, WalletSettings,
+    WalletTemplate,
 };
 
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
@@ -78,12 +79,15 @@ impl Display for ElectrumModel {
 }
 
 impl ElectrumModel {
-    fn new(network: PublicNetwork) -> Self {
-        ElectrumModel {
-            electrum_preset: ElectrumPreset::Blockstream,
-            electrum_server: ElectrumPreset::Blockstream.to_string(),
-            electrum_port: network.electrum_port(),
-            electrum_sec: ElectrumSec::Tls,
+    pub fn new(network: &PublicNetwork) -> Self {
+        match ElectrumServer::local(network) {
+            Some(electrum) => electrum.into(),
+            None => ElectrumModel {
+                electrum_preset: ElectrumPreset::Blockstream,
+                electrum_server: ElectrumPreset::Blockstream.to_string(),
+                electrum_port: network.electrum_port(),
+                electrum_sec: ElectrumSec::Tls,
+            },
         }
     }
 
@@ -127,7 +131,7 @@ impl TryFrom<&ViewModel> for WalletSettings {
             model.spending_model.spending_conditions(),
             model.descriptor_classes.clone(),
             model.terminal_derivation(),
-            model.network,
+            model.network.clone(),
             model.electrum_model.clone().into(),
         )
     }
@@ -142,7 +146,7 @@ impl ViewModel {
             signers: none!(),
             active_signer: None,
             spending_model: SpendingModel::new(),
-            electrum_model: ElectrumModel::new(PublicNetwork::Mainnet),
+            electrum_model: ElectrumModel::new(&PublicNetwork::Mainnet),
             network: PublicNetwork::Mainnet,
             descriptor: None,
             template: None,
@@ -164,10 +168,11 @@ impl ViewModel {
         self.stream = stream;
         self.descriptor_classes = bset![template.descriptor_class];
         self.support_multiclass = false;
-        self.network = template.network;
+        self.network = template.network.clone();
         self.signers = empty!();
+        self.spending_model.set_headers(default!());
         self.spending_model.reset_conditions(&template.conditions);
-        self.electrum_model = ElectrumModel::new(template.network);
+        self.electrum_model = ElectrumModel::new(&template.network);
         self.template = Some(template);
 
         self.export_lnpbp = false;
@@ -193,8 +198,12 @@ impl ViewModel {
         self.stream = stream;
         self.support_multiclass = descriptor_classes.len() > 1;
         self.descriptor_classes = descriptor_classes;
-        self.network = settings.network();
+        self.network = settings.network().clone();
         self.signers = settings.signers().clone();
+        // The index is a cache, so a damaged one is simply rebuilt with the next sync; the index
+        // of encrypted wallets is not readable here and timelocks are estimated without it
+        self.spending_model
+            .set_headers(HeaderIndex::read_for(&self.path, None).unwrap_or_default());
         self.spending_model
             .reset_conditions(settings.spending_conditions());
         self.electrum_model = settings.electrum().clone().into();
@@ -288,7 +297,7 @@ impl ViewModel {
                 *fingerprint,
                 device.clone(),
                 &self.bip43(),
-                self.network,
+                self.network.clone(),
             ));
         }
     }
@@ -87,6 +87,7 @@ pub struct Widgets {
     mainnet_tgl: ToggleButton,
     testnet_tgl: ToggleButton,
     signet_tgl: ToggleButton,
+    regtest_tgl: ToggleButton,
     export_core_tgl: ToggleButton,
     export_lnpbp_tgl: ToggleButton,
     electr_blockstream_tgl: ToggleButton,
@@ -118,7 +119,7 @@ impl Widgets {
         // New wallet
         if let Some(ref template) = model.template {
             self.update_template(template);
-            self.update_signer_details(None, template.network, template.bip43());
+            self.update_signer_details(None, &template.network, template.bip43());
             self.pages.set_page(0);
         } else {
             let new_wallet = model.is_new_wallet();
@@ -151,18 +152,18 @@ impl Widgets {
             }
         }
 
-        self.mainnet_tgl
-            .set_active(model.network == PublicNetwork::Mainnet);
-        self.testnet_tgl
-            .set_active(model.network == PublicNetwork::Testnet);
-        self.signet_tgl
-            .set_active(model.network == PublicNetwork::Signet);
+        self.update_network_toggles(&model.network);
 
-        self.update_electrum(&mut model.electrum_model.clone(), true, true);
-        self.update_network();
+        self.update_electrum(
+            &mut model.electrum_model.clone(),
+            &model.network,
+            true,
+            true,
+        );
+        self.update_network(&model.network);
 
         self.update_signers(&model.signers);
-        self.update_signer_details(None, model.network, model.bip43());
+        self.update_signer_details(None, &model.network, model.bip43());
         self.update_descr_classes(&model.descriptor_classes);
         self.update_descriptor(model.descriptor.as_ref(), model.export_lnpbp);
 
@@ -247,6 +248,12 @@ impl Widgets {
             connect_clicked(_),
             Msg::NetworkChange(PublicNetwork::Signet)
         );
+        connect!(
+            relm,
+            self.regtest_tgl,
+            connect_clicked(_),
+            Msg::NetworkChange(PublicNetwork::Regtest)
+        );
 
         connect!(
             relm,
@@ -392,8 +399,9 @@ impl Widgets {
     }
 
     pub(super) fn bind_spending_model(&self, sender: Sender<()>, model: &SpendingModel) {
+        let spending_model = model.clone();
         self.spending_list.bind_model(Some(model), move |item| {
-            spending_row::RowWidgets::init(sender.clone(), item)
+            spending_row::RowWidgets::init(sender.clone(), item, spending_model.headers())
         });
     }
 
@@ -421,21 +429,21 @@ impl Widgets {
         }
     }
 
-    pub fn network(&self) -> PublicNetwork {
-        match (
-            self.mainnet_tgl.is_active(),
-            self.testnet_tgl.is_active(),
-            self.signet_tgl.is_active(),
-        ) {
-            (true, false, false) => PublicNetwork::Mainnet,
-            (_, true, false) => PublicNetwork::Testnet,
-            (_, _, true) => PublicNetwork::Signet,
-            _ => unreachable!("inconsistent network togglers state"),
-        }
+    fn update_network_toggles(&self, network: &PublicNetwork) {
+        self.mainnet_tgl
+            .set_active(*network == PublicNetwork::Mainnet);
+        self.testnet_tgl
+            .set_active(*network == PublicNetwork::Testnet);
+        // Custom signets are configured in the launcher and share the signet toggle
+        self.signet_tgl.set_active(matches!(
+            network,
+            PublicNetwork::Signet | PublicNetwork::CustomSignet(_)
+        ));
+        self.regtest_tgl
+            .set_active(*network == PublicNetwork::Regtest);
     }
 
-    pub fn update_network(&self) {
-        let network = self.network();
+    pub fn update_network(&self, network: &PublicNetwork) {
         self.devices_btn.set_sensitive(!network.is_testnet());
         self.devices_btn.set_tooltip_text(if network.is_testnet() {
             Some("Hardware signers can be only used on mainnet")
@@ -451,6 +459,7 @@ impl Widgets {
     pub fn update_electrum(
         &self,
         model: &mut ElectrumModel,
+        network: &PublicNetwork,
         update_preset: bool,
         update_sec: bool,
     ) {
@@ -466,7 +475,7 @@ impl Widgets {
             model.electrum_server = model.electrum_preset.to_string();
             model.electrum_port = model
                 .electrum_preset
-                .electrum_port(model.electrum_sec, self.network());
+                .electrum_port(model.electrum_sec, network);
         }
         if update_sec {
             self.tor_tgl
@@ -507,7 +516,7 @@ impl Widgets {
         self.test_btn.set_sensitive(true);
     }
 
-    fn update_derivation(&self, format: &DerivationType, network: PublicNetwork) {
+    fn update_derivation(&self, format: &DerivationType, network: &PublicNetwork) {
         match format {
             DerivationType::LnpBp(_) => {
                 self.descr_legacy_tgl.set_active(true);
@@ -530,11 +539,7 @@ impl Widgets {
             }
         }
 
-        self.mainnet_tgl
-            .set_active(network == PublicNetwork::Mainnet);
-        self.testnet_tgl
-            .set_active(network == PublicNetwork::Testnet);
-        self.signet_tgl.set_active(network == PublicNetwork::Signet);
+        self.update_network_toggles(network);
     }
 
     fn update_template(&self, template: &WalletTemplate) {
@@ -586,7 +591,7 @@ impl Widgets {
     pub fn update_signer_details(
         &self,
         details: Option<(&Signer, TrackingAccount)>,
-        network: PublicNetwork,
+        network: &PublicNetwork,
         standard: Bip43,
     ) {
         let signer = details.as_ref().map(|d| d.0);
@@ -624,7 +629,7 @@ impl Widgets {
         }
 
         if let Some((signer, ref derivation)) = details {
-            let origin_format = signer.origin_format(network);
+            let origin_format = signer.origin_format(network.clone());
 
             gtk::prelude::ComboBoxTextExt::remove(&self.path_cmb, 3);
             gtk::prelude::ComboBoxTextExt::remove(&self.path_cmb, 2);
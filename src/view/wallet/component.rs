@@ -12,29 +12,28 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::OsStr;
 use std::path::PathBuf;
+use std::{fs, io};
 
-use ::wallet::descriptors::InputDescriptor;
-use ::wallet::locks::{LockTime, SeqNo};
-use ::wallet::psbt::{Construct, Psbt};
-use ::wallet::scripts::PubkeyScript;
-use bitcoin::blockdata::constants::WITNESS_SCALE_FACTOR;
-use bitcoin::policy::DUST_RELAY_TX_FEE;
-use bitcoin::secp256k1::SECP256K1;
-use bitcoin::{EcdsaSighashType, Transaction, TxIn, TxOut};
+use ::wallet::psbt::Psbt;
+use bitcoin::{BlockHeader, Transaction, Txid};
 use gladis::Gladis;
 use gtk::prelude::*;
-use gtk::{ApplicationWindow, ResponseType};
-use miniscript::DescriptorTrait;
+use gtk::{ApplicationWindow, MessageType, ResponseType};
 use relm::{init, Channel, Relm, StreamHandle, Update, Widget};
 use wallet::hd::{SegmentIndexes, UnhardenedIndex};
-use wallet::lex_order::lex_order::LexOrder;
 
 use super::pay::beneficiary_row::Beneficiary;
 use super::pay::FeeRate;
 use super::{pay, ElectrumState, Msg, ViewModel, Widgets};
-use crate::model::psbt::McKeys;
-use crate::model::{AddressSource, Wallet};
-use crate::view::{error_dlg, launch, settings, NotificationBoxExt};
+use crate::model::psbt::{McKeys, SighashType};
+use crate::model::{
+    bump, labels, AddressSource, BumpMethod, CoinSelectStrategy, HistoryEntry, Payment,
+    PaymentRequest, Wallet,
+};
+use crate::view::{
+    error_dlg, file_create_dlg, file_open_dlg, launch, msg_dlg, passphrase_dlg, settings,
+    NotificationBoxExt,
+};
 use crate::worker::electrum::TxidMeta;
 use crate::worker::{electrum, exchange, ElectrumWorker, ExchangeWorker};
 
@@ -49,6 +48,8 @@ pub struct Component {
     electrum_worker: ElectrumWorker,
     tx_buffer: Vec<Transaction>,
     addr_buffer: BTreeMap<AddressSource, BTreeSet<TxidMeta>>,
+    /// Transaction which fee is being bumped with the fee bump dialog and the bump method.
+    bump_txid: Option<(Txid, BumpMethod)>,
 
     settings: relm::Component<settings::Component>,
     launcher_stream: Option<StreamHandle<launch::Msg>>,
@@ -74,119 +75,214 @@ impl Component {
         }
     }
 
-    pub fn compose_psbt(&mut self) -> Result<(Psbt, UnhardenedIndex, u32), pay::Error> {
+    fn update_headers(&mut self, headers: impl IntoIterator<Item = (u32, BlockHeader)>) {
+        if let Err(err) = self.model.update_headers(headers) {
+            error_dlg(
+                self.widgets.as_root(),
+                "Error saving block index",
+                "It was impossible to save block timestamps next to the wallet file",
+                Some(&err.to_string()),
+            );
+        }
+    }
+
+    fn show_bump(&mut self, txid: Txid) {
         let wallet = self.model.as_wallet();
+        let method = match wallet.bump_method(txid) {
+            Some(method) => method,
+            None => return,
+        };
+        let fee_rate = wallet
+            .history()
+            .iter()
+            .find(|entry| entry.onchain.txid == txid)
+            .and_then(HistoryEntry::fee_rate);
+        let suggested = wallet
+            .ephemerals()
+            .fees
+            .0
+            .max(fee_rate.unwrap_or_default() + bump::INCREMENTAL_RELAY_FEE);
+        self.bump_txid = Some((txid, method));
+        self.widgets.show_bump(method, fee_rate, suggested);
+    }
 
-        let output_count = self.model.beneficiaries().n_items();
-        let mut txouts = Vec::with_capacity(output_count as usize);
-        let mut output_value = 0u64;
-        for no in 0..output_count {
-            let beneficiary = self
-                .model
-                .beneficiaries()
-                .item(no)
-                .expect("BeneficiaryModel is broken")
-                .downcast::<Beneficiary>()
-                .expect("BeneficiaryModel is broken");
-            let script_pubkey = beneficiary.address()?.script_pubkey();
-            let value = beneficiary.amount_sats();
-            if value == 0 {
-                return Err(pay::Error::Amount);
-            }
-            output_value += value;
-            txouts.push(TxOut {
-                script_pubkey,
-                value,
-            });
+    fn bump_fee(&mut self, txid: Txid, method: BumpMethod, fee_rate: f32) {
+        let bump = match self.model.as_wallet().bump_fee(
+            txid,
+            method,
+            fee_rate,
+            self.model.headers(),
+        ) {
+            Ok(bump) => bump,
+            Err(err) => {
+                error_dlg(
+                    self.widgets.as_root(),
+                    "Fee bump",
+                    "Unable to create transaction paying the higher fee",
+                    Some(&err.to_string()),
+                );
+                return;
+            }
+        };
+        let mut psbt = bump.psbt;
+        for signer in self.model.as_settings().signers() {
+            psbt.set_signer_name(signer.master_fp, &signer.name);
         }
-
-        if output_value == 0 {
-            return Err(pay::Error::NoBeneficiaries);
+        self.launcher_stream.as_ref().map(|stream| {
+            stream.emit(launch::Msg::CreatePsbt(
+                psbt,
+                self.model.as_settings().network().clone(),
+            ))
+        });
+        if self
+            .model
+            .as_wallet_mut()
+            .update_next_change_index(bump.change_index)
+        {
+            self.save();
         }
+    }
 
-        // TODO: Support constructing PSBTs from multiple descriptors (at descriptor-wallet lib)
-        let (descriptor, _) = self.model.as_settings().descriptors_all()?;
-        let lock_time = LockTime::with_height(734438).expect("hardcoded height");
-        let change_index = wallet.next_change_index();
-
-        let fee_rate = self.model.fee_rate();
-        let mut fee = 0;
-        let mut next_fee = DUST_RELAY_TX_FEE;
-        let mut prevouts = bset! {};
-        let satisfaciton_weights = descriptor.max_satisfaction_weight()? as f32;
-        let mut cycle_lim = 0usize;
-        let mut vsize = 0.0f32;
-        while fee <= DUST_RELAY_TX_FEE && fee != next_fee {
-            fee = next_fee;
-            prevouts = wallet
-                .coinselect(output_value + fee as u64)
-                .ok_or(pay::Error::InsufficientFunds)?
-                .0;
-            let txins = prevouts
-                .iter()
-                .map(|p| TxIn {
-                    previous_output: p.outpoint,
-                    script_sig: none!(),
-                    sequence: 0, // TODO: Support spending from CSV outputs
-                    witness: none!(),
-                })
-                .collect::<Vec<_>>();
-
-            let tx = Transaction {
-                version: 1,
-                lock_time: lock_time.as_u32(),
-                input: txins,
-                output: txouts.clone(),
-            };
-            vsize = tx.vsize() as f32 + satisfaciton_weights / WITNESS_SCALE_FACTOR as f32;
-            next_fee = (fee_rate * vsize).ceil() as u32;
-            cycle_lim += 1;
-            if cycle_lim > 6 {
-                return Err(pay::Error::FeeFailure);
+    fn import_labels(&mut self) {
+        let path = match file_open_dlg(
+            Some(self.widgets.as_root()),
+            "Import labels",
+            "BIP-329 wallet labels",
+            "*.jsonl",
+        ) {
+            Some(path) => path,
+            None => return,
+        };
+        let labels = match fs::File::open(path)
+            .map_err(labels::Error::from)
+            .and_then(|file| labels::read_labels(io::BufReader::new(file)))
+        {
+            Ok(labels) => labels,
+            Err(err) => {
+                error_dlg(
+                    self.widgets.as_root(),
+                    "Error importing labels",
+                    "Unable to read labels from the file",
+                    Some(&err.to_string()),
+                );
+                return;
             }
-        }
+        };
 
-        let inputs = prevouts
-            .into_iter()
-            .map(|prevout| InputDescriptor {
-                outpoint: prevout.outpoint,
-                terminal: prevout.terminal(),
-                seq_no: SeqNo::new_rbf(), // TODO: Support spending from CSV outputs
-                tweak: None,
-                sighash_type: EcdsaSighashType::All, // TODO: Support more sighashes in the UI
-            })
-            .collect::<Vec<_>>();
-        let outputs = txouts
-            .into_iter()
-            .map(|txout| (PubkeyScript::from(txout.script_pubkey), txout.value))
-            .collect::<Vec<_>>();
-
-        let mut psbt = Psbt::construct(
-            &SECP256K1,
-            &descriptor,
-            lock_time,
-            &inputs,
-            &outputs,
-            change_index,
-            fee as u64,
-            wallet,
-        )?;
-        psbt.lex_order();
+        let total = labels.len();
+        let count = self.model.as_wallet_mut().import_labels(labels);
+        let wallet = self.model.as_wallet();
+        self.widgets
+            .update_history(wallet.history(), self.model.headers());
+        self.widgets.update_utxos(wallet, self.model.headers());
+        self.save();
+
+        msg_dlg(
+            self.widgets.as_root(),
+            MessageType::Info,
+            "Labels imported",
+            &format!("{} of {} labels were imported", count, total),
+            (count < total).then(|| {
+                "Labels for unknown transactions and signers, addresses from other networks, \
+                 inputs and public keys are not used by the wallet"
+            }),
+        );
+    }
 
-        for signer in self.model.as_settings().signers() {
-            psbt.set_signer_name(signer.master_fp, &signer.name);
+    fn export_labels(&self) {
+        let name = format!(
+            "{}-labels.jsonl",
+            self.model
+                .path()
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .unwrap_or_default()
+        );
+        let path = match file_create_dlg(
+            Some(self.widgets.as_root()),
+            "Export labels",
+            "BIP-329 wallet labels",
+            "*.jsonl",
+            &name,
+        ) {
+            Some(path) => path,
+            None => return,
+        };
+        let labels = self.model.as_wallet().export_labels();
+        if let Err(err) =
+            fs::File::create(path).and_then(|file| labels::write_labels(file, &labels))
+        {
+            error_dlg(
+                self.widgets.as_root(),
+                "Error exporting labels",
+                "Unable to write labels to the file",
+                Some(&err.to_string()),
+            );
         }
+    }
 
-        self.model.set_vsize(vsize);
+    fn change_passphrase(&mut self) {
+        let details = if self.model.is_encrypted() {
+            "The wallet file is encrypted. Leave the passphrase empty to store it unencrypted."
+        } else {
+            "The wallet file is not encrypted. Anyone with access to it can see your extended \
+             public keys, addresses and transaction history."
+        };
+        let passphrase = match passphrase_dlg(
+            self.widgets.as_root(),
+            "Wallet passphrase",
+            "Enter new passphrase for the wallet file",
+            Some(details),
+        ) {
+            Some(passphrase) => passphrase,
+            None => return,
+        };
+        let encrypt = !passphrase.is_empty();
+        if encrypt {
+            let repeated = passphrase_dlg(
+                self.widgets.as_root(),
+                "Wallet passphrase",
+                "Repeat new passphrase",
+                None,
+            );
+            if repeated.as_ref() != Some(&passphrase) {
+                error_dlg(
+                    self.widgets.as_root(),
+                    "Wallet passphrase",
+                    "Passphrases do not match",
+                    Some("The wallet file was left unchanged."),
+                );
+                return;
+            }
+        }
 
-        Ok((psbt, change_index, fee))
+        let passphrase = Some(passphrase).filter(|_| encrypt);
+        match self.model.set_passphrase(passphrase) {
+            Ok(_) => msg_dlg(
+                self.widgets.as_root(),
+                MessageType::Info,
+                "Wallet passphrase",
+                if encrypt {
+                    "Wallet file is now encrypted"
+                } else {
+                    "Wallet file is now stored unencrypted"
+                },
+                None,
+            ),
+            Err(err) => error_dlg(
+                self.widgets.as_root(),
+                "Wallet passphrase",
+                "Unable to save the wallet file",
+                Some(&err.to_string()),
+            ),
+        }
     }
 
-    pub fn sync_pay(&mut self) -> Option<(Psbt, UnhardenedIndex, u32)> {
-        let res = self.compose_psbt();
+    pub fn compose_psbt(&mut self) -> Result<(Psbt, UnhardenedIndex, u32), pay::Error> {
+        self.model.set_selection(None);
 
         let output_count = self.model.beneficiaries().n_items();
-        let mut total = 0u64;
+        let mut payments = Vec::with_capacity(output_count as usize);
         for no in 0..output_count {
             let beneficiary = self
                 .model
@@ -195,17 +291,63 @@ impl Component {
                 .expect("BeneficiaryModel is broken")
                 .downcast::<Beneficiary>()
                 .expect("BeneficiaryModel is broken");
-            total += beneficiary.amount_sats();
+            payments.push(Payment {
+                script_pubkey: beneficiary.address()?.script_pubkey(),
+                amount: beneficiary.amount_sats(),
+                subtract_fee: beneficiary.subtract_fee(),
+            });
         }
+        let request = PaymentRequest {
+            payments,
+            fee_rate: self.model.fee_rate(),
+            coinselect: self.model.coinselect(),
+            manual_coins: self.model.manual_coins().clone(),
+            spending_path: self.model.spending_path(),
+            sighash_type: self.model.sighash_type(),
+        };
+
+        let composition = self
+            .model
+            .as_wallet()
+            .compose_payment(&request, self.model.headers())?;
+        self.model.set_vsize(composition.vsize);
+        self.model.set_selection(Some(composition.selection));
+
+        Ok((
+            composition.psbt,
+            composition.change_index,
+            composition.fee as u32,
+        ))
+    }
+
+    pub fn sync_pay(&mut self) -> Option<(Psbt, UnhardenedIndex, u32)> {
+        let res = self.compose_psbt();
+
+        // Payment amounts may differ from the entered ones when sending all funds or deducting
+        // the fee from the payments, so they are taken from the composed transaction
+        let paid = res.as_ref().ok().and_then(|(_, _, fee)| {
+            let selection = self.model.selection().as_ref()?;
+            let total = selection.value - selection.change.unwrap_or_default() - *fee as u64;
+            Some((total, *fee))
+        });
 
         self.pay_widgets.update_info(
             self.model.fee_rate(),
             self.model.as_wallet().ephemerals().fees,
             self.model.vsize(),
-            res.as_ref().ok().map(|(_, _, fee)| (total, *fee)),
+            paid,
+            self.model.selection().as_ref(),
         );
 
         match res {
+            Ok(data) if self.model.sighash_type() != SighashType::All => {
+                self.pay_widgets.show_warning(&format!(
+                    "Signatures with {} sighash type do not commit to the whole transaction, \
+                     allowing it to be modified after signing",
+                    self.model.sighash_type()
+                ));
+                Some(data)
+            }
             Ok(data) => {
                 self.pay_widgets.hide_message();
                 Some(data)
@@ -254,10 +396,12 @@ impl Component {
                     .update_electrum_state(ElectrumState::RetrievingFees);
                 self.model.as_wallet_mut().update_last_block(&block_info);
                 self.widgets.update_last_block(&block_info);
+                self.update_headers([(block_info.height as u32, block_info.header)]);
             }
             electrum::Msg::LastBlockUpdate(block_info) => {
                 self.model.as_wallet_mut().update_last_block(&block_info);
                 self.widgets.update_last_block(&block_info);
+                self.update_headers([(block_info.height as u32, block_info.header)]);
             }
             electrum::Msg::FeeEstimate(f0, f1, f2) => {
                 self.widgets
@@ -274,9 +418,9 @@ impl Component {
             electrum::Msg::UtxoBatch(batch, no) => {
                 self.widgets
                     .update_electrum_state(ElectrumState::RetrievingHistory(no as usize * 2 + 1));
-                let wallet = self.model.as_wallet_mut();
-                wallet.update_utxos(batch);
-                self.widgets.update_utxos(&wallet.utxos());
+                self.model.as_wallet_mut().update_utxos(batch);
+                let wallet = self.model.as_wallet();
+                self.widgets.update_utxos(wallet, self.model.headers());
                 self.widgets.update_state(
                     wallet.state(),
                     wallet.tx_count(),
@@ -288,17 +432,20 @@ impl Component {
                     .update_electrum_state(ElectrumState::RetrievingTransactions(progress));
                 self.tx_buffer.extend(batch);
             }
+            electrum::Msg::Headers(headers) => self.update_headers(headers),
             electrum::Msg::Complete => {
                 self.model
                     .as_wallet_mut()
                     .update_complete(&self.addr_buffer, &self.tx_buffer);
+                self.model.update_timestamps();
                 self.addr_buffer.clear();
                 self.tx_buffer.clear();
                 self.save();
 
                 let exchange_rate = self.model.exchange_rate;
-                let wallet = self.model.as_wallet_mut();
-                self.widgets.update_history(&wallet.history());
+                let wallet = self.model.as_wallet();
+                self.widgets
+                    .update_history(wallet.history(), self.model.headers());
                 self.widgets
                     .update_state(wallet.state(), wallet.tx_count(), exchange_rate);
                 self.widgets.update_addresses(&wallet.address_info());
@@ -321,12 +468,12 @@ impl Update for Component {
     // Specify the model used for this widget.
     type Model = ViewModel;
     // Specify the model parameter used to init the model.
-    type ModelParam = (Wallet, PathBuf);
+    type ModelParam = (Wallet, PathBuf, Option<String>);
     // Specify the type of the messages sent to the update function.
     type Msg = Msg;
 
     fn model(_relm: &Relm<Self>, param: Self::ModelParam) -> Self::Model {
-        ViewModel::with(param.0, param.1)
+        ViewModel::with(param.0, param.1, param.2)
     }
 
     fn update(&mut self, event: Msg) {
@@ -357,6 +504,9 @@ impl Update for Component {
                     .as_ref()
                     .map(|stream| stream.emit(launch::Msg::ShowPage(launch::Page::Import)));
             }
+            Msg::LabelsImport => self.import_labels(),
+            Msg::LabelsExport => self.export_labels(),
+            Msg::Passphrase => self.change_passphrase(),
             Msg::Close => self.close(),
             Msg::About => {
                 self.launcher_stream
@@ -377,8 +527,28 @@ impl Update for Component {
                 self.handle_exchange(msg);
             }
             Msg::Refresh => {
-                self.electrum_worker.sync();
+                self.electrum_worker
+                    .sync(self.model.as_wallet().history_txids());
+            }
+            Msg::Rescan => {
+                let wallet = self.model.as_wallet();
+                self.widgets.show_rescan(
+                    wallet.as_settings().gap_limit(),
+                    wallet.max_safe_index().first_index() + 1,
+                );
+            }
+            Msg::RescanResponse(ResponseType::Ok) => {
+                self.widgets.hide_rescan();
+                let (gap_limit, depth) = self.widgets.rescan_params();
+                if self.model.as_wallet_mut().update_gap_limit(gap_limit) {
+                    self.save();
+                }
+                self.electrum_worker.update_gap_limit(gap_limit);
+                self.electrum_worker
+                    .rescan(self.model.as_wallet().history_txids(), depth);
+                self.widgets.update_invoice(&self.model);
             }
+            Msg::RescanResponse(_) => self.widgets.hide_rescan(),
             Msg::Update(signers, descriptor_classes, electrum) => {
                 match self
                     .model
@@ -422,6 +592,18 @@ impl Update for Component {
                     .map(|a| *a = sats);
                 self.widgets.update_invoice(&self.model);
             }
+            Msg::InvoiceClass(index) => {
+                let class = index.and_then(|index| {
+                    self.model
+                        .as_settings()
+                        .descriptor_classes()
+                        .iter()
+                        .nth(index as usize)
+                        .copied()
+                });
+                self.model.as_invoice_mut().class = class;
+                self.widgets.update_invoice(&self.model);
+            }
             Msg::InvoiceIndex(index) => {
                 let index = UnhardenedIndex::from_index(index)
                     .expect("unhardened index adjustment requirements broken");
@@ -438,6 +620,45 @@ impl Update for Component {
             Msg::RegisterLauncher(stream) => {
                 self.launcher_stream = Some(stream);
             }
+            Msg::HistoryActivate(path) => {
+                if let Some(txid) = self.widgets.history_txid(&path) {
+                    self.show_bump(txid);
+                }
+            }
+            Msg::BumpResponse(ResponseType::Ok) => {
+                self.widgets.hide_bump();
+                let fee_rate = self.widgets.bump_fee_rate();
+                if let Some((txid, method)) = self.bump_txid.take() {
+                    self.bump_fee(txid, method, fee_rate);
+                }
+            }
+            Msg::BumpResponse(_) => {
+                self.widgets.hide_bump();
+                self.bump_txid = None;
+            }
+            Msg::UtxoFreeze(path) => {
+                let outpoint = match self.widgets.utxo_outpoint(&path) {
+                    Some(outpoint) => outpoint,
+                    None => return,
+                };
+                let wallet = self.model.as_wallet_mut();
+                let frozen = !wallet.is_frozen(outpoint);
+                wallet.set_frozen(outpoint, frozen);
+                self.widgets
+                    .update_utxos(self.model.as_wallet(), self.model.headers());
+                self.save();
+            }
+            Msg::UtxoLabel(path, label) => {
+                let outpoint = match self.widgets.utxo_outpoint(&path) {
+                    Some(outpoint) => outpoint,
+                    None => return,
+                };
+                if self.model.as_wallet_mut().set_utxo_label(outpoint, label) {
+                    self.widgets
+                        .update_utxos(self.model.as_wallet(), self.model.headers());
+                    self.save();
+                }
+            }
             Msg::ElectrumWatch(msg) => self.handle_electrum(msg),
         }
     }
@@ -449,6 +670,9 @@ impl Component {
             pay::Msg::Show => {
                 self.model.beneficiaries_mut().clear();
                 self.model.beneficiaries_mut().append(&Beneficiary::new());
+                self.model.clear_manual_coins();
+                // Wallet settings may have changed since the dialog was shown last time
+                self.model.set_spending_path(self.model.spending_path());
                 self.model
                     .set_fee_rate(self.model.as_wallet().ephemerals().fees.0);
                 self.pay_widgets.init_ui(&self.model);
@@ -463,7 +687,7 @@ impl Component {
                 self.launcher_stream.as_ref().map(|stream| {
                     stream.emit(launch::Msg::CreatePsbt(
                         psbt,
-                        self.model.as_settings().network(),
+                        self.model.as_settings().network().clone(),
                     ))
                 });
                 // Update latest change index in wallet settings by sending message to the wallet
@@ -517,6 +741,43 @@ impl Component {
                 }
                 self.model.set_fee_rate(fee_rate);
             }
+            pay::Msg::CoinSelect => {
+                let strategy = self.pay_widgets.coinselect_strategy();
+                if strategy == self.model.coinselect() {
+                    return;
+                }
+                self.model.set_coinselect(strategy);
+                self.pay_widgets
+                    .show_coins(strategy == CoinSelectStrategy::Manual);
+            }
+            pay::Msg::CoinToggle(path) => match self.pay_widgets.toggle_coin(&path) {
+                Some((outpoint, picked)) => self.model.pick_coin(outpoint, picked),
+                None => return,
+            },
+            pay::Msg::Sighash => {
+                let sighash_type = self.pay_widgets.sighash_type();
+                if sighash_type == self.model.sighash_type() {
+                    return;
+                }
+                self.model.set_sighash_type(sighash_type);
+            }
+            pay::Msg::Condition => {
+                let path = match self.pay_widgets.spending_path() {
+                    Some(path) if path != self.model.spending_path() => path,
+                    _ => return,
+                };
+                self.model.set_spending_path(path);
+                self.model.clear_manual_coins();
+                let paths = self.model.spending_paths();
+                self.pay_widgets
+                    .update_condition_status(paths.get(path).map(|(_, status)| *status));
+                self.pay_widgets.update_coins(
+                    self.model.as_wallet(),
+                    self.model.headers(),
+                    &self.model.path_utxos(),
+                    self.model.manual_coins(),
+                );
+            }
             _ => return, // Changes which do not update wallet tx
         }
 
@@ -541,8 +802,13 @@ impl Widget for Component {
         let stream = relm.stream().clone();
         let (electrum_channel, sender) =
             Channel::new(move |msg| stream.emit(Msg::ElectrumWatch(msg)));
-        let electrum_worker = ElectrumWorker::with(sender, model.as_wallet().to_settings(), 60)
-            .expect("unable to instantiate electrum thread");
+        let electrum_worker = ElectrumWorker::with(
+            sender,
+            model.as_wallet().to_settings(),
+            model.headers().heights(),
+            60,
+        )
+        .expect("unable to instantiate electrum thread");
 
         let stream = relm.stream().clone();
         let (exchange_channel, sender) =
@@ -561,7 +827,7 @@ impl Widget for Component {
         pay_widgets.bind_beneficiary_model(relm, &model);
         pay_widgets.init_ui(&model);
 
-        electrum_worker.sync();
+        electrum_worker.sync(model.as_wallet().history_txids());
 
         Component {
             model,
@@ -575,6 +841,7 @@ impl Widget for Component {
             electrum_worker,
             tx_buffer: empty!(),
             addr_buffer: empty!(),
+            bump_txid: None,
 
             launcher_stream: None,
         }
@@ -16,6 +16,7 @@ mod widget;
 
 use std::collections::BTreeSet;
 
+use gtk::{ResponseType, TreePath};
 use relm::StreamHandle;
 pub(super) use view_model::ViewModel;
 pub(self) use widget::Widgets;
@@ -34,16 +35,26 @@ pub enum Msg {
     About,
     Duplicate,
     Import,
+    LabelsImport,
+    LabelsExport,
+    Passphrase,
     Launch(launch::Msg),
     Settings,
     Update(Vec<Signer>, BTreeSet<DescriptorClass>, ElectrumServer),
     Pay(pay::Msg),
     Fiat(Fiat),
     Refresh,
+    Rescan,
+    RescanResponse(ResponseType),
     InvoiceAmountToggle(bool),
     InvoiceIndexToggle(bool),
     InvoiceAmount(f64),
     InvoiceIndex(u32),
+    InvoiceClass(Option<u32>),
+    HistoryActivate(TreePath),
+    BumpResponse(ResponseType),
+    UtxoFreeze(TreePath),
+    UtxoLabel(TreePath, String),
     ElectrumWatch(electrum::Msg),
     ExchangeRefresh(exchange::Msg),
     RegisterLauncher(StreamHandle<launch::Msg>),
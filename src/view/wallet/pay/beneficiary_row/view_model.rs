@@ -26,6 +26,7 @@ use wallet::scripts::address::AddressCompat;
 pub struct BeneficiaryInner {
     address: RefCell<String>,
     amount: RefCell<u64>,
+    subtract_fee: RefCell<bool>,
 }
 
 // Basic declaration of our type for the GObject type system
@@ -63,6 +64,13 @@ impl ObjectImpl for BeneficiaryInner {
                     0, // Allowed range and default value
                     glib::ParamFlags::READWRITE,
                 ),
+                glib::ParamSpecBoolean::new(
+                    "subtract-fee",
+                    "SubtractFee",
+                    "SubtractFee",
+                    false,
+                    glib::ParamFlags::READWRITE,
+                ),
             ]
         });
 
@@ -89,6 +97,12 @@ impl ObjectImpl for BeneficiaryInner {
                     .expect("type conformity checked by `Object::set_property`");
                 self.amount.replace(amount);
             }
+            "subtract-fee" => {
+                let subtract_fee = value
+                    .get()
+                    .expect("type conformity checked by `Object::set_property`");
+                self.subtract_fee.replace(subtract_fee);
+            }
             _ => unimplemented!(),
         }
     }
@@ -97,6 +111,7 @@ impl ObjectImpl for BeneficiaryInner {
         match pspec.name() {
             "address" => self.address.borrow().to_value(),
             "amount" => self.amount.borrow().to_value(),
+            "subtract-fee" => self.subtract_fee.borrow().to_value(),
             _ => unimplemented!(),
         }
     }
@@ -131,6 +146,9 @@ impl Beneficiary {
     }
 
     pub fn amount_sats(&self) -> u64 { self.property::<u64>("amount") }
+
+    /// Whether the transaction fee is deducted from the payment amount.
+    pub fn subtract_fee(&self) -> bool { self.property::<bool>("subtract-fee") }
 }
 
 #[derive(Debug, Default)]
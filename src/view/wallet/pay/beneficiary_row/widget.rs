@@ -15,7 +15,7 @@ use ::wallet::address::AddressCompat;
 use bitcoin::Address;
 use gladis::Gladis;
 use gtk::prelude::*;
-use gtk::{glib, Entry, ListBoxRow};
+use gtk::{glib, CheckButton, Entry, ListBoxRow};
 use relm::Relm;
 
 use super::Beneficiary;
@@ -27,6 +27,7 @@ pub struct RowWidgets {
     beneficiary_row: ListBoxRow,
     address_fld: Entry,
     amount_fld: Entry,
+    subtract_fee_chk: CheckButton,
 }
 
 impl RowWidgets {
@@ -57,6 +58,13 @@ impl RowWidgets {
             connect_changed(_),
             wallet::Msg::Pay(pay::Msg::BeneficiaryEdit(row.index() as u32))
         );
+        let row = row_widgets.beneficiary_row.clone();
+        connect!(
+            relm,
+            row_widgets.subtract_fee_chk,
+            connect_toggled(_),
+            wallet::Msg::Pay(pay::Msg::BeneficiaryEdit(row.index() as u32))
+        );
 
         row_widgets.beneficiary_row.upcast::<gtk::Widget>()
     }
@@ -149,5 +157,10 @@ impl RowWidgets {
             })
             .flags(flags)
             .build();
+
+        self.subtract_fee_chk
+            .bind_property("active", beneficiary, "subtract-fee")
+            .flags(flags)
+            .build();
     }
 }
@@ -12,11 +12,12 @@
 pub(super) mod beneficiary_row;
 mod widget;
 
-use ::wallet::psbt;
 use bitcoin::util::address;
-use gtk::ResponseType;
+use gtk::{ResponseType, TreePath};
 pub(super) use widget::Widgets;
 
+use crate::model::compose;
+
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
 #[display(doc_comments)]
 pub enum FeeRate {
@@ -39,38 +40,22 @@ pub enum Msg {
     SelectBeneficiary(u32),
     FeeSet,
     FeeSetBlocks(FeeRate),
+    CoinSelect,
+    CoinToggle(TreePath),
+    Condition,
+    Sighash,
     Response(ResponseType),
 }
 
 #[derive(Debug, Display, From, Error)]
 #[display(doc_comments)]
 pub enum Error {
-    /// Available wallet funds are insufficient to cover the transaction
-    InsufficientFunds,
-
     /// One or more of beneficiaries has incorrect address (please see exclamation marks next to
     /// the addresses).
     #[from(address::Error)]
     Address,
 
-    /// Please add at least one beneficiary.
-    NoBeneficiaries,
-
-    /// One or more of payment amounts are zeros.
-    Amount,
-
-    /// Internal error in descriptor; please provide the developer with the following details:
-    ///
-    /// {0}
+    /// Unable to compose transaction: {0}
     #[from]
-    Miniscript(miniscript::Error),
-
-    /// Internal error in PSBT; please provide the developer with the following details:
-    ///
-    /// {0}
-    #[from]
-    PsbtConstruct(psbt::construct::Error),
-
-    /// Unable to compute proper fee
-    FeeFailure,
+    Compose(compose::Error),
 }
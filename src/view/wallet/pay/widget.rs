@@ -9,15 +9,25 @@
 // a copy of the AGPL-3.0 License along with this software. If not, see
 // <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
 
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use bitcoin::OutPoint;
 use gladis::Gladis;
 use gtk::prelude::*;
 use gtk::{
-    Adjustment, Box, Button, Dialog, HeaderBar, Image, Label, ListBox, ListBoxRow, Menu, MenuItem,
-    PositionType, ResponseType, Scale, SpinButton, ToolButton,
+    Adjustment, Box, Button, CellRendererToggle, ComboBoxText, Dialog, HeaderBar, Image, Label,
+    ListBox, ListBoxRow, ListStore, Menu, MenuItem, PositionType, ResponseType, Scale,
+    ScrolledWindow, SpinButton, ToolButton, TreePath,
 };
 use relm::Relm;
 
 use super::{beneficiary_row, FeeRate, Msg};
+use crate::model::psbt::SighashType;
+use crate::model::{
+    CoinSelectStrategy, CoinSelection, HeaderIndex, PathStatus, SpendingCondition, UtxoTxid,
+    Wallet,
+};
 use crate::view::{wallet, NotificationBoxExt};
 
 // Create the structure that holds the widgets used in the view.
@@ -49,6 +59,17 @@ pub struct Widgets {
     block1_mi: MenuItem,
     block2_mi: MenuItem,
     block3_mi: MenuItem,
+
+    condition_cmb: ComboBoxText,
+    condition_lbl: Label,
+    sighash_cmb: ComboBoxText,
+
+    coinselect_cmb: ComboBoxText,
+    waste_lbl: Label,
+
+    coin_box: ScrolledWindow,
+    coin_store: ListStore,
+    coin_toggle: CellRendererToggle,
 }
 
 impl Widgets {
@@ -62,7 +83,27 @@ impl Widgets {
         self.fee_adj.set_upper(fees.0 as f64 * 2.0);
         self.fee_adj.set_lower(fees.2 as f64 / 10.0);
 
-        self.update_info(model.fee_rate(), fees, model.vsize(), None);
+        self.update_conditions(&model.spending_paths(), model.spending_path());
+        let sighash = SighashType::all()
+            .iter()
+            .position(|s| *s == model.sighash_type())
+            .unwrap_or_default();
+        self.sighash_cmb.set_active(Some(sighash as u32));
+
+        let coinselect = CoinSelectStrategy::all()
+            .iter()
+            .position(|s| *s == model.coinselect())
+            .unwrap_or_default();
+        self.coinselect_cmb.set_active(Some(coinselect as u32));
+        self.show_coins(model.coinselect() == CoinSelectStrategy::Manual);
+        self.update_coins(
+            model.as_wallet(),
+            model.headers(),
+            &model.path_utxos(),
+            model.manual_coins(),
+        );
+
+        self.update_info(model.fee_rate(), fees, model.vsize(), None, None);
     }
 
     pub fn show(&self) { self.dialog.show() }
@@ -135,6 +176,30 @@ impl Widgets {
             connect_activate(_),
             wallet::Msg::Pay(Msg::FeeSetBlocks(FeeRate::ThreeBlocks))
         );
+        connect!(
+            relm,
+            self.condition_cmb,
+            connect_changed(_),
+            wallet::Msg::Pay(Msg::Condition)
+        );
+        connect!(
+            relm,
+            self.sighash_cmb,
+            connect_changed(_),
+            wallet::Msg::Pay(Msg::Sighash)
+        );
+        connect!(
+            relm,
+            self.coinselect_cmb,
+            connect_changed(_),
+            wallet::Msg::Pay(Msg::CoinSelect)
+        );
+        connect!(
+            relm,
+            self.coin_toggle,
+            connect_toggled(_, path),
+            wallet::Msg::Pay(Msg::CoinToggle(path))
+        );
     }
 
     pub fn bind_beneficiary_model(
@@ -143,10 +208,10 @@ impl Widgets {
         model: &wallet::ViewModel,
     ) {
         let relm = relm.clone();
-        let network = model.as_settings().network();
+        let network = model.as_settings().network().clone();
         self.beneficiary_list
             .bind_model(Some(model.beneficiaries()), move |item| {
-                beneficiary_row::RowWidgets::init(relm.clone(), item, network)
+                beneficiary_row::RowWidgets::init(relm.clone(), item, network.clone())
             });
     }
 
@@ -156,6 +221,7 @@ impl Widgets {
         fees: (f32, f32, f32),
         vsize: f32,
         paid: Option<(u64, u32)>,
+        selection: Option<&CoinSelection>,
     ) {
         self.prepare_btn.set_sensitive(paid.is_some());
 
@@ -177,6 +243,14 @@ impl Widgets {
             self.total_lbl.set_text("unknown");
         }
 
+        match selection {
+            Some(selection) => self.waste_lbl.set_text(&format!(
+                "{}, waste {} sats",
+                selection.strategy, selection.waste
+            )),
+            None => self.waste_lbl.set_text("-"),
+        }
+
         if self.fee_adj.value() as f32 != fee_rate {
             self.fee_adj.set_value(fee_rate as f64);
         }
@@ -201,6 +275,85 @@ impl Widgets {
 
     pub fn fee_rate(&self) -> f64 { self.fee_adj.value() }
 
+    pub fn coinselect_strategy(&self) -> CoinSelectStrategy {
+        self.coinselect_cmb
+            .active()
+            .and_then(|index| CoinSelectStrategy::all().get(index as usize))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Lists wallet spending conditions, selecting condition number `active`. The list is
+    /// disabled for wallets having a single spending condition.
+    pub fn update_conditions(&self, paths: &[(SpendingCondition, PathStatus)], active: usize) {
+        self.condition_cmb.remove_all();
+        for (condition, status) in paths {
+            let mark = if status.is_available() {
+                ""
+            } else {
+                " (locked)"
+            };
+            self.condition_cmb
+                .append_text(&format!("{}{}", condition, mark));
+        }
+        self.condition_cmb.set_sensitive(paths.len() > 1);
+        self.condition_cmb.set_active(Some(active as u32));
+        self.update_condition_status(paths.get(active).map(|(_, status)| *status));
+    }
+
+    pub fn update_condition_status(&self, status: Option<PathStatus>) {
+        match status {
+            Some(status) => self.condition_lbl.set_text(&status.to_string()),
+            None => self.condition_lbl.set_text("-"),
+        }
+    }
+
+    pub fn spending_path(&self) -> Option<usize> {
+        self.condition_cmb.active().map(|index| index as usize)
+    }
+
+    pub fn sighash_type(&self) -> SighashType {
+        self.sighash_cmb
+            .active()
+            .and_then(|index| SighashType::all().get(index as usize))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn show_coins(&self, show: bool) { self.coin_box.set_visible(show) }
+
+    /// Lists coins which can be picked for the manual coin selection.
+    pub fn update_coins(
+        &self,
+        wallet: &Wallet,
+        headers: &HeaderIndex,
+        utxos: &[UtxoTxid],
+        picked: &BTreeSet<OutPoint>,
+    ) {
+        self.coin_store.clear();
+        for utxo in utxos {
+            let outpoint = utxo.outpoint();
+            self.coin_store.insert_with_values(None, &[
+                (0, &picked.contains(&outpoint)),
+                (1, &outpoint.to_string()),
+                (2, &utxo.addr_src.address.to_string()),
+                (3, &format!("{:.08}", utxo.value as f64 / 100_000_000.0)),
+                (4, &wallet.utxo_label(outpoint).unwrap_or_default()),
+                (5, &utxo.mining_info(headers)),
+            ]);
+        }
+    }
+
+    /// Flips the "spend" mark of a coin, returning the coin and its new state.
+    pub fn toggle_coin(&self, path: &TreePath) -> Option<(OutPoint, bool)> {
+        let iter = self.coin_store.iter(path)?;
+        let picked = !self.coin_store.value(&iter, 0).get::<bool>().ok()?;
+        let outpoint = self.coin_store.value(&iter, 1).get::<String>().ok()?;
+        let outpoint = OutPoint::from_str(&outpoint).ok()?;
+        self.coin_store.set_value(&iter, 0, &picked.to_value());
+        Some((outpoint, picked))
+    }
+
     pub fn select_beneficiary(&self, index: u32) {
         self.beneficiary_list
             .select_row(self.beneficiary_list.row_at_index(index as i32).as_ref())
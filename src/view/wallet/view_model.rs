@@ -12,12 +12,15 @@
 use std::collections::BTreeSet;
 use std::path::PathBuf;
 
+use bitcoin::{BlockHeader, OutPoint};
 use wallet::hd::UnhardenedIndex;
 
 use super::pay::beneficiary_row::BeneficiaryModel;
+use crate::model::psbt::SighashType;
 use crate::model::{
-    file, DescriptorClass, DescriptorError, ElectrumServer, FileDocument, Signer, Wallet,
-    WalletSettings,
+    file, BlockTime, CoinSelectStrategy, CoinSelection, DescriptorClass, DescriptorError,
+    ElectrumServer, FileDocument, HeaderIndex, PathStatus, Signer, SpendingCondition, UtxoTxid,
+    Wallet, WalletSettings,
 };
 use crate::worker::exchange::{Exchange, Fiat};
 
@@ -25,6 +28,8 @@ use crate::worker::exchange::{Exchange, Fiat};
 pub struct InvoiceModel {
     pub amount: Option<u64>,
     pub index: Option<UnhardenedIndex>,
+    /// Address type; uses the primary descriptor class of the wallet if not set.
+    pub class: Option<DescriptorClass>,
 }
 
 #[derive(Getters)]
@@ -33,6 +38,13 @@ pub struct ViewModel {
 
     path: PathBuf,
 
+    /// Passphrase used to encrypt the wallet file; the file is stored unencrypted if absent.
+    #[getter(skip)]
+    passphrase: Option<String>,
+
+    /// Timestamps of the blocks relevant to the wallet, cached next to the wallet file.
+    headers: HeaderIndex,
+
     #[getter(as_mut)]
     beneficiaries: BeneficiaryModel,
 
@@ -42,6 +54,20 @@ pub struct ViewModel {
     #[getter(as_copy)]
     vsize: f32,
 
+    #[getter(as_copy)]
+    coinselect: CoinSelectStrategy, // Used by payment window
+
+    selection: Option<CoinSelection>,
+
+    manual_coins: BTreeSet<OutPoint>, // Used by payment window
+
+    /// Index of the spending condition used to spend wallet funds, in DFS order.
+    #[getter(as_copy)]
+    spending_path: usize, // Used by payment window
+
+    #[getter(as_copy)]
+    sighash_type: SighashType, // Used by payment window
+
     #[getter(skip)]
     invoice: InvoiceModel,
 
@@ -56,12 +82,21 @@ pub struct ViewModel {
 }
 
 impl ViewModel {
-    pub fn with(wallet: Wallet, path: PathBuf) -> ViewModel {
+    pub fn with(wallet: Wallet, path: PathBuf, passphrase: Option<String>) -> ViewModel {
+        // The index is a cache, so a damaged one is simply rebuilt with the next sync
+        let headers = HeaderIndex::read_for(&path, passphrase.as_deref()).unwrap_or_default();
         ViewModel {
             fee_rate: wallet.ephemerals().fees.0 * 100_000_000.0, // TODO: Update on window opening
             vsize: 0.0,
+            coinselect: default!(),
+            selection: None,
+            manual_coins: bset![],
+            spending_path: 0,
+            sighash_type: default!(),
             wallet,
             path,
+            passphrase,
+            headers,
             beneficiaries: BeneficiaryModel::new(),
             invoice: none!(),
             exchange: Exchange::Kraken,
@@ -70,7 +105,36 @@ impl ViewModel {
         }
     }
 
-    pub fn save(&mut self) -> Result<usize, file::Error> { self.wallet.write_file(&self.path) }
+    pub fn save(&mut self) -> Result<usize, file::Error> {
+        self.wallet
+            .write_file_with(&self.path, self.passphrase.as_deref())
+    }
+
+    /// Adds block headers to the index, saving it if changed.
+    pub fn update_headers(
+        &mut self,
+        headers: impl IntoIterator<Item = (u32, BlockHeader)>,
+    ) -> Result<(), file::Error> {
+        if self.headers.extend(headers) {
+            self.headers
+                .write_for(&self.path, self.passphrase.as_deref())?;
+        }
+        Ok(())
+    }
+
+    pub fn update_timestamps(&mut self) { self.wallet.update_timestamps(&self.headers); }
+
+    pub fn is_encrypted(&self) -> bool { self.passphrase.is_some() }
+
+    /// Changes (or removes, if `None` is given) the wallet file passphrase and re-saves the file,
+    /// dropping backups made with the previous passphrase.
+    pub fn set_passphrase(&mut self, passphrase: Option<String>) -> Result<usize, file::Error> {
+        self.passphrase = passphrase;
+        self.headers
+            .write_for(&self.path, self.passphrase.as_deref())?;
+        self.wallet
+            .rekey_file(&self.path, self.passphrase.as_deref())
+    }
 
     pub fn as_wallet(&self) -> &Wallet { &self.wallet }
     pub fn as_wallet_mut(&mut self) -> &mut Wallet { &mut self.wallet }
@@ -86,6 +150,70 @@ impl ViewModel {
 
     pub fn set_fee_rate(&mut self, fee_rate: f32) { self.fee_rate = fee_rate; }
 
+    pub fn set_coinselect(&mut self, strategy: CoinSelectStrategy) { self.coinselect = strategy; }
+
+    pub fn set_sighash_type(&mut self, sighash_type: SighashType) {
+        self.sighash_type = sighash_type;
+    }
+
+    pub fn set_selection(&mut self, selection: Option<CoinSelection>) {
+        self.selection = selection;
+    }
+
+    pub fn clear_manual_coins(&mut self) { self.manual_coins.clear(); }
+
+    pub fn pick_coin(&mut self, outpoint: OutPoint, picked: bool) {
+        match picked {
+            true => self.manual_coins.insert(outpoint),
+            false => self.manual_coins.remove(&outpoint),
+        };
+    }
+
+    /// Selects spending condition number `path`, falling back to the first condition if the
+    /// wallet has no such condition.
+    pub fn set_spending_path(&mut self, path: usize) {
+        self.spending_path = if path < self.as_settings().spending_paths().len() {
+            path
+        } else {
+            0
+        };
+    }
+
+    pub fn spending_condition(&self) -> SpendingCondition {
+        self.as_settings()
+            .spending_paths()
+            .get(self.spending_path)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Last known block, with its timestamp taken from the header index when possible.
+    pub fn tip(&self) -> BlockTime { BlockTime::with(self.wallet.height(), &self.headers) }
+
+    /// Wallet spending conditions with their current availability.
+    pub fn spending_paths(&self) -> Vec<(SpendingCondition, PathStatus)> {
+        let tip = self.tip();
+        self.as_settings()
+            .spending_paths()
+            .into_iter()
+            .map(|condition| {
+                let status = self
+                    .wallet
+                    .path_status(condition.timelock(), tip, &self.headers);
+                (condition, status)
+            })
+            .collect()
+    }
+
+    /// Coins which can be spent with the selected spending condition.
+    pub fn path_utxos(&self) -> Vec<UtxoTxid> {
+        self.wallet.path_utxos(
+            self.spending_condition().timelock(),
+            self.tip(),
+            &self.headers,
+        )
+    }
+
     pub fn update_descriptor(
         &mut self,
         signers: Vec<Signer>,
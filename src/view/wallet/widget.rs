@@ -11,22 +11,26 @@
 
 use std::collections::BTreeSet;
 use std::ffi::OsStr;
+use std::str::FromStr;
 
+use bitcoin::{OutPoint, Txid};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use electrum_client::HeaderNotification;
 use gladis::Gladis;
 use gtk::gdk_pixbuf::Pixbuf;
 use gtk::prelude::*;
 use gtk::{
-    gdk, Adjustment, ApplicationWindow, Button, CheckButton, Entry, HeaderBar, Image, Label,
-    ListStore, MenuItem, Popover, RadioMenuItem, SpinButton, Spinner, Statusbar, TreeView,
+    gdk, Adjustment, ApplicationWindow, Button, CellRendererText, CellRendererToggle, CheckButton,
+    ComboBoxText, Dialog, Entry, HeaderBar, Image, Label, ListStore, MenuItem, Popover,
+    RadioMenuItem, SpinButton, Spinner, Statusbar, TreePath, TreeView,
 };
 use relm::Relm;
 use wallet::hd::SegmentIndexes;
 
 use super::{pay, ElectrumState, Msg, ViewModel};
 use crate::model::{
-    AddressSummary, ElectrumSec, ElectrumServer, HistoryEntry, UtxoTxid, WalletState,
+    AddressSummary, BumpMethod, ElectrumSec, ElectrumServer, HeaderIndex, HistoryEntry, Wallet,
+    WalletState,
 };
 use crate::view::{launch, APP_ICON, APP_ICON_TOOL};
 use crate::worker::exchange::{Exchange, Fiat};
@@ -62,6 +66,10 @@ pub struct Widgets {
     pay_btn: Button,
     redefine_mi: MenuItem,
     import_mi: MenuItem,
+    labels_import_mi: MenuItem,
+    labels_export_mi: MenuItem,
+    rescan_mi: MenuItem,
+    passphrase_mi: MenuItem,
     settings_mi: MenuItem,
     launcher_mi: MenuItem,
     about_mi: MenuItem,
@@ -93,6 +101,8 @@ pub struct Widgets {
     address_list: TreeView,
     utxo_list: TreeView,
     history_list: TreeView,
+    utxo_frozen: CellRendererToggle,
+    utxo_label: CellRendererText,
 
     status_bar: Statusbar,
     status_lbl: Label,
@@ -112,7 +122,17 @@ pub struct Widgets {
     index_stp: SpinButton,
     index_adj: Adjustment,
     index_img: Image,
+    address_type_cmb: ComboBoxText,
     address_fld: Entry,
+
+    rescan_dlg: Dialog,
+    gap_adj: Adjustment,
+    depth_adj: Adjustment,
+
+    bump_dlg: Dialog,
+    bump_adj: Adjustment,
+    bump_rate_lbl: Label,
+    bump_info_lbl: Label,
 }
 
 impl Widgets {
@@ -136,6 +156,25 @@ impl Widgets {
         connect!(relm, self.refresh_btn, connect_clicked(_), Msg::Refresh);
         connect!(relm, self.redefine_mi, connect_activate(_), Msg::Duplicate);
         connect!(relm, self.import_mi, connect_activate(_), Msg::Import);
+        connect!(
+            relm,
+            self.labels_import_mi,
+            connect_activate(_),
+            Msg::LabelsImport
+        );
+        connect!(
+            relm,
+            self.labels_export_mi,
+            connect_activate(_),
+            Msg::LabelsExport
+        );
+        connect!(relm, self.rescan_mi, connect_activate(_), Msg::Rescan);
+        connect!(
+            relm,
+            self.passphrase_mi,
+            connect_activate(_),
+            Msg::Passphrase
+        );
         connect!(relm, self.settings_mi, connect_activate(_), Msg::Settings);
         connect!(
             relm,
@@ -188,6 +227,49 @@ impl Widgets {
             connect_value_changed(adj),
             Msg::InvoiceIndex(adj.value() as u32)
         );
+        connect!(
+            relm,
+            self.address_type_cmb,
+            connect_changed(cmb),
+            Msg::InvoiceClass(cmb.active())
+        );
+
+        connect!(
+            relm,
+            self.utxo_frozen,
+            connect_toggled(_, path),
+            Msg::UtxoFreeze(path)
+        );
+        connect!(
+            relm,
+            self.utxo_label,
+            connect_edited(_, path, label),
+            Msg::UtxoLabel(path, label.to_owned())
+        );
+
+        connect!(
+            relm,
+            self.rescan_dlg,
+            connect_response(_, resp),
+            Msg::RescanResponse(resp)
+        );
+        self.rescan_dlg
+            .connect_delete_event(|dlg, _| dlg.hide_on_delete());
+
+        connect!(
+            relm,
+            self.history_list,
+            connect_row_activated(_, path, _),
+            Msg::HistoryActivate(path.clone())
+        );
+        connect!(
+            relm,
+            self.bump_dlg,
+            connect_response(_, resp),
+            Msg::BumpResponse(resp)
+        );
+        self.bump_dlg
+            .connect_delete_event(|dlg, _| dlg.hide_on_delete());
 
         self.address_fld.connect_icon_press(|entry, _, _| {
             let val = entry.text();
@@ -222,6 +304,13 @@ impl Widgets {
         self.fiat_eur.set_active(model.fiat == Fiat::EUR);
         self.fiat_chf.set_active(model.fiat == Fiat::CHF);
 
+        for class in settings.descriptor_classes() {
+            self.address_type_cmb.append_text(&class.to_string());
+        }
+        self.address_type_cmb.set_active(Some(0));
+        self.address_type_cmb
+            .set_sensitive(settings.descriptor_classes().len() > 1);
+
         self.update_invoice(model);
     }
 
@@ -229,7 +318,10 @@ impl Widgets {
         let invoice = model.as_invoice();
         let wallet = model.as_wallet();
         let next_index = wallet.next_default_index();
-        let address = wallet.indexed_address(invoice.index.unwrap_or(next_index));
+        let class = invoice
+            .class
+            .unwrap_or_else(|| wallet.as_settings().primary_class());
+        let address = wallet.indexed_address(class, invoice.index.unwrap_or(next_index));
         let index_reuse = invoice.index.unwrap_or(next_index) >= next_index;
 
         self.amount_chk.set_active(invoice.amount.is_some());
@@ -238,7 +330,7 @@ impl Widgets {
         self.index_chk.set_active(invoice.index.is_some());
         self.index_stp.set_sensitive(invoice.index.is_some());
         self.index_adj
-            .set_upper((next_index.first_index() + 19) as f64);
+            .set_upper(wallet.max_safe_index().first_index() as f64);
         self.index_adj
             .set_value(invoice.index.unwrap_or(next_index).first_index() as f64);
         self.index_img.set_visible(!index_reuse);
@@ -255,6 +347,45 @@ impl Widgets {
         self.address_fld.set_text(&invoice_str);
     }
 
+    pub fn show_rescan(&self, gap_limit: u16, depth: u32) {
+        self.gap_adj.set_value(gap_limit as f64);
+        self.depth_adj.set_value(depth as f64);
+        self.rescan_dlg.show();
+    }
+
+    pub fn hide_rescan(&self) { self.rescan_dlg.hide() }
+
+    /// Returns gap limit and scan depth chosen by the user.
+    pub fn rescan_params(&self) -> (u16, u16) {
+        (self.gap_adj.value() as u16, self.depth_adj.value() as u16)
+    }
+
+    /// Shows fee bump dialog for a transaction paying `fee_rate` (if known), proposing
+    /// `suggested` fee rate.
+    pub fn show_bump(&self, method: BumpMethod, fee_rate: Option<f32>, suggested: f32) {
+        self.bump_info_lbl.set_text(match method {
+            BumpMethod::ReplaceByFee => {
+                "The transaction will be replaced with a new one paying the same beneficiaries a \
+                 higher fee. The fee increase is taken from the change and, if necessary, from \
+                 additional wallet funds."
+            }
+            BumpMethod::ChildPaysForParent => {
+                "A new transaction will send the received funds to a wallet change address, paying \
+                 enough fee for both transactions to be mined at the target fee rate."
+            }
+        });
+        self.bump_rate_lbl.set_text(&match fee_rate {
+            Some(fee_rate) => format!("{:.1} sat/vbyte", fee_rate),
+            None => s!("unknown"),
+        });
+        self.bump_adj.set_value(suggested as f64);
+        self.bump_dlg.show();
+    }
+
+    pub fn hide_bump(&self) { self.bump_dlg.hide() }
+
+    pub fn bump_fee_rate(&self) -> f32 { self.bump_adj.value() as f32 }
+
     pub fn update_electrum_server(&self, electrum: &ElectrumServer) {
         self.status_lbl
             .set_text(&"New electrum server, please refresh");
@@ -319,7 +450,7 @@ impl Widgets {
         self.height_lbl.set_text(&last_block.height.to_string());
     }
 
-    pub fn update_history(&mut self, history: &BTreeSet<HistoryEntry>) {
+    pub fn update_history(&mut self, history: &BTreeSet<HistoryEntry>, headers: &HeaderIndex) {
         self.history_store.clear();
         let mut balance = 0i64;
         for item in history {
@@ -331,25 +462,41 @@ impl Widgets {
                 (1, &item.onchain.txid.to_string()),
                 (2, &btc),
                 (3, &btc_balance),
-                (4, &item.mining_info()),
+                (4, &item.mining_info(headers)),
                 (5, &item.color()),
             ]);
         }
     }
 
-    pub fn update_utxos(&mut self, utxos: &BTreeSet<UtxoTxid>) {
+    pub fn update_utxos(&mut self, wallet: &Wallet, headers: &HeaderIndex) {
         self.utxo_store.clear();
-        for item in utxos {
+        for item in wallet.utxos() {
             let btc = format_btc_value(item.value);
+            let outpoint = item.outpoint();
             self.utxo_store.insert_with_values(None, &[
                 (0, &item.addr_src.address.to_string()),
                 (1, &item.onchain.txid.to_string()),
                 (2, &btc),
-                (3, &item.mining_info()),
+                (3, &item.mining_info(headers)),
+                (4, &wallet.is_frozen(outpoint)),
+                (5, &wallet.utxo_label(outpoint).unwrap_or_default()),
+                (6, &outpoint.to_string()),
             ]);
         }
     }
 
+    pub fn history_txid(&self, path: &TreePath) -> Option<Txid> {
+        let iter = self.history_store.iter(path)?;
+        let txid = self.history_store.value(&iter, 1).get::<String>().ok()?;
+        Txid::from_str(&txid).ok()
+    }
+
+    pub fn utxo_outpoint(&self, path: &TreePath) -> Option<OutPoint> {
+        let iter = self.utxo_store.iter(path)?;
+        let outpoint = self.utxo_store.value(&iter, 6).get::<String>().ok()?;
+        OutPoint::from_str(&outpoint).ok()
+    }
+
     pub fn update_addresses(&mut self, address_info: &[AddressSummary]) {
         self.address_store.clear();
         for info in address_info {
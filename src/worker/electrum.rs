@@ -16,22 +16,27 @@ use std::time::Duration;
 use std::{io, thread};
 
 use amplify::Wrapper;
-use bitcoin::Transaction;
+use bitcoin::{BlockHeader, Transaction, Txid};
 use electrum_client::{
     Client as ElectrumClient, ElectrumApi, GetHistoryRes, HeaderNotification, ListUnspentRes,
 };
-use relm::Sender;
-use wallet::hd::{SegmentIndexes, UnhardenedIndex};
+use wallet::hd::SegmentIndexes;
 use wallet::scripts::PubkeyScript;
 
 use crate::model::{
-    AddressSource, ElectrumServer, OnchainStatus, OnchainTxid, UtxoTxid, WalletSettings,
+    AddressSource, ElectrumServer, HeaderIndex, OnchainStatus, OnchainTxid, UtxoTxid, Wallet,
+    WalletSettings,
 };
+use crate::worker::MsgSender;
+
+/// Number of addresses requested from the electrum server in a single batch.
+const BATCH_SIZE: u16 = 20;
 
 enum Cmd {
-    Sync,
+    Sync(BTreeSet<Txid>, u16),
     Pull,
     Update(ElectrumServer),
+    GapLimit(u16),
 }
 
 pub enum Msg {
@@ -44,6 +49,7 @@ pub enum Msg {
     TxidBatch(BTreeMap<AddressSource, BTreeSet<TxidMeta>>, u16),
     UtxoBatch(BTreeSet<UtxoTxid>, u16),
     TxBatch(Vec<Transaction>, f32),
+    Headers(BTreeMap<u32, BlockHeader>),
     ChannelDisconnected,
     Error(electrum_client::Error),
 }
@@ -104,9 +110,12 @@ pub struct ElectrumWorker {
 }
 
 impl ElectrumWorker {
+    /// Starts the worker. Headers of the blocks with `known_heights` are not requested from the
+    /// server.
     pub fn with(
-        sender: Sender<Msg>,
+        sender: impl MsgSender<Msg>,
         mut wallet_settings: WalletSettings,
+        mut known_heights: BTreeSet<u32>,
         interval: u64,
     ) -> Result<Self, io::Error> {
         let (tx, rx) = mpsc::channel::<Cmd>();
@@ -120,9 +129,18 @@ impl ElectrumWorker {
                         client = electrum_init(wallet_settings.electrum(), &sender);
                         Ok(())
                     }
-                    (Some(client), Ok(Cmd::Sync)) => {
-                        electrum_sync(&client, &wallet_settings, &sender)
+                    (_, Ok(Cmd::GapLimit(gap_limit))) => {
+                        wallet_settings.update_gap_limit(gap_limit);
+                        Ok(())
                     }
+                    (Some(client), Ok(Cmd::Sync(known_txids, depth))) => electrum_sync(
+                        client,
+                        &wallet_settings,
+                        &known_txids,
+                        &mut known_heights,
+                        depth,
+                        &sender,
+                    ),
                     (Some(client), Ok(Cmd::Pull)) => client.block_headers_pop().map(|res| {
                         if let Some(last_block) = res {
                             sender
@@ -165,7 +183,17 @@ impl ElectrumWorker {
         })
     }
 
-    pub fn sync(&self) { self.cmd(Cmd::Sync) }
+    /// Synchronizes wallet history, downloading only transactions which are not in
+    /// `known_txids`.
+    pub fn sync(&self, known_txids: BTreeSet<Txid>) { self.cmd(Cmd::Sync(known_txids, 0)) }
+
+    /// Synchronizes wallet history scanning both keychains at least up to `depth` addresses,
+    /// and continuing with the gap limit after that.
+    pub fn rescan(&self, known_txids: BTreeSet<Txid>, depth: u16) {
+        self.cmd(Cmd::Sync(known_txids, depth))
+    }
+
+    pub fn update_gap_limit(&self, gap_limit: u16) { self.cmd(Cmd::GapLimit(gap_limit)) }
 
     pub fn pull(&self) { self.cmd(Cmd::Pull) }
 
@@ -182,7 +210,60 @@ pub fn electrum_connect(url: &str) -> Result<ElectrumClient, electrum_client::Er
     ElectrumClient::from_config(url, config)
 }
 
-fn electrum_init(electrum: &ElectrumServer, sender: &Sender<Msg>) -> Option<ElectrumClient> {
+/// Synchronizes the wallet in the current thread, blocking until the synchronization is
+/// complete. Unlike [`ElectrumWorker`], does not require an event loop, so it can be used by
+/// headless applications. Block headers which are not known yet are added to the `headers`
+/// index.
+pub fn sync_blocking(
+    wallet: &mut Wallet,
+    headers: &mut HeaderIndex,
+    depth: u16,
+) -> Result<(), electrum_client::Error> {
+    let settings = wallet.to_settings();
+    let client = electrum_connect(&settings.electrum().to_string())?;
+    let (sender, receiver) = mpsc::channel();
+    electrum_sync(
+        &client,
+        &settings,
+        &wallet.history_txids(),
+        &mut headers.heights(),
+        depth,
+        &sender,
+    )?;
+    drop(sender);
+
+    let mut addr_buffer = bmap![];
+    let mut tx_buffer = vec![];
+    for msg in receiver {
+        match msg {
+            Msg::LastBlock(last_block) | Msg::LastBlockUpdate(last_block) => {
+                wallet.update_last_block(&last_block);
+                headers.insert(last_block.height as u32, &last_block.header);
+            }
+            Msg::FeeEstimate(f0, f1, f2) => {
+                wallet.update_fees(f0, f1, f2);
+                wallet.clear_utxos();
+            }
+            Msg::TxidBatch(batch, _) => addr_buffer.extend(batch),
+            Msg::UtxoBatch(batch, _) => wallet.update_utxos(batch),
+            Msg::TxBatch(batch, _) => tx_buffer.extend(batch),
+            Msg::Headers(batch) => {
+                headers.extend(batch);
+            }
+            Msg::Complete => {
+                wallet.update_complete(&addr_buffer, &tx_buffer);
+                wallet.update_timestamps(headers);
+            }
+            Msg::Connecting | Msg::Connected | Msg::ChannelDisconnected | Msg::Error(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn electrum_init(
+    electrum: &ElectrumServer,
+    sender: &impl MsgSender<Msg>,
+) -> Option<ElectrumClient> {
     electrum_connect(&electrum.to_string())
         .map_err(|err| {
             sender
@@ -195,7 +276,10 @@ fn electrum_init(electrum: &ElectrumServer, sender: &Sender<Msg>) -> Option<Elec
 fn electrum_sync(
     client: &ElectrumClient,
     wallet_settings: &WalletSettings,
-    sender: &Sender<Msg>,
+    known_txids: &BTreeSet<Txid>,
+    known_heights: &mut BTreeSet<u32>,
+    depth: u16,
+    sender: &impl MsgSender<Msg>,
 ) -> Result<(), electrum_client::Error> {
     sender
         .send(Msg::Connecting)
@@ -217,21 +301,31 @@ fn electrum_sync(
 
     let network = bitcoin::Network::from(wallet_settings.network());
 
+    let gap_limit = wallet_settings.gap_limit();
     let mut txids = bset![];
-    let mut upto_index = map! { true => UnhardenedIndex::zero(), false => UnhardenedIndex::zero() };
-    for change in [false, true] {
+    let mut heights = bset![];
+    let keychains = wallet_settings
+        .descriptor_classes()
+        .iter()
+        .flat_map(|class| [(*class, false), (*class, true)]);
+    for (class, change) in keychains {
         let mut offset = 0u16;
-        let mut upto = UnhardenedIndex::zero();
-        *upto_index.entry(change).or_default() = loop {
+        // Index following the last used address
+        let mut unused = 0u16;
+        while offset < unused.saturating_add(gap_limit) || offset < depth {
             let spk = wallet_settings
-                .script_pubkeys(change, offset..=(offset + 19))
+                .script_pubkeys(
+                    class,
+                    change,
+                    offset..=offset.saturating_add(BATCH_SIZE - 1),
+                )
                 .map_err(|err| electrum_client::Error::Message(err.to_string()))?;
             let batch = client
                 .batch_script_get_history(spk.values().map(PubkeyScript::as_inner))?
                 .into_iter()
                 .zip(&spk)
                 .map(|(history, (index, script))| {
-                    let addr_src = AddressSource::with(script, *index, change, network);
+                    let addr_src = AddressSource::with(script, *index, change, class, network);
                     let txids = history
                         .into_iter()
                         .map(TxidMeta::from)
@@ -244,16 +338,21 @@ fn electrum_sync(
                 .values()
                 .flat_map(|item| item.iter().map(|meta| meta.onchain.txid))
                 .collect::<Vec<_>>();
-            if new_txids.is_empty() {
-                break upto;
-            } else {
-                upto = batch
-                    .keys()
-                    .map(|item| item.index)
-                    .max()
-                    .unwrap_or_default();
+            if let Some(last_used) = batch
+                .iter()
+                .filter(|(_, txids)| !txids.is_empty())
+                .map(|(addr_src, _)| addr_src.index.first_index() as u16)
+                .max()
+            {
+                unused = unused.max(last_used + 1);
             }
             txids.extend(new_txids);
+            heights.extend(
+                batch
+                    .values()
+                    .flatten()
+                    .filter_map(|meta| meta.onchain.status.height()),
+            );
             sender
                 .send(Msg::TxidBatch(batch, offset))
                 .expect("electrum watcher channel is broken");
@@ -264,7 +363,8 @@ fn electrum_sync(
                 .zip(spk)
                 .flat_map(|(utxo, (index, script))| {
                     utxo.into_iter().map(move |res| {
-                        UtxoTxid::with(res, AddressSource::with(&script, index, change, network))
+                        let addr_src = AddressSource::with(&script, index, change, class, network);
+                        UtxoTxid::with(res, addr_src)
                     })
                 })
                 .collect::<BTreeSet<_>>();
@@ -273,11 +373,19 @@ fn electrum_sync(
                 .send(Msg::UtxoBatch(utxos, offset))
                 .expect("electrum watcher channel is broken");
 
-            offset += 20;
-        };
+            offset = match offset.checked_add(BATCH_SIZE) {
+                Some(offset) => offset,
+                None => break,
+            };
+        }
     }
 
-    let txids = txids.into_iter().collect::<Vec<_>>();
+    // Transactions are immutable, so we need to download only those which we haven't seen yet;
+    // status changes of the known ones are reported via `TxidBatch`.
+    let txids = txids
+        .into_iter()
+        .filter(|txid| !known_txids.contains(txid))
+        .collect::<Vec<_>>();
     for (no, chunk) in txids.chunks(20).enumerate() {
         let tx_list = client.batch_transaction_get(chunk)?;
         let progress = (no + 1) as f32 / txids.len() as f32 / 20.0;
@@ -286,6 +394,20 @@ fn electrum_sync(
             .expect("electrum watcher channel is broken");
     }
 
+    // Block headers are immutable as well (up to reorgs, which do not change the timestamps
+    // significantly), so we request only those which are not in the local index yet.
+    let heights = heights
+        .into_iter()
+        .filter(|height| !known_heights.contains(height))
+        .collect::<Vec<_>>();
+    for chunk in heights.chunks(BATCH_SIZE as usize) {
+        let headers = client.batch_block_header(chunk.iter().copied())?;
+        known_heights.extend(chunk);
+        sender
+            .send(Msg::Headers(chunk.iter().copied().zip(headers).collect()))
+            .expect("electrum watcher channel is broken");
+    }
+
     sender
         .send(Msg::Complete)
         .expect("electrum watcher channel is broken");
@@ -14,7 +14,7 @@ use std::thread::JoinHandle;
 use std::time::Duration;
 use std::{io, thread};
 
-use relm::Sender;
+use crate::worker::MsgSender;
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
 pub enum Exchange {
@@ -74,7 +74,7 @@ pub struct ExchangeWorker {
 
 impl ExchangeWorker {
     pub fn with(
-        sender: Sender<Msg>,
+        sender: impl MsgSender<Msg>,
         mut exchange: Exchange,
         mut fiat: Fiat,
         interval: u64,
@@ -132,7 +132,11 @@ impl ExchangeWorker {
     fn cmd(&self, cmd: Cmd) { self.tx.send(cmd).expect("Exchange thread is dead") }
 }
 
-fn exchange_refresh(exchange: Exchange, fiat: Fiat, sender: &Sender<Msg>) -> Result<(), String> {
+fn exchange_refresh(
+    exchange: Exchange,
+    fiat: Fiat,
+    sender: &impl MsgSender<Msg>,
+) -> Result<(), String> {
     let url = format!("{}{}", exchange, fiat);
     let data: serde_json::Value = ureq::get(&url)
         .call()
@@ -9,8 +9,29 @@
 // a copy of the AGPL-3.0 License along with this software. If not, see
 // <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
 
+//! Background workers talking to the network. Workers report to their owner through a
+//! [`MsgSender`], which is a GTK main loop channel in the desktop app and a plain
+//! [`std::sync::mpsc`] channel everywhere else.
+
 pub mod electrum;
 pub mod exchange;
 
+use std::sync::mpsc;
+
 pub use electrum::ElectrumWorker;
 pub use exchange::ExchangeWorker;
+
+/// Channel delivering worker messages to the worker owner.
+pub trait MsgSender<Msg>: Send + 'static {
+    /// Errors if the receiving side of the channel is gone.
+    fn send(&self, msg: Msg) -> Result<(), mpsc::SendError<Msg>>;
+}
+
+impl<Msg: Send + 'static> MsgSender<Msg> for mpsc::Sender<Msg> {
+    fn send(&self, msg: Msg) -> Result<(), mpsc::SendError<Msg>> { mpsc::Sender::send(self, msg) }
+}
+
+#[cfg(feature = "ui")]
+impl<Msg: Send + 'static> MsgSender<Msg> for relm::Sender<Msg> {
+    fn send(&self, msg: Msg) -> Result<(), mpsc::SendError<Msg>> { relm::Sender::send(self, msg) }
+}
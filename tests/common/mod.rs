@@ -0,0 +1,41 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime SA, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! Wallet data shared by the integration tests.
+
+use std::str::FromStr;
+
+use bitcoin::util::bip32::ExtendedPubKey;
+use mycitadel::model::{
+    DescriptorClass, ElectrumPreset, ElectrumServer, PublicNetwork, Signer, SpendingCondition,
+    WalletSettings,
+};
+use wallet::hd::{Bip43, TerminalStep};
+
+/// BIP-32 test vector 1 master key.
+pub const XPUB: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+
+/// Single-signature mainnet segwit wallet with the [`XPUB`] signer. This is the wallet stored in
+/// the file format fixtures.
+pub fn settings() -> WalletSettings {
+    let network = PublicNetwork::Mainnet;
+    let xpub = ExtendedPubKey::from_str(XPUB).unwrap();
+    let signer = Signer::with_xpub(xpub, &Bip43::singlesig_segwit0(), network.clone());
+    WalletSettings::with(
+        [signer],
+        [(0, SpendingCondition::default())],
+        [DescriptorClass::SegwitV0],
+        vec![TerminalStep::range(0u8, 1u8), TerminalStep::Wildcard],
+        network.clone(),
+        ElectrumServer::tls(ElectrumPreset::Blockstream, &network),
+    )
+    .unwrap()
+}
@@ -0,0 +1,361 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime SA, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! Wallet file format fixtures. Files in `tests/fixtures` were written by the application before
+//! the format version was put into the file header (see `mycitadel::model::migrations`) and must
+//! open with the current code:
+//! - `v0-settings.mcw`: wallet settings only;
+//! - `v1-empty.mcw`: wallet which was never synced;
+//! - `v1-history.mcw`: synced wallet with two transactions and two unspent outputs.
+
+#[macro_use]
+extern crate amplify;
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use amplify::Wrapper;
+use bitcoin::{Address, BlockHash, BlockHeader, Transaction, TxIn, TxOut, Txid};
+use chrono::Utc;
+use common::settings;
+use electrum_client::HeaderNotification;
+use mycitadel::model::compose::Error as ComposeError;
+use mycitadel::model::{
+    file, AddressSource, DescriptorClass, FileDocument, HeaderIndex, OnchainStatus, OnchainTxid,
+    Payment, PaymentRequest, TipError, UtxoTxid, Wallet, DEFAULT_GAP_LIMIT,
+};
+use mycitadel::worker::electrum::TxidMeta;
+use strict_encoding::StrictEncode;
+use wallet::hd::{SegmentIndexes, UnhardenedIndex};
+
+/// First 4 bytes of SHA256("mycitadel:wallet:v1"), used by the unversioned formats.
+const LEGACY_MAGIC: [u8; 4] = [0xa4, 0x54, 0x6a, 0x8e];
+/// First 4 bytes of SHA256("mycitadel:wallet").
+const MAGIC: [u8; 4] = [0xcb, 0x44, 0x57, 0xb5];
+
+const FUNDING_TXID: &str = "a4f938e2a33974fc1f374588783c0627eafce7bdcac6c0af9dd1052aeca8da11";
+const SPENDING_TXID: &str = "e9c4a9d53811f5ce79554bda3e55a0f60d9f4a7b354df80e9c0eaa77db0a6211";
+
+fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn fixture(name: &str, magic: [u8; 4], version: Option<u16>, data: impl StrictEncode) -> PathBuf {
+    let mut doc = magic.to_vec();
+    if let Some(version) = version {
+        doc.extend(version.to_le_bytes());
+    }
+    data.strict_encode(&mut doc).unwrap();
+    let path = std::env::temp_dir().join(format!("mycitadel-{}-{}.mcw", name, std::process::id()));
+    fs::write(&path, doc).unwrap();
+    path
+}
+
+fn index(no: u32) -> UnhardenedIndex { UnhardenedIndex::from_index(no).unwrap() }
+
+#[test]
+fn v0_settings_only() {
+    let path = fixture_path("v0-settings.mcw");
+    assert_eq!(fs::read(&path).unwrap()[..4], LEGACY_MAGIC);
+    let wallet = Wallet::read_file(&path).unwrap();
+
+    assert_eq!(wallet.as_settings(), &settings());
+    assert_eq!(wallet.as_settings().gap_limit(), DEFAULT_GAP_LIMIT);
+    assert_eq!(wallet.tx_count(), 0);
+    assert_eq!(wallet.height(), 0);
+}
+
+#[test]
+fn v1_empty() {
+    let path = fixture_path("v1-empty.mcw");
+    assert_eq!(fs::read(&path).unwrap()[..4], LEGACY_MAGIC);
+    let wallet = Wallet::read_file(&path).unwrap();
+
+    assert_eq!(wallet.as_settings(), &settings());
+    assert_eq!(wallet.as_settings().gap_limit(), DEFAULT_GAP_LIMIT);
+    assert_eq!(wallet.tx_count(), 0);
+    assert!(wallet.utxos().is_empty());
+    assert_eq!(wallet.next_default_index(), UnhardenedIndex::zero());
+}
+
+#[test]
+fn v1_with_history() {
+    let path = fixture_path("v1-history.mcw");
+    assert_eq!(fs::read(&path).unwrap()[..4], LEGACY_MAGIC);
+    let wallet = Wallet::read_file(&path).unwrap();
+    let funding_txid = Txid::from_str(FUNDING_TXID).unwrap();
+    let spending_txid = Txid::from_str(SPENDING_TXID).unwrap();
+
+    assert_eq!(wallet.as_settings(), &settings());
+    assert_eq!(wallet.height(), 740_010);
+    assert_eq!(
+        wallet.last_block(),
+        BlockHash::from_str("00000000000000000004ac4e0fdb1c59c0b3c5ea48a2f3c48b7e51fd71c5dd6c")
+            .unwrap()
+    );
+    assert_eq!(wallet.state().balance, 59_000);
+    assert_eq!(wallet.state().volume, 100_000);
+    assert_eq!(wallet.ephemerals().fees, (20.0, 10.0, 5.0));
+    assert_eq!(wallet.next_default_index(), index(2));
+    assert_eq!(wallet.next_change_index(), index(1));
+
+    let utxos = wallet
+        .utxos()
+        .iter()
+        .map(|utxo| {
+            assert_eq!(utxo.addr_src.class, DescriptorClass::SegwitV0);
+            (utxo.onchain.txid, utxo.vout, utxo.value, utxo.addr_src.change, utxo.addr_src.index)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(utxos, vec![
+        (funding_txid, 2, 20_000, false, index(1)),
+        (spending_txid, 1, 39_000, true, index(0)),
+    ]);
+
+    assert_eq!(wallet.tx_count(), 2);
+    let funding = wallet
+        .history()
+        .iter()
+        .find(|entry| entry.onchain.txid == funding_txid)
+        .unwrap();
+    assert_eq!(funding.onchain.status, OnchainStatus::Blockchain(739_999));
+    assert_eq!(
+        funding.onchain.date_time.map(|date_time| date_time.timestamp()),
+        Some(1_654_000_000)
+    );
+    assert_eq!(funding.fee, Some(1_000));
+    assert!(funding.credit.is_empty());
+    assert_eq!(funding.debit.keys().copied().collect::<Vec<_>>(), vec![0, 2]);
+    assert!(funding
+        .debit
+        .values()
+        .all(|addr_src| addr_src.class == DescriptorClass::SegwitV0 && !addr_src.change));
+    assert_eq!(funding.payers[&0], (Some("Employer".to_owned()), None));
+    assert_eq!(funding.comment, None);
+
+    let spending = wallet
+        .history()
+        .iter()
+        .find(|entry| entry.onchain.txid == spending_txid)
+        .unwrap();
+    assert_eq!(spending.onchain.status, OnchainStatus::Blockchain(740_001));
+    assert_eq!(spending.onchain.date_time, None);
+    assert_eq!(spending.tx.lock_time, 740_000);
+    assert_eq!(spending.credit[&0].value, 100_000);
+    assert_eq!(spending.credit[&0].addr_src.class, DescriptorClass::SegwitV0);
+    assert_eq!(spending.credit[&0].addr_src.index, index(0));
+    assert!(spending.debit[&1].change);
+    assert_eq!(spending.debit[&1].class, DescriptorClass::SegwitV0);
+    assert_eq!(spending.beneficiaries[&0], "Landlord");
+    assert_eq!(spending.comment.as_deref(), Some("Rent"));
+
+    // Migrated wallet is saved in the current format without losing data
+    let resaved = std::env::temp_dir().join(format!("mycitadel-v1-{}.mcw", std::process::id()));
+    wallet.write_file(&resaved).unwrap();
+    let data = fs::read(&resaved).unwrap();
+    let reopened = Wallet::read_file(&resaved).unwrap();
+    fs::remove_file(resaved).unwrap();
+
+    assert_eq!(data[..4], MAGIC);
+    assert_eq!(reopened.utxos(), wallet.utxos());
+    assert_eq!(reopened.history(), wallet.history());
+}
+
+#[test]
+fn current_roundtrip() {
+    let path = std::env::temp_dir().join(format!("mycitadel-v2-{}.mcw", std::process::id()));
+    Wallet::from(settings()).write_file(&path).unwrap();
+    let data = fs::read(&path).unwrap();
+    let wallet = Wallet::read_file(&path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(data[..4], MAGIC);
+    assert_eq!(data[4..6], Wallet::DOC_VERSION.to_le_bytes());
+    assert_eq!(wallet.as_settings(), &settings());
+}
+
+#[test]
+fn future_version() {
+    let path = fixture(
+        "future",
+        MAGIC,
+        Some(Wallet::DOC_VERSION + 1),
+        Wallet::from(settings()),
+    );
+    let result = Wallet::read_file(&path);
+    fs::remove_file(path).unwrap();
+
+    assert!(matches!(result, Err(file::Error::FutureVersion { .. })));
+}
+
+#[test]
+fn encrypted_roundtrip() {
+    let path = std::env::temp_dir().join(format!("mycitadel-enc-{}.mcw", std::process::id()));
+    Wallet::from(settings())
+        .write_file_with(&path, Some("passphrase"))
+        .unwrap();
+
+    assert!(Wallet::is_encrypted(&path).unwrap());
+    assert!(matches!(
+        Wallet::read_file(&path),
+        Err(file::Error::PassphraseRequired)
+    ));
+    assert!(matches!(
+        Wallet::read_file_with(&path, Some("wrong")),
+        Err(file::Error::Decryption)
+    ));
+    let wallet = Wallet::read_file_with(&path, Some("passphrase")).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(wallet.as_settings(), &settings());
+}
+
+#[test]
+fn backup_recovery() {
+    let dir = std::env::temp_dir().join(format!("mycitadel-backup-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("wallet.mcw");
+
+    let wallet = Wallet::from(settings());
+    for _ in 0..=file::BACKUP_COUNT {
+        wallet.write_file(&path).unwrap();
+    }
+    assert_eq!(Wallet::backups(&path).len(), file::BACKUP_COUNT);
+
+    fs::write(&path, b"garbage").unwrap();
+    assert!(Wallet::read_file(&path).is_err());
+    let restored = Wallet::restore_backup(&path, None).unwrap();
+    assert_eq!(restored.as_settings(), &settings());
+    assert!(Wallet::read_file(&path).is_ok());
+    assert_eq!(
+        fs::read(dir.join("wallet.mcw.damaged")).unwrap(),
+        b"garbage"
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rekey_drops_backups() {
+    let dir = std::env::temp_dir().join(format!("mycitadel-rekey-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("wallet.mcw");
+
+    let wallet = Wallet::from(settings());
+    for _ in 0..3 {
+        wallet.write_file(&path).unwrap();
+    }
+    assert_eq!(Wallet::backups(&path).len(), 2);
+
+    wallet.rekey_file(&path, Some("passphrase")).unwrap();
+    assert!(Wallet::is_encrypted(&path).unwrap());
+    assert!(Wallet::backups(&path).is_empty());
+
+    wallet.write_file_with(&path, Some("passphrase")).unwrap();
+    assert_eq!(Wallet::backups(&path).len(), 1);
+    assert!(Wallet::is_encrypted(&Wallet::backups(&path)[0]).unwrap());
+
+    wallet.rekey_file(&path, None).unwrap();
+    assert!(!Wallet::is_encrypted(&path).unwrap());
+    assert!(Wallet::backups(&path).is_empty());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn encrypted_wallet_composes() {
+    let dir = std::env::temp_dir().join(format!("mycitadel-compose-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("wallet.mcw");
+    let passphrase = Some("passphrase");
+
+    let settings = settings();
+    let class = DescriptorClass::SegwitV0;
+    let index = UnhardenedIndex::zero();
+    let script = settings.script_pubkeys(class, false, 0..=0).unwrap()[&index].clone();
+    let addr_src = AddressSource::with(&script, index, false, class, bitcoin::Network::Bitcoin);
+    let funding = Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn::default()],
+        output: vec![TxOut {
+            value: 100_000,
+            script_pubkey: script.into_inner(),
+        }],
+    };
+    let onchain = OnchainTxid {
+        txid: funding.txid(),
+        status: OnchainStatus::Blockchain(740_000),
+        date_time: None,
+    };
+    let mut wallet = Wallet::from(settings);
+    wallet.update_utxos(bset![UtxoTxid {
+        onchain,
+        value: 100_000,
+        vout: 0,
+        addr_src,
+    }]);
+    wallet.update_complete(
+        &bmap! { addr_src => bset![TxidMeta { onchain, fee: None }] },
+        &[funding],
+    );
+    let header = BlockHeader {
+        version: 1,
+        prev_blockhash: zero!(),
+        merkle_root: zero!(),
+        time: Utc::now().timestamp() as u32,
+        bits: 0,
+        nonce: 0,
+    };
+    wallet.update_last_block(&HeaderNotification {
+        height: 740_010,
+        header,
+    });
+    let mut headers = HeaderIndex::default();
+    headers.insert(740_010, &header);
+    wallet.write_file_with(&path, passphrase).unwrap();
+    headers.write_for(&path, passphrase).unwrap();
+
+    // Block index discloses when the wallet was used, so it is encrypted as well
+    assert!(matches!(
+        HeaderIndex::read_for(&path, None),
+        Err(file::Error::PassphraseRequired)
+    ));
+    assert!(!fs::read(HeaderIndex::index_path(&path))
+        .unwrap()
+        .windows(4)
+        .any(|window| window == header.time.to_le_bytes()));
+
+    let wallet = Wallet::read_file_with(&path, passphrase).unwrap();
+    let headers = HeaderIndex::read_for(&path, passphrase).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+
+    let external = Address::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").unwrap();
+    let request = PaymentRequest::with(
+        vec![Payment {
+            script_pubkey: external.script_pubkey(),
+            amount: 50_000,
+            subtract_fee: false,
+        }],
+        5.0,
+    );
+    assert!(matches!(
+        wallet.compose_payment(&request, &HeaderIndex::default()),
+        Err(ComposeError::LockTime(TipError::Unknown))
+    ));
+    let composition = wallet.compose_payment(&request, &headers).unwrap();
+    assert_eq!(composition.psbt.inputs.len(), 1);
+}
@@ -0,0 +1,164 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime SA, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! JSON serialization of the wallet data is a public interface of `mc-tx`, so its field names
+//! must stay stable.
+
+#![cfg(feature = "serde")]
+
+#[macro_use]
+extern crate amplify;
+
+mod common;
+
+use std::str::FromStr;
+
+use bitcoin::{Network, OutPoint, Transaction, Txid};
+use common::settings;
+use mycitadel::model::{
+    AddressSource, DescriptorClass, HistoryEntry, OnchainStatus, OnchainTxid, UtxoTxid, Wallet,
+    WalletSettings,
+};
+use serde_json::Value;
+use wallet::hd::{SegmentIndexes, UnhardenedIndex};
+
+fn onchain() -> OnchainTxid {
+    OnchainTxid {
+        txid: Txid::from_str(&"11".repeat(32)).unwrap(),
+        status: OnchainStatus::Blockchain(740_000),
+        date_time: None,
+    }
+}
+
+fn utxo(settings: &WalletSettings) -> UtxoTxid {
+    let index = UnhardenedIndex::zero();
+    let script_pubkey = settings
+        .script_pubkeys(DescriptorClass::SegwitV0, false, 0..=0)
+        .unwrap()
+        .remove(&index)
+        .unwrap();
+    UtxoTxid {
+        onchain: onchain(),
+        value: 100_000,
+        vout: 1,
+        addr_src: AddressSource::with(
+            &script_pubkey,
+            index,
+            false,
+            DescriptorClass::SegwitV0,
+            Network::Bitcoin,
+        ),
+    }
+}
+
+fn keys(value: &Value) -> Vec<&str> {
+    let mut keys = value
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys
+}
+
+#[test]
+fn wallet_schema() {
+    let settings = settings();
+    let mut wallet = Wallet::from(settings.clone());
+    wallet.update_utxos(bset![utxo(&settings)]);
+    wallet.update_next_change_index(UnhardenedIndex::from_index(3u32).unwrap());
+    wallet.set_utxo_label(OutPoint::new(onchain().txid, 1), "deposit");
+
+    let value = serde_json::to_value(&wallet).unwrap();
+    assert_eq!(keys(&value), [
+        "address_labels",
+        "ephemerals",
+        "frozen",
+        "height",
+        "history",
+        "last_block",
+        "last_indexes",
+        "settings",
+        "state",
+        "utxo_labels",
+        "utxos",
+        "wip",
+    ]);
+    assert_eq!(keys(&value["settings"]), [
+        "core",
+        "electrum",
+        "gap_limit",
+        "network",
+        "signers"
+    ]);
+    assert_eq!(keys(&value["state"]), ["balance", "volume"]);
+
+    let json = serde_json::to_string(&wallet).unwrap();
+    let restored: Wallet = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.as_settings(), &settings);
+    assert_eq!(restored.utxos(), wallet.utxos());
+}
+
+#[test]
+fn utxo_schema() {
+    let utxo = utxo(&settings());
+    let value = serde_json::to_value(&utxo).unwrap();
+    assert_eq!(keys(&value), ["addr_src", "onchain", "value", "vout"]);
+    assert_eq!(keys(&value["onchain"]), ["date_time", "status", "txid"]);
+    assert_eq!(keys(&value["addr_src"]), [
+        "address", "change", "class", "index"
+    ]);
+    assert_eq!(
+        value["onchain"]["status"],
+        serde_json::json!({ "blockchain": 740_000 })
+    );
+    assert_eq!(
+        value["addr_src"]["address"],
+        utxo.addr_src.address.to_string()
+    );
+
+    let restored: UtxoTxid = serde_json::from_value(value).unwrap();
+    assert_eq!(restored, utxo);
+}
+
+#[test]
+fn history_schema() {
+    let entry = HistoryEntry {
+        onchain: onchain(),
+        tx: Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        },
+        credit: empty!(),
+        debit: empty!(),
+        payers: empty!(),
+        beneficiaries: empty!(),
+        fee: Some(141),
+        comment: Some(s!("rent")),
+    };
+    let value = serde_json::to_value(&entry).unwrap();
+    assert_eq!(keys(&value), [
+        "beneficiaries",
+        "comment",
+        "credit",
+        "debit",
+        "fee",
+        "onchain",
+        "payers",
+        "tx"
+    ]);
+
+    let restored: HistoryEntry = serde_json::from_value(value).unwrap();
+    assert_eq!(restored, entry);
+}